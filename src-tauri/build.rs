@@ -2,9 +2,22 @@ fn main() {
     const APP_COMMANDS: &[&str] = &[
         "connect_to_server",
         "disconnect_from_server",
+        "cancel_connection",
+        "list_sessions",
+        "open_session",
+        "close_session",
+        "set_active_session",
         "get_connection_status",
+        "get_connection_metrics",
+        "refresh_public_servers",
+        "diagnose_server",
         "send_chat_message",
         "change_room",
+        "create_managed_room",
+        "get_room_directory",
+        "list_operator_passwords",
+        "forget_operator_password",
+        "export_operator_passwords",
         "set_ready",
         "update_playlist",
         "get_config",
@@ -13,6 +26,8 @@ fn main() {
         "detect_available_players",
         "get_cached_players",
         "refresh_player_detection",
+        "start_local_server",
+        "stop_local_server",
     ];
 
     let manifest = tauri_build::AppManifest::new().commands(APP_COMMANDS);
@@ -3,21 +3,26 @@ use parking_lot::Mutex;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Instant;
-use tauri::{AppHandle, Emitter};
 use tempfile::TempDir;
 
 use crate::client::{
-    chat::ChatManager, local_state::LocalPlaybackState, playlist::Playlist, state::ClientState,
-    sync::SyncEngine,
+    chat::ChatManager, events::ClientEventSink, host::ClientHost, local_state::LocalPlaybackState,
+    playlist::Playlist, sessions::SessionRegistry, sessions::DEFAULT_SESSION_ID,
+    state::ClientState, sync::SyncEngine,
 };
-use crate::config::{SyncplayConfig, UnpauseAction};
+use crate::config::{SyncplayConfig, TlsPolicy, UnpauseAction};
 use crate::network::connection::Connection;
 use crate::network::messages::HelloMessage;
 use crate::network::ping::PingService;
 use crate::player::backend::{PlayerBackend, PlayerKind};
+use crate::server::SyncplayServer;
 
 /// Global application state
 pub struct AppState {
+    /// Id of the session this state drives
+    pub session_id: String,
+    /// Sessions opened from the same client, shared by all of their states
+    pub sessions: Arc<SessionRegistry>,
     /// Network connection to Syncplay server
     pub connection: Arc<Mutex<Option<Arc<Connection>>>>,
     /// Player backend instance
@@ -42,8 +47,12 @@ pub struct AppState {
     pub last_hello: Arc<Mutex<Option<HelloMessage>>>,
     /// Whether hello has been sent for the current connection
     pub hello_sent: Arc<Mutex<bool>>,
-    /// Tauri app handle for event emission
-    pub app_handle: Arc<Mutex<Option<AppHandle>>>,
+    /// TLS policy of the current connection
+    pub tls_policy: Arc<Mutex<TlsPolicy>>,
+    /// Receiver for client events
+    pub event_sink: Arc<Mutex<Option<Arc<dyn ClientEventSink>>>>,
+    /// Embedder services (config persistence, resources)
+    pub host: Arc<Mutex<Option<Arc<dyn ClientHost>>>>,
     /// Autoplay countdown state
     pub autoplay: Arc<Mutex<AutoPlayState>>,
     /// Ping RTT tracking
@@ -78,6 +87,10 @@ pub struct AppState {
     pub room_warning_state: Arc<Mutex<RoomWarningState>>,
     /// Whether the room warning task is running
     pub room_warning_task_running: Arc<Mutex<bool>>,
+    /// Reconnect supervisor state
+    pub reconnect: Arc<Mutex<ReconnectState>>,
+    /// Embedded server started from the UI
+    pub local_server: Arc<Mutex<Option<SyncplayServer>>>,
 }
 
 #[derive(Debug, Default)]
@@ -89,6 +102,8 @@ pub struct IgnoringOnTheFlyState {
 impl AppState {
     pub fn new() -> Arc<Self> {
         Arc::new(Self {
+            session_id: DEFAULT_SESSION_ID.to_string(),
+            sessions: Arc::new(SessionRegistry::new()),
             connection: Arc::new(Mutex::new(None)),
             player: Arc::new(Mutex::new(None)),
             player_process: Arc::new(Mutex::new(None)),
@@ -101,7 +116,9 @@ impl AppState {
             suppress_unpause_check: Arc::new(Mutex::new(false)),
             last_hello: Arc::new(Mutex::new(None)),
             hello_sent: Arc::new(Mutex::new(false)),
-            app_handle: Arc::new(Mutex::new(None)),
+            tls_policy: Arc::new(Mutex::new(TlsPolicy::default())),
+            event_sink: Arc::new(Mutex::new(None)),
+            host: Arc::new(Mutex::new(None)),
             autoplay: Arc::new(Mutex::new(AutoPlayState::default())),
             ping_service: Arc::new(Mutex::new(PingService::default())),
             last_latency_calculation: Arc::new(Mutex::new(None)),
@@ -119,20 +136,65 @@ impl AppState {
             last_control_password_attempt: Arc::new(Mutex::new(None)),
             room_warning_state: Arc::new(Mutex::new(RoomWarningState::default())),
             room_warning_task_running: Arc::new(Mutex::new(false)),
+            reconnect: Arc::new(Mutex::new(ReconnectState::default())),
+            local_server: Arc::new(Mutex::new(None)),
         })
     }
 
-    /// Set the Tauri app handle for event emission
-    pub fn set_app_handle(&self, handle: AppHandle) {
-        *self.app_handle.lock() = Some(handle);
+    /// State for another session sharing this one's config, events and registry
+    pub fn for_session(&self, session_id: String) -> Arc<Self> {
+        Arc::new(Self {
+            session_id,
+            sessions: self.sessions.clone(),
+            config: self.config.clone(),
+            event_sink: self.event_sink.clone(),
+            host: self.host.clone(),
+            detected_players: self.detected_players.clone(),
+            detected_players_updated_at: self.detected_players_updated_at.clone(),
+            local_server: self.local_server.clone(),
+            controlled_room_passwords: self.controlled_room_passwords.clone(),
+            ..Self::default()
+        })
+    }
+
+    /// Session that currently owns the player
+    pub fn playback_session(self: &Arc<Self>) -> Arc<Self> {
+        if self.is_playback_session() {
+            return self.clone();
+        }
+        self.sessions
+            .get(&self.sessions.active_id())
+            .unwrap_or_else(|| self.clone())
     }
 
-    /// Emit an event to the frontend
+    /// Whether this session drives playback rather than only following chat
+    pub fn is_playback_session(&self) -> bool {
+        self.sessions.is_active(&self.session_id)
+    }
+
+    /// Set the receiver for client events
+    pub fn set_event_sink(&self, sink: Arc<dyn ClientEventSink>) {
+        *self.event_sink.lock() = Some(sink);
+    }
+
+    /// Set the embedder services
+    pub fn set_host(&self, host: Arc<dyn ClientHost>) {
+        *self.host.lock() = Some(host);
+    }
+
+    /// Emit an event to the frontend, tagged with the session it belongs to
     pub fn emit_event(&self, event: &str, payload: impl serde::Serialize + Clone) {
-        if let Some(handle) = self.app_handle.lock().as_ref() {
-            if let Err(e) = handle.emit(event, payload) {
-                tracing::error!("Failed to emit event {}: {}", event, e);
+        let Some(sink) = self.event_sink.lock().clone() else {
+            return;
+        };
+        match serde_json::to_value(payload) {
+            Ok(mut payload) => {
+                if let serde_json::Value::Object(fields) = &mut payload {
+                    fields.insert("sessionId".to_string(), self.session_id.clone().into());
+                }
+                sink.emit(event, payload)
             }
+            Err(e) => tracing::error!("Failed to serialize event {}: {}", event, e),
         }
     }
 
@@ -154,6 +216,8 @@ impl AppState {
 impl Default for AppState {
     fn default() -> Self {
         Self {
+            session_id: DEFAULT_SESSION_ID.to_string(),
+            sessions: Arc::new(SessionRegistry::new()),
             connection: Arc::new(Mutex::new(None)),
             player: Arc::new(Mutex::new(None)),
             player_process: Arc::new(Mutex::new(None)),
@@ -166,7 +230,9 @@ impl Default for AppState {
             suppress_unpause_check: Arc::new(Mutex::new(false)),
             last_hello: Arc::new(Mutex::new(None)),
             hello_sent: Arc::new(Mutex::new(false)),
-            app_handle: Arc::new(Mutex::new(None)),
+            tls_policy: Arc::new(Mutex::new(TlsPolicy::default())),
+            event_sink: Arc::new(Mutex::new(None)),
+            host: Arc::new(Mutex::new(None)),
             autoplay: Arc::new(Mutex::new(AutoPlayState::default())),
             ping_service: Arc::new(Mutex::new(PingService::default())),
             last_latency_calculation: Arc::new(Mutex::new(None)),
@@ -184,6 +250,8 @@ impl Default for AppState {
             last_control_password_attempt: Arc::new(Mutex::new(None)),
             room_warning_state: Arc::new(Mutex::new(RoomWarningState::default())),
             room_warning_task_running: Arc::new(Mutex::new(false)),
+            reconnect: Arc::new(Mutex::new(ReconnectState::default())),
+            local_server: Arc::new(Mutex::new(None)),
         }
     }
 }
@@ -205,6 +273,25 @@ pub struct RoomWarningState {
     pub not_ready: Option<String>,
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct ReconnectState {
+    /// Bumped on every reconnect start or cancellation so stale loops exit
+    pub generation: u64,
+    pub active: bool,
+    /// Session to restore once the server has answered our List request
+    pub restore: Option<SessionSnapshot>,
+}
+
+/// Session state captured when the connection drops unexpectedly
+#[derive(Debug, Clone)]
+pub struct SessionSnapshot {
+    pub room: String,
+    pub playlist: Vec<String>,
+    pub playlist_index: Option<usize>,
+    pub position: Option<f64>,
+    pub paused: bool,
+}
+
 impl Default for AutoPlayState {
     fn default() -> Self {
         Self {
@@ -224,6 +311,7 @@ impl Default for AutoPlayState {
 pub struct ConnectionStatusEvent {
     pub connected: bool,
     pub server: Option<String>,
+    pub reconnecting: bool,
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -232,12 +320,21 @@ pub struct UserListEvent {
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct UserInfo {
     pub username: String,
     pub room: String,
     pub file: Option<String>,
     pub is_ready: bool,
     pub is_controller: bool,
+    pub features: Option<serde_json::Value>,
+    pub version: Option<String>,
+    /// RFC 3339 timestamps
+    pub joined_at: Option<String>,
+    pub last_activity: Option<String>,
+    /// Seconds ahead of the local player, negative when behind.
+    /// Only known for the viewer the server reports as slowest.
+    pub sync_offset: Option<f64>,
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -0,0 +1,195 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::network::messages::ClientFeatures;
+
+/// Chat length used before the server tells us its limit
+pub const DEFAULT_MAX_CHAT_MESSAGE_LENGTH: usize = 150;
+
+// Fallbacks for servers that predate the feature list
+const FALLBACK_MAX_CHAT_MESSAGE_LENGTH: usize = 50;
+const FALLBACK_MAX_USERNAME_LENGTH: usize = 16;
+const FALLBACK_MAX_ROOM_NAME_LENGTH: usize = 35;
+const FALLBACK_MAX_FILENAME_LENGTH: usize = 250;
+
+// First server versions shipping each feature
+const FEATURE_LIST_MIN_VERSION: (u32, u32, u32) = (1, 5, 0);
+const SHARED_PLAYLIST_MIN_VERSION: (u32, u32, u32) = (1, 4, 0);
+const CHAT_MIN_VERSION: (u32, u32, u32) = (1, 5, 0);
+const READINESS_MIN_VERSION: (u32, u32, u32) = (1, 3, 0);
+const MANAGED_ROOMS_MIN_VERSION: (u32, u32, u32) = (1, 3, 0);
+
+/// Features the connected server supports, from its Hello
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerCapabilities {
+    pub chat: bool,
+    pub shared_playlists: bool,
+    pub readiness: bool,
+    pub managed_rooms: bool,
+    pub persistent_rooms: bool,
+    pub isolate_rooms: bool,
+    pub max_chat_message_length: usize,
+    pub max_username_length: Option<usize>,
+    pub max_room_name_length: Option<usize>,
+    pub max_filename_length: Option<usize>,
+}
+
+impl Default for ServerCapabilities {
+    /// Assume everything until the server Hello says otherwise
+    fn default() -> Self {
+        Self {
+            chat: true,
+            shared_playlists: true,
+            readiness: true,
+            managed_rooms: true,
+            persistent_rooms: false,
+            isolate_rooms: false,
+            max_chat_message_length: DEFAULT_MAX_CHAT_MESSAGE_LENGTH,
+            max_username_length: None,
+            max_room_name_length: None,
+            max_filename_length: None,
+        }
+    }
+}
+
+impl ServerCapabilities {
+    /// Capabilities advertised in a server Hello, falling back to version checks
+    pub fn from_hello(realversion: &str, features: Option<&ClientFeatures>) -> Self {
+        let version = parse_version(realversion);
+        let meets = |min: (u32, u32, u32)| version.map(|v| v >= min).unwrap_or(true);
+        let has_feature_list = meets(FEATURE_LIST_MIN_VERSION);
+        let mut capabilities = Self {
+            chat: meets(CHAT_MIN_VERSION),
+            shared_playlists: meets(SHARED_PLAYLIST_MIN_VERSION),
+            readiness: meets(READINESS_MIN_VERSION),
+            managed_rooms: meets(MANAGED_ROOMS_MIN_VERSION),
+            persistent_rooms: false,
+            isolate_rooms: false,
+            max_chat_message_length: if has_feature_list {
+                DEFAULT_MAX_CHAT_MESSAGE_LENGTH
+            } else {
+                FALLBACK_MAX_CHAT_MESSAGE_LENGTH
+            },
+            max_username_length: Some(FALLBACK_MAX_USERNAME_LENGTH),
+            max_room_name_length: Some(FALLBACK_MAX_ROOM_NAME_LENGTH),
+            max_filename_length: Some(FALLBACK_MAX_FILENAME_LENGTH),
+        };
+
+        let Some(features) = features else {
+            return capabilities;
+        };
+        let flag = |key: &str| features.extra.get(key).and_then(Value::as_bool);
+        let limit = |key: &str| {
+            features
+                .extra
+                .get(key)
+                .and_then(Value::as_u64)
+                .filter(|value| *value > 0)
+                .map(|value| value as usize)
+        };
+
+        if let Some(chat) = features.chat {
+            capabilities.chat = chat;
+        }
+        if let Some(shared_playlists) = features.shared_playlists {
+            capabilities.shared_playlists = shared_playlists;
+        }
+        if let Some(readiness) = flag("readiness").or(features.ready_state) {
+            capabilities.readiness = readiness;
+        }
+        if let Some(managed_rooms) = features.managed_rooms {
+            capabilities.managed_rooms = managed_rooms;
+        }
+        if let Some(persistent_rooms) = features.persistent_rooms {
+            capabilities.persistent_rooms = persistent_rooms;
+        }
+        if let Some(isolate_rooms) = flag("isolateRooms") {
+            capabilities.isolate_rooms = isolate_rooms;
+        }
+        if let Some(length) = limit("maxChatMessageLength") {
+            capabilities.max_chat_message_length = length;
+        }
+        if let Some(length) = limit("maxUsernameLength") {
+            capabilities.max_username_length = Some(length);
+        }
+        if let Some(length) = limit("maxRoomNameLength") {
+            capabilities.max_room_name_length = Some(length);
+        }
+        if let Some(length) = limit("maxFilenameLength") {
+            capabilities.max_filename_length = Some(length);
+        }
+        capabilities
+    }
+}
+
+/// Parse `major.minor.patch`, ignoring any suffix such as `-beta`
+fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.trim().split('.').map(|part| {
+        part.chars()
+            .take_while(char::is_ascii_digit)
+            .collect::<String>()
+            .parse::<u32>()
+            .ok()
+    });
+    let major = parts.next()??;
+    let minor = parts.next().flatten().unwrap_or(0);
+    let patch = parts.next().flatten().unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn features(extra: &[(&str, Value)]) -> ClientFeatures {
+        ClientFeatures {
+            shared_playlists: None,
+            chat: Some(false),
+            ready_state: None,
+            managed_rooms: Some(true),
+            persistent_rooms: None,
+            extra: extra
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect::<HashMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn test_capabilities_from_feature_list() {
+        let features = features(&[
+            ("readiness", Value::from(false)),
+            ("isolateRooms", Value::from(true)),
+            ("maxChatMessageLength", Value::from(300)),
+            ("maxUsernameLength", Value::from(20)),
+        ]);
+        let capabilities = ServerCapabilities::from_hello("1.7.3", Some(&features));
+        assert!(!capabilities.chat);
+        assert!(capabilities.shared_playlists);
+        assert!(!capabilities.readiness);
+        assert!(capabilities.managed_rooms);
+        assert!(!capabilities.persistent_rooms);
+        assert!(capabilities.isolate_rooms);
+        assert_eq!(capabilities.max_chat_message_length, 300);
+        assert_eq!(capabilities.max_username_length, Some(20));
+        assert_eq!(capabilities.max_room_name_length, Some(35));
+    }
+
+    #[test]
+    fn test_capabilities_for_old_servers() {
+        let capabilities = ServerCapabilities::from_hello("1.3.4", None);
+        assert!(!capabilities.chat);
+        assert!(!capabilities.shared_playlists);
+        assert!(capabilities.readiness);
+        assert!(capabilities.managed_rooms);
+        assert_eq!(capabilities.max_chat_message_length, 50);
+
+        let capabilities = ServerCapabilities::from_hello("1.7.0-beta", None);
+        assert!(capabilities.chat);
+        assert!(capabilities.shared_playlists);
+        assert_eq!(parse_version("1.7.0-beta"), Some((1, 7, 0)));
+        assert_eq!(parse_version("garbage"), None);
+    }
+}
@@ -76,6 +76,10 @@ pub enum ChatCommand {
     Ready,
     /// Set not ready: /unready
     Unready,
+    /// Create a managed room: /create <name>
+    Create(String),
+    /// Authenticate as room operator: /auth <password>
+    Auth(String),
     /// Unknown command
     Unknown(String),
 }
@@ -105,6 +109,20 @@ impl ChatCommand {
             "/help" | "/h" | "/?" => Some(ChatCommand::Help),
             "/ready" => Some(ChatCommand::Ready),
             "/unready" => Some(ChatCommand::Unready),
+            "/auth" | "/a" => {
+                if parts.len() > 1 {
+                    Some(ChatCommand::Auth(parts[1..].join(" ")))
+                } else {
+                    Some(ChatCommand::Unknown("Usage: /auth <password>".to_string()))
+                }
+            }
+            "/create" | "/c" => {
+                if parts.len() > 1 {
+                    Some(ChatCommand::Create(parts[1..].join(" ")))
+                } else {
+                    Some(ChatCommand::Unknown("Usage: /create <name>".to_string()))
+                }
+            }
             _ => Some(ChatCommand::Unknown(format!(
                 "Unknown command: {}",
                 command
@@ -119,6 +137,8 @@ impl ChatCommand {
 /list or /l - List all users in the current room
 /ready - Mark yourself as ready
 /unready - Mark yourself as not ready
+/create <name> or /c <name> - Create a managed room and become its operator
+/auth <password> or /a <password> - Authenticate as operator of the current managed room
 /help or /h or /? - Show this help message"#
             .to_string()
     }
@@ -262,6 +282,24 @@ mod tests {
         assert_eq!(cmd, Some(ChatCommand::Unready));
     }
 
+    #[test]
+    fn test_chat_command_parse_create() {
+        let cmd = ChatCommand::parse("/create movie night");
+        assert_eq!(cmd, Some(ChatCommand::Create("movie night".to_string())));
+
+        let cmd = ChatCommand::parse("/c");
+        assert!(matches!(cmd, Some(ChatCommand::Unknown(_))));
+    }
+
+    #[test]
+    fn test_chat_command_parse_auth() {
+        let cmd = ChatCommand::parse("/auth AB-123-456");
+        assert_eq!(cmd, Some(ChatCommand::Auth("AB-123-456".to_string())));
+
+        let cmd = ChatCommand::parse("/a");
+        assert!(matches!(cmd, Some(ChatCommand::Unknown(_))));
+    }
+
     #[test]
     fn test_chat_command_parse_unknown() {
         let cmd = ChatCommand::parse("/unknown");
@@ -0,0 +1,585 @@
+// Step-by-step connection diagnostics for a server
+
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use super::session::{advertised_features, connect_timeouts, proxy_for, tls_trust_for};
+use crate::config::{SyncplayConfig, TlsPolicy};
+use crate::network::connection::Connection;
+use crate::network::messages::{HelloMessage, ProtocolMessage, RoomInfo, TLSMessage};
+use crate::network::tls::TlsTrust;
+
+/// Longest wait for any single check
+const STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Username used when none is configured
+const FALLBACK_USERNAME: &str = "syncplay-diagnostics";
+
+/// Checks in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticCheck {
+    Dns,
+    TcpConnect,
+    StartTls,
+    Certificate,
+    Hello,
+}
+
+const CHECKS: [DiagnosticCheck; 5] = [
+    DiagnosticCheck::Dns,
+    DiagnosticCheck::TcpConnect,
+    DiagnosticCheck::StartTls,
+    DiagnosticCheck::Certificate,
+    DiagnosticCheck::Hello,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Passed,
+    /// Worked, but not the way it should (e.g. no TLS)
+    Warning,
+    Failed,
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticStep {
+    pub check: DiagnosticCheck,
+    pub status: CheckStatus,
+    pub duration_ms: Option<f64>,
+    pub detail: String,
+}
+
+/// Outcome of [`diagnose_server`]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticReport {
+    pub host: String,
+    pub port: u16,
+    pub steps: Vec<DiagnosticStep>,
+    pub resolved_addresses: Vec<String>,
+    pub encrypted: bool,
+    pub certificate_fingerprint: Option<String>,
+    pub server_version: Option<String>,
+    pub motd: Option<String>,
+}
+
+impl DiagnosticReport {
+    fn new(host: &str, port: u16) -> Self {
+        Self {
+            host: host.to_string(),
+            port,
+            steps: Vec::new(),
+            resolved_addresses: Vec::new(),
+            encrypted: false,
+            certificate_fingerprint: None,
+            server_version: None,
+            motd: None,
+        }
+    }
+
+    fn push(
+        &mut self,
+        check: DiagnosticCheck,
+        status: CheckStatus,
+        started: Option<Instant>,
+        detail: impl Into<String>,
+    ) {
+        self.steps.push(DiagnosticStep {
+            check,
+            status,
+            duration_ms: started.map(|started| started.elapsed().as_secs_f64() * 1000.0),
+            detail: detail.into(),
+        });
+    }
+
+    /// Mark checks that never ran as skipped
+    fn finish(mut self) -> Self {
+        for check in CHECKS {
+            if !self.steps.iter().any(|step| step.check == check) {
+                self.push(
+                    check,
+                    CheckStatus::Skipped,
+                    None,
+                    "Not run because an earlier check failed",
+                );
+            }
+        }
+        self
+    }
+
+    /// Whether every check passed or only warned
+    pub fn succeeded(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|step| step.status != CheckStatus::Failed)
+    }
+
+    pub fn step(&self, check: DiagnosticCheck) -> Option<&DiagnosticStep> {
+        self.steps.iter().find(|step| step.check == check)
+    }
+}
+
+/// Resolve, connect, negotiate TLS and exchange a Hello with `host:port`,
+/// using the TLS and proxy settings saved for that server.
+///
+/// The Hello joins a throwaway room so nobody sees the probe.
+pub async fn diagnose_server(config: &SyncplayConfig, host: &str, port: u16) -> DiagnosticReport {
+    let mut report = DiagnosticReport::new(host, port);
+    let proxy = proxy_for(config, host, port);
+    let trust = tls_trust_for(config, host, port);
+
+    if proxy.is_some() {
+        report.push(
+            DiagnosticCheck::Dns,
+            CheckStatus::Skipped,
+            None,
+            "The proxy resolves the server name",
+        );
+    } else {
+        let started = Instant::now();
+        match tokio::time::timeout(STEP_TIMEOUT, tokio::net::lookup_host((host, port))).await {
+            Ok(Ok(addresses)) => {
+                for address in addresses {
+                    let ip = address.ip().to_string();
+                    if !report.resolved_addresses.contains(&ip) {
+                        report.resolved_addresses.push(ip);
+                    }
+                }
+                let detail = format!("Resolved to {}", report.resolved_addresses.join(", "));
+                report.push(
+                    DiagnosticCheck::Dns,
+                    CheckStatus::Passed,
+                    Some(started),
+                    detail,
+                );
+            }
+            Ok(Err(e)) => {
+                report.push(
+                    DiagnosticCheck::Dns,
+                    CheckStatus::Failed,
+                    Some(started),
+                    format!("Failed to resolve {}: {}", host, e),
+                );
+                return report.finish();
+            }
+            Err(_) => {
+                report.push(
+                    DiagnosticCheck::Dns,
+                    CheckStatus::Failed,
+                    Some(started),
+                    "DNS lookup timed out",
+                );
+                return report.finish();
+            }
+        }
+    }
+
+    let via_proxy = proxy.is_some();
+    let connection = Connection::new()
+        .with_tls_trust(trust.clone())
+        .with_proxy(proxy)
+        .with_timeouts(connect_timeouts(config));
+    let started = Instant::now();
+    let mut receiver = match tokio::time::timeout(
+        STEP_TIMEOUT,
+        connection.connect(host.to_string(), port),
+    )
+    .await
+    {
+        Ok(Ok(receiver)) => {
+            let detail = if via_proxy {
+                "Connected through the proxy"
+            } else {
+                "Connected"
+            };
+            report.push(
+                DiagnosticCheck::TcpConnect,
+                CheckStatus::Passed,
+                Some(started),
+                detail,
+            );
+            receiver
+        }
+        Ok(Err(e)) => {
+            report.push(
+                DiagnosticCheck::TcpConnect,
+                CheckStatus::Failed,
+                Some(started),
+                format!("{:#}", e),
+            );
+            return report.finish();
+        }
+        Err(_) => {
+            report.push(
+                DiagnosticCheck::TcpConnect,
+                CheckStatus::Failed,
+                Some(started),
+                "Connection timed out",
+            );
+            return report.finish();
+        }
+    };
+
+    run_handshake_checks(config, &connection, &mut receiver, &trust, &mut report).await;
+    connection.disconnect();
+    report.finish()
+}
+
+async fn run_handshake_checks(
+    config: &SyncplayConfig,
+    connection: &Connection,
+    receiver: &mut mpsc::Receiver<ProtocolMessage>,
+    trust: &TlsTrust,
+    report: &mut DiagnosticReport,
+) {
+    let server = config.server_for(&report.host, report.port);
+    let policy = server.map(|server| server.tls_policy).unwrap_or_default();
+    if policy == TlsPolicy::Disabled {
+        report.push(
+            DiagnosticCheck::StartTls,
+            CheckStatus::Skipped,
+            None,
+            "TLS is disabled for this server",
+        );
+        report.push(
+            DiagnosticCheck::Certificate,
+            CheckStatus::Skipped,
+            None,
+            "No TLS",
+        );
+    } else if !negotiate_tls(connection, receiver, trust, policy, report).await {
+        return;
+    }
+
+    // Like a real connection, never let the server password travel in plaintext
+    let saved_password = server.and_then(|server| server.password.clone());
+    let withheld_password = !report.encrypted && saved_password.is_some();
+    let username = Some(config.user.username.trim())
+        .filter(|name| !name.is_empty())
+        .unwrap_or(FALLBACK_USERNAME)
+        .to_string();
+    let hello = ProtocolMessage::Hello {
+        Hello: HelloMessage {
+            username,
+            password: saved_password.filter(|_| report.encrypted),
+            room: Some(RoomInfo {
+                name: format!("diagnostics-{:08x}", rand::random::<u32>()),
+                password: None,
+            }),
+            version: "1.2.255".to_string(),
+            realversion: "1.7.4".to_string(),
+            features: Some(advertised_features(config)),
+            motd: None,
+        },
+    };
+    let started = Instant::now();
+    if let Err(e) = connection.send(hello) {
+        report.push(
+            DiagnosticCheck::Hello,
+            CheckStatus::Failed,
+            Some(started),
+            format!("Failed to send Hello: {:#}", e),
+        );
+        return;
+    }
+    let reply = next_matching(receiver, |message| match message {
+        ProtocolMessage::Hello { Hello } => Some(Ok(Hello)),
+        ProtocolMessage::Error { Error } => Some(Err(Error.message)),
+        _ => None,
+    })
+    .await;
+    let withheld_note = if withheld_password {
+        " (sent without the saved server password, the connection is not encrypted)"
+    } else {
+        ""
+    };
+    match reply {
+        Some(Ok(hello)) => {
+            report.push(
+                DiagnosticCheck::Hello,
+                CheckStatus::Passed,
+                Some(started),
+                format!("Server version {}{}", hello.realversion, withheld_note),
+            );
+            report.server_version = Some(hello.realversion);
+            report.motd = hello.motd.filter(|motd| !motd.trim().is_empty());
+        }
+        Some(Err(message)) => report.push(
+            DiagnosticCheck::Hello,
+            CheckStatus::Failed,
+            Some(started),
+            format!("Server rejected the Hello: {}{}", message, withheld_note),
+        ),
+        None => report.push(
+            DiagnosticCheck::Hello,
+            CheckStatus::Failed,
+            Some(started),
+            "No Hello reply from the server",
+        ),
+    }
+}
+
+/// Ask for startTLS and upgrade the connection.
+/// Returns whether the Hello may be sent afterwards.
+async fn negotiate_tls(
+    connection: &Connection,
+    receiver: &mut mpsc::Receiver<ProtocolMessage>,
+    trust: &TlsTrust,
+    policy: TlsPolicy,
+    report: &mut DiagnosticReport,
+) -> bool {
+    let started = Instant::now();
+    let request = ProtocolMessage::TLS {
+        TLS: TLSMessage {
+            start_tls: Some("send".to_string()),
+        },
+    };
+    if let Err(e) = connection.send(request) {
+        report.push(
+            DiagnosticCheck::StartTls,
+            CheckStatus::Failed,
+            Some(started),
+            format!("Failed to send the startTLS request: {:#}", e),
+        );
+        return false;
+    }
+    // Servers without TLS support may answer startTLS with an Error
+    let accepted = next_matching(receiver, |message| match message {
+        ProtocolMessage::TLS { TLS } => Some(TLS.start_tls.as_deref() == Some("true")),
+        ProtocolMessage::Error { .. } => Some(false),
+        _ => None,
+    })
+    .await;
+    match accepted {
+        Some(true) => {
+            report.push(
+                DiagnosticCheck::StartTls,
+                CheckStatus::Passed,
+                Some(started),
+                "Server supports TLS",
+            );
+            let started = Instant::now();
+            match connection.upgrade_tls().await {
+                Ok(fingerprint) => {
+                    let (status, detail) = if trust.pin_sha256.is_some() {
+                        (
+                            CheckStatus::Passed,
+                            "Certificate matches the pinned fingerprint",
+                        )
+                    } else if trust.trust_on_first_use {
+                        (
+                            CheckStatus::Warning,
+                            "Certificate accepted on first use without chain validation",
+                        )
+                    } else {
+                        (CheckStatus::Passed, "Certificate chain is valid")
+                    };
+                    report.push(DiagnosticCheck::Certificate, status, Some(started), detail);
+                    report.encrypted = true;
+                    report.certificate_fingerprint = fingerprint;
+                }
+                Err(e) => {
+                    report.push(
+                        DiagnosticCheck::Certificate,
+                        CheckStatus::Failed,
+                        Some(started),
+                        format!("{:#}", e),
+                    );
+                    return false;
+                }
+            }
+        }
+        Some(false) if policy == TlsPolicy::Required => {
+            report.push(
+                DiagnosticCheck::StartTls,
+                CheckStatus::Failed,
+                Some(started),
+                "Server does not support TLS, which this server's TLS policy requires",
+            );
+            report.push(
+                DiagnosticCheck::Certificate,
+                CheckStatus::Skipped,
+                None,
+                "No TLS",
+            );
+            report.push(
+                DiagnosticCheck::Hello,
+                CheckStatus::Failed,
+                None,
+                "Not sent: the TLS policy forbids a Hello without encryption",
+            );
+            return false;
+        }
+        Some(false) => {
+            report.push(
+                DiagnosticCheck::StartTls,
+                CheckStatus::Warning,
+                Some(started),
+                "Server does not support TLS; the connection is not encrypted",
+            );
+            report.push(
+                DiagnosticCheck::Certificate,
+                CheckStatus::Skipped,
+                None,
+                "No TLS",
+            );
+        }
+        None => {
+            report.push(
+                DiagnosticCheck::StartTls,
+                CheckStatus::Failed,
+                Some(started),
+                "No answer to the startTLS request",
+            );
+            return false;
+        }
+    }
+    true
+}
+
+/// First message accepted by `matcher`, or `None` on timeout or hangup
+async fn next_matching<T>(
+    receiver: &mut mpsc::Receiver<ProtocolMessage>,
+    mut matcher: impl FnMut(ProtocolMessage) -> Option<T>,
+) -> Option<T> {
+    tokio::time::timeout(STEP_TIMEOUT, async {
+        while let Some(message) = receiver.recv().await {
+            if let Some(value) = matcher(message) {
+                return Some(value);
+            }
+        }
+        None
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use crate::network::mock_server::{self, MockServer};
+
+    #[tokio::test]
+    async fn test_diagnose_plaintext_server() {
+        let server = MockServer::bind().await;
+        let port = server.port();
+        let peer = tokio::spawn(async move {
+            let mut peer = server.accept().await;
+            let hello = peer.accept_hello("false").await;
+            assert!(hello.room.unwrap().name.starts_with("diagnostics-"));
+            let mut reply = mock_server::hello_reply(&hello.username, "lobby");
+            if let ProtocolMessage::Hello { Hello } = &mut reply {
+                Hello.motd = Some("Welcome".to_string());
+            }
+            peer.send(reply).await;
+            peer.expect_closed().await;
+        });
+
+        let report = diagnose_server(&SyncplayConfig::default(), "127.0.0.1", port).await;
+        peer.await.unwrap();
+
+        assert!(report.succeeded(), "{:?}", report);
+        assert_eq!(report.resolved_addresses, vec!["127.0.0.1"]);
+        let status = |check| report.step(check).unwrap().status;
+        assert_eq!(status(DiagnosticCheck::Dns), CheckStatus::Passed);
+        assert_eq!(status(DiagnosticCheck::TcpConnect), CheckStatus::Passed);
+        assert_eq!(status(DiagnosticCheck::StartTls), CheckStatus::Warning);
+        assert_eq!(status(DiagnosticCheck::Certificate), CheckStatus::Skipped);
+        assert_eq!(status(DiagnosticCheck::Hello), CheckStatus::Passed);
+        assert!(!report.encrypted);
+        assert_eq!(report.server_version.as_deref(), Some("1.7.4"));
+        assert_eq!(report.motd.as_deref(), Some("Welcome"));
+    }
+
+    fn config_with_policy(port: u16, policy: TlsPolicy) -> SyncplayConfig {
+        let mut config = SyncplayConfig::default();
+        config.add_recent_server(ServerConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            password: Some("secret".to_string()),
+            tls_policy: policy,
+            ..ServerConfig::default()
+        });
+        config
+    }
+
+    #[tokio::test]
+    async fn test_diagnose_required_tls_withholds_hello() {
+        let server = MockServer::bind().await;
+        let port = server.port();
+        let peer = tokio::spawn(async move {
+            let mut peer = server.accept().await;
+            peer.expect(|message| match message {
+                ProtocolMessage::TLS { .. } => Some(()),
+                _ => None,
+            })
+            .await;
+            peer.send(mock_server::tls_answer("false")).await;
+            peer.expect_closed().await
+        });
+
+        let config = config_with_policy(port, TlsPolicy::Required);
+        let report = diagnose_server(&config, "127.0.0.1", port).await;
+        let received = peer.await.unwrap();
+
+        assert!(!received
+            .iter()
+            .any(|message| matches!(message, ProtocolMessage::Hello { .. })));
+        let status = |check| report.step(check).unwrap().status;
+        assert_eq!(status(DiagnosticCheck::StartTls), CheckStatus::Failed);
+        assert_eq!(status(DiagnosticCheck::Hello), CheckStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_diagnose_disabled_tls_skips_start_tls_and_password() {
+        let server = MockServer::bind().await;
+        let port = server.port();
+        let peer = tokio::spawn(async move {
+            let mut peer = server.accept().await;
+            let hello = match peer.recv().await {
+                ProtocolMessage::Hello { Hello } => Hello,
+                other => panic!("Expected Hello, got {:?}", other),
+            };
+            peer.send(mock_server::hello_reply(&hello.username, "lobby"))
+                .await;
+            peer.expect_closed().await;
+            hello
+        });
+
+        let config = config_with_policy(port, TlsPolicy::Disabled);
+        let report = diagnose_server(&config, "127.0.0.1", port).await;
+        let hello = peer.await.unwrap();
+
+        assert!(hello.password.is_none());
+        assert!(report.succeeded(), "{:?}", report);
+        let step = |check| report.step(check).unwrap();
+        assert_eq!(step(DiagnosticCheck::StartTls).status, CheckStatus::Skipped);
+        assert!(step(DiagnosticCheck::Hello).detail.contains("password"));
+    }
+
+    #[tokio::test]
+    async fn test_diagnose_unreachable_server() {
+        let port = {
+            let server = MockServer::bind().await;
+            server.port()
+        };
+        let report = diagnose_server(&SyncplayConfig::default(), "127.0.0.1", port).await;
+        assert!(!report.succeeded());
+        assert_eq!(report.steps.len(), CHECKS.len());
+        assert_eq!(
+            report.step(DiagnosticCheck::TcpConnect).unwrap().status,
+            CheckStatus::Failed
+        );
+        assert_eq!(
+            report.step(DiagnosticCheck::Hello).unwrap().status,
+            CheckStatus::Skipped
+        );
+    }
+}
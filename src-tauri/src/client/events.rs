@@ -0,0 +1,52 @@
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+/// Event produced by the client core
+#[derive(Debug, Clone)]
+pub struct ClientEvent {
+    pub name: String,
+    pub payload: Value,
+}
+
+/// Receives events produced by the client core
+pub trait ClientEventSink: Send + Sync {
+    fn emit(&self, event: &str, payload: Value);
+}
+
+/// Event sink that forwards events into a channel
+pub struct ChannelEventSink {
+    tx: mpsc::UnboundedSender<ClientEvent>,
+}
+
+impl ChannelEventSink {
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<ClientEvent>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self { tx }, rx)
+    }
+}
+
+impl ClientEventSink for ChannelEventSink {
+    fn emit(&self, event: &str, payload: Value) {
+        let _ = self.tx.send(ClientEvent {
+            name: event.to_string(),
+            payload,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_sink_forwards_events() {
+        let (sink, mut rx) = ChannelEventSink::new();
+        sink.emit("ping-updated", serde_json::json!({ "rttMs": 12.0 }));
+        let event = rx.try_recv().unwrap();
+        assert_eq!(event.name, "ping-updated");
+        assert_eq!(event.payload["rttMs"], 12.0);
+
+        drop(rx);
+        sink.emit("ignored", Value::Null);
+    }
+}
@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::config::SyncplayConfig;
+
+/// Platform services the client core needs from its embedder
+pub trait ClientHost: Send + Sync {
+    /// Persist an updated configuration
+    fn save_config(&self, config: &SyncplayConfig) -> Result<(), String>;
+
+    /// Load saved room operator passwords, keyed by room
+    fn load_room_passwords(&self) -> Result<HashMap<String, String>, String>;
+
+    /// Persist room operator passwords outside the config
+    fn save_room_passwords(&self, passwords: &HashMap<String, String>) -> Result<(), String>;
+
+    /// Locate a bundled resource file
+    fn resolve_resource(&self, name: &str) -> Option<PathBuf>;
+}
+
+/// Host for scripts and tests: nothing is persisted and no resources are bundled
+pub struct HeadlessHost;
+
+impl ClientHost for HeadlessHost {
+    fn save_config(&self, _config: &SyncplayConfig) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn load_room_passwords(&self) -> Result<HashMap<String, String>, String> {
+        Ok(HashMap::new())
+    }
+
+    fn save_room_passwords(&self, _passwords: &HashMap<String, String>) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn resolve_resource(&self, _name: &str) -> Option<PathBuf> {
+        None
+    }
+}
@@ -1,7 +1,17 @@
+pub mod capabilities;
 pub mod chat;
+pub mod diagnostics;
+pub mod events;
+pub mod host;
 pub mod local_state;
 pub mod playlist;
 pub mod ready;
+pub mod replay;
+pub mod room_directory;
+pub mod server_error;
+pub mod server_list;
+pub mod session;
+pub mod sessions;
 pub mod state;
 pub mod sync;
 pub mod userlist;
@@ -0,0 +1,266 @@
+// Replay of recorded protocol traffic against a simulated player
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::events::ClientEventSink;
+use super::session::{handle_server_message, SyncplayClient};
+use crate::network::messages::ProtocolMessage;
+use crate::network::recorder::{Direction, RecordedFrame};
+use crate::player::backend::{PlayerBackend, PlayerKind};
+use crate::player::properties::PlayerState;
+
+/// A command the client issued to the player during replay
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "call", rename_all = "snake_case")]
+pub enum PlayerCall {
+    Seek { position: f64 },
+    SetPaused { paused: bool },
+    SetSpeed { speed: f64 },
+    LoadFile { path: String },
+    Osd { text: String },
+}
+
+struct SimulatedPlayback {
+    position: f64,
+    paused: bool,
+    speed: f64,
+    filename: Option<String>,
+    updated_at: Instant,
+}
+
+impl SimulatedPlayback {
+    fn current_position(&self) -> f64 {
+        if self.paused {
+            self.position
+        } else {
+            self.position + self.updated_at.elapsed().as_secs_f64() * self.speed
+        }
+    }
+
+    /// Fold elapsed playback into `position` before changing anything
+    fn settle(&mut self) {
+        self.position = self.current_position();
+        self.updated_at = Instant::now();
+    }
+}
+
+/// Player backend that simulates playback and logs every command it receives
+pub struct ReplayPlayer {
+    playback: Mutex<SimulatedPlayback>,
+    calls: Mutex<Vec<PlayerCall>>,
+    sink: Option<Arc<dyn ClientEventSink>>,
+}
+
+impl ReplayPlayer {
+    /// Paused player at 0s; calls are also emitted as `replay-player-call` events to `sink`
+    pub fn new(sink: Option<Arc<dyn ClientEventSink>>) -> Self {
+        Self {
+            playback: Mutex::new(SimulatedPlayback {
+                position: 0.0,
+                paused: true,
+                speed: 1.0,
+                filename: None,
+                updated_at: Instant::now(),
+            }),
+            calls: Mutex::new(Vec::new()),
+            sink,
+        }
+    }
+
+    /// Commands received so far
+    pub fn calls(&self) -> Vec<PlayerCall> {
+        self.calls.lock().clone()
+    }
+
+    fn log(&self, call: PlayerCall) {
+        if let Some(sink) = &self.sink {
+            if let Ok(payload) = serde_json::to_value(&call) {
+                sink.emit("replay-player-call", payload);
+            }
+        }
+        self.calls.lock().push(call);
+    }
+}
+
+#[async_trait]
+impl PlayerBackend for ReplayPlayer {
+    fn kind(&self) -> PlayerKind {
+        PlayerKind::Unknown
+    }
+
+    fn name(&self) -> &'static str {
+        "Replay"
+    }
+
+    fn get_state(&self) -> PlayerState {
+        let playback = self.playback.lock();
+        PlayerState {
+            position: Some(playback.current_position()),
+            paused: Some(playback.paused),
+            filename: playback.filename.clone(),
+            duration: None,
+            path: playback.filename.clone(),
+            speed: Some(playback.speed),
+        }
+    }
+
+    async fn poll_state(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn set_position(&self, position: f64) -> anyhow::Result<()> {
+        {
+            let mut playback = self.playback.lock();
+            playback.settle();
+            playback.position = position;
+        }
+        self.log(PlayerCall::Seek { position });
+        Ok(())
+    }
+
+    async fn set_paused(&self, paused: bool) -> anyhow::Result<()> {
+        {
+            let mut playback = self.playback.lock();
+            playback.settle();
+            playback.paused = paused;
+        }
+        self.log(PlayerCall::SetPaused { paused });
+        Ok(())
+    }
+
+    async fn set_speed(&self, speed: f64) -> anyhow::Result<()> {
+        {
+            let mut playback = self.playback.lock();
+            playback.settle();
+            playback.speed = speed;
+        }
+        self.log(PlayerCall::SetSpeed { speed });
+        Ok(())
+    }
+
+    async fn load_file(&self, path: &str) -> anyhow::Result<()> {
+        {
+            let mut playback = self.playback.lock();
+            playback.filename = Some(path.to_string());
+            playback.position = 0.0;
+            playback.updated_at = Instant::now();
+        }
+        self.log(PlayerCall::LoadFile {
+            path: path.to_string(),
+        });
+        Ok(())
+    }
+
+    fn show_osd(&self, text: &str, _duration_ms: Option<u64>) -> anyhow::Result<()> {
+        self.log(PlayerCall::Osd {
+            text: text.to_string(),
+        });
+        Ok(())
+    }
+}
+
+/// Feed the received frames of a recording through the client's message handling.
+///
+/// Identity is taken from the recorded Hello. With `realtime`, frames are
+/// delivered with their original spacing, which timing-sensitive sync bugs need.
+pub async fn replay_recording(
+    client: &SyncplayClient,
+    player: Arc<ReplayPlayer>,
+    frames: &[RecordedFrame],
+    realtime: bool,
+) {
+    let state = client.state();
+    *state.player.lock() = Some(player);
+
+    let started = Instant::now();
+    for frame in frames {
+        if realtime {
+            let due = Duration::from_secs_f64(frame.elapsed_ms.max(0.0) / 1000.0);
+            tokio::time::sleep(due.saturating_sub(started.elapsed())).await;
+        }
+        match (&frame.direction, &frame.message) {
+            (Direction::Sent, ProtocolMessage::Hello { Hello }) => {
+                state.client_state.set_username(Hello.username.clone());
+                if let Some(room) = &Hello.room {
+                    state.client_state.set_room(room.name.clone());
+                }
+            }
+            (Direction::Sent, _) => {}
+            (Direction::Received, message) => {
+                handle_server_message(message.clone(), state).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::events::ChannelEventSink;
+    use crate::config::SyncplayConfig;
+    use crate::network::messages::{HelloMessage, PlayState, RoomInfo, StateMessage};
+
+    fn frame(elapsed_ms: f64, direction: Direction, message: ProtocolMessage) -> RecordedFrame {
+        RecordedFrame {
+            elapsed_ms,
+            direction,
+            message,
+        }
+    }
+
+    fn state(position: f64, paused: bool, do_seek: bool) -> ProtocolMessage {
+        ProtocolMessage::State {
+            State: StateMessage {
+                playstate: Some(PlayState {
+                    position,
+                    paused,
+                    do_seek: Some(do_seek),
+                    set_by: Some("bob".to_string()),
+                }),
+                ping: None,
+                ignoring_on_the_fly: None,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_drives_player_from_recorded_states() {
+        let mut config = SyncplayConfig::default();
+        config.user.autosave_joins_to_list = false;
+        let (sink, _events) = ChannelEventSink::new();
+        let client = SyncplayClient::headless(config, Arc::new(sink));
+        let player = Arc::new(ReplayPlayer::new(None));
+
+        let hello = ProtocolMessage::Hello {
+            Hello: HelloMessage {
+                username: "alice".to_string(),
+                password: None,
+                room: Some(RoomInfo {
+                    name: "lobby".to_string(),
+                    password: None,
+                }),
+                version: "1.2.255".to_string(),
+                realversion: "1.7.4".to_string(),
+                features: None,
+                motd: None,
+            },
+        };
+        let frames = vec![
+            frame(0.0, Direction::Sent, hello),
+            frame(10.0, Direction::Received, state(0.0, true, false)),
+            frame(20.0, Direction::Received, state(42.0, false, true)),
+        ];
+        replay_recording(&client, player.clone(), &frames, false).await;
+
+        assert_eq!(client.state().client_state.get_username(), "alice");
+        let calls = player.calls();
+        assert!(calls.contains(&PlayerCall::Seek { position: 42.0 }));
+        assert!(calls.contains(&PlayerCall::SetPaused { paused: false }));
+        let position = player.get_state().position.unwrap();
+        assert!(position >= 42.0, "{}", position);
+    }
+}
@@ -0,0 +1,94 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::state::User;
+use crate::utils::is_controlled_room;
+
+/// Occupancy and playback of one room, built from the server user list
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomSummary {
+    pub name: String,
+    pub user_count: usize,
+    pub ready_count: usize,
+    /// Distinct files being played in the room, sorted
+    pub files: Vec<String>,
+    pub has_controller: bool,
+    pub managed: bool,
+}
+
+/// Summarize `users` per room, sorted by room name
+pub fn room_directory(users: &[User]) -> Vec<RoomSummary> {
+    let mut rooms: BTreeMap<&str, (RoomSummary, BTreeSet<&str>)> = BTreeMap::new();
+    for user in users {
+        let (summary, files) = rooms.entry(user.room.as_str()).or_insert_with(|| {
+            (
+                RoomSummary {
+                    name: user.room.clone(),
+                    user_count: 0,
+                    ready_count: 0,
+                    files: Vec::new(),
+                    has_controller: false,
+                    managed: is_controlled_room(&user.room),
+                },
+                BTreeSet::new(),
+            )
+        });
+        summary.user_count += 1;
+        if user.is_ready {
+            summary.ready_count += 1;
+        }
+        summary.has_controller |= user.is_controller;
+        if let Some(file) = user.file.as_deref().filter(|file| !file.is_empty()) {
+            files.insert(file);
+        }
+    }
+    rooms
+        .into_values()
+        .map(|(mut summary, files)| {
+            summary.files = files.into_iter().map(str::to_string).collect();
+            summary
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(name: &str, room: &str, file: Option<&str>, ready: bool, controller: bool) -> User {
+        User {
+            file: file.map(str::to_string),
+            is_ready: ready,
+            is_controller: controller,
+            ..User::new(name, room)
+        }
+    }
+
+    #[test]
+    fn test_room_directory_groups_users_by_room() {
+        let users = vec![
+            user("alice", "lobby", Some("a.mkv"), true, false),
+            user("bob", "lobby", Some("a.mkv"), false, false),
+            user("carol", "+movie:ABCDEF123456", Some("b.mkv"), true, true),
+            user("dave", "+movie:ABCDEF123456", None, true, false),
+        ];
+        let directory = room_directory(&users);
+        assert_eq!(directory.len(), 2);
+
+        let movie = &directory[0];
+        assert_eq!(movie.name, "+movie:ABCDEF123456");
+        assert_eq!(movie.user_count, 2);
+        assert_eq!(movie.ready_count, 2);
+        assert_eq!(movie.files, vec!["b.mkv".to_string()]);
+        assert!(movie.has_controller);
+        assert!(movie.managed);
+
+        let lobby = &directory[1];
+        assert_eq!(lobby.user_count, 2);
+        assert_eq!(lobby.ready_count, 1);
+        assert_eq!(lobby.files, vec!["a.mkv".to_string()]);
+        assert!(!lobby.has_controller);
+        assert!(!lobby.managed);
+    }
+}
@@ -0,0 +1,96 @@
+use serde::Serialize;
+
+/// What a server `Error` message is complaining about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerErrorKind {
+    /// Missing or wrong server password
+    WrongPassword,
+    /// The server does not accept this client's protocol version
+    VersionMismatch,
+    /// The requested username is already in use
+    UsernameTaken,
+    /// A managed room could not be created
+    RoomCreationFailed,
+    Unknown,
+}
+
+/// Error message sent by the server, classified by its text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ServerError {
+    pub kind: ServerErrorKind,
+    pub message: String,
+}
+
+impl ServerError {
+    pub fn classify(message: &str) -> Self {
+        let text = message.to_lowercase();
+        let mentions = |needles: &[&str]| needles.iter().any(|needle| text.contains(needle));
+        let kind = if text.contains("password") && !text.contains("room") {
+            ServerErrorKind::WrongPassword
+        } else if mentions(&["version mismatch", "incompatible", "unsupported version"]) {
+            ServerErrorKind::VersionMismatch
+        } else if text.contains("name")
+            && mentions(&["in use", "taken", "already", "exists"])
+            && !text.contains("room")
+        {
+            ServerErrorKind::UsernameTaken
+        } else if text.contains("room") && mentions(&["create", "controlled", "managed"]) {
+            ServerErrorKind::RoomCreationFailed
+        } else {
+            ServerErrorKind::Unknown
+        };
+        Self {
+            kind,
+            message: message.to_string(),
+        }
+    }
+
+    /// Errors after which reconnecting with the same settings cannot succeed
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self.kind,
+            ServerErrorKind::WrongPassword | ServerErrorKind::VersionMismatch
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(message: &str) -> ServerErrorKind {
+        ServerError::classify(message).kind
+    }
+
+    #[test]
+    fn test_classify_reference_server_errors() {
+        assert_eq!(
+            kind("Wrong password supplied"),
+            ServerErrorKind::WrongPassword
+        );
+        assert_eq!(kind("Password required"), ServerErrorKind::WrongPassword);
+        assert_eq!(
+            kind("Version mismatch between client and server"),
+            ServerErrorKind::VersionMismatch
+        );
+        assert_eq!(
+            kind("Username is already in use"),
+            ServerErrorKind::UsernameTaken
+        );
+        assert_eq!(
+            kind("Failed to create managed room"),
+            ServerErrorKind::RoomCreationFailed
+        );
+        assert_eq!(kind("Not a json encoded string"), ServerErrorKind::Unknown);
+        assert_eq!(kind("Unknown command startTLS"), ServerErrorKind::Unknown);
+    }
+
+    #[test]
+    fn test_only_credential_and_version_errors_are_fatal() {
+        assert!(ServerError::classify("Wrong password supplied").is_fatal());
+        assert!(ServerError::classify("Version mismatch between client and server").is_fatal());
+        assert!(!ServerError::classify("Username is already in use").is_fatal());
+        assert!(!ServerError::classify("Not logged in, send Hello first").is_fatal());
+    }
+}
@@ -0,0 +1,201 @@
+// Public server list fetching and latency probing
+
+use anyhow::{bail, Context, Result};
+use futures::future::join_all;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use crate::config::PublicServer;
+use crate::network::happy_eyeballs;
+use crate::utils::parse_server_address;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_SERVER_PORT: u16 = 8999;
+
+/// A public server with its measured TCP connect latency
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbedServer {
+    pub name: String,
+    pub address: String,
+    /// `None` when the server could not be reached within the probe timeout
+    pub latency_ms: Option<f64>,
+}
+
+/// Download and parse the public server list at `url`
+pub async fn fetch_public_servers(url: &str) -> Result<Vec<PublicServer>> {
+    let client = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .context("Failed to create HTTP client")?;
+    let body = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("Failed to fetch server list from {}", url))?
+        .text()
+        .await
+        .context("Failed to read server list")?;
+    parse_server_list(&body)
+}
+
+/// Parse a server list in the official client's format.
+///
+/// The update endpoint answers with a JSON object whose `public-servers`
+/// field is a Python literal list of `(name, address)` tuples; plain JSON
+/// arrays of pairs or `{name, address}` objects are accepted too.
+pub fn parse_server_list(body: &str) -> Result<Vec<PublicServer>> {
+    let json: Value = serde_json::from_str(body).context("Server list is not valid JSON")?;
+    let list = match &json {
+        Value::Object(object) => object
+            .get("public-servers")
+            .or_else(|| object.get("publicServers"))
+            .context("Server list has no public-servers field")?,
+        other => other,
+    };
+    let servers = match list {
+        Value::String(literal) => parse_python_pairs(literal),
+        Value::Array(entries) => entries.iter().filter_map(parse_entry).collect(),
+        _ => bail!("Unsupported server list format"),
+    };
+    if servers.is_empty() {
+        bail!("Server list is empty");
+    }
+    Ok(servers)
+}
+
+fn parse_entry(entry: &Value) -> Option<PublicServer> {
+    let (name, address) = match entry {
+        Value::Array(pair) => (pair.first()?.as_str()?, pair.get(1)?.as_str()?),
+        Value::Object(object) => (
+            object.get("name")?.as_str()?,
+            object.get("address")?.as_str()?,
+        ),
+        _ => return None,
+    };
+    Some(PublicServer {
+        name: name.to_string(),
+        address: address.trim().to_string(),
+    })
+}
+
+fn parse_python_pairs(literal: &str) -> Vec<PublicServer> {
+    // The site escapes quotes as HTML entities
+    let literal = literal
+        .replace("&#8221;", "'")
+        .replace("&#8217;", "'")
+        .replace("&#8242;", "'");
+    static PAIR: OnceLock<Regex> = OnceLock::new();
+    let pair = PAIR.get_or_init(|| {
+        Regex::new(r#"[(\[]\s*['"]([^'"]+)['"]\s*,\s*['"]([^'"]+)['"]\s*[)\]]"#)
+            .expect("valid server pair regex")
+    });
+    pair.captures_iter(&literal)
+        .map(|captures| PublicServer {
+            name: captures[1].to_string(),
+            address: captures[2].trim().to_string(),
+        })
+        .collect()
+}
+
+/// Measure how long a TCP connect to `address` (`host[:port]`) takes
+pub async fn probe_latency(address: &str, timeout: Duration) -> Option<f64> {
+    let (host, port) = parse_server_address(address, DEFAULT_SERVER_PORT).ok()?;
+    let started = Instant::now();
+    match tokio::time::timeout(timeout, happy_eyeballs::connect(&host, port)).await {
+        Ok(Ok(_)) => Some(started.elapsed().as_secs_f64() * 1000.0),
+        _ => None,
+    }
+}
+
+/// Probe all servers concurrently, fastest first and unreachable ones last
+pub async fn probe_servers(servers: Vec<PublicServer>, timeout: Duration) -> Vec<ProbedServer> {
+    let latencies = join_all(
+        servers
+            .iter()
+            .map(|server| probe_latency(&server.address, timeout)),
+    )
+    .await;
+    let mut probed: Vec<ProbedServer> = servers
+        .into_iter()
+        .zip(latencies)
+        .map(|(server, latency_ms)| ProbedServer {
+            name: server.name,
+            address: server.address,
+            latency_ms,
+        })
+        .collect();
+    probed.sort_by(|a, b| match (a.latency_ms, b.latency_ms) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+    probed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_parse_official_server_list() {
+        let body = r#"{"version": "1.7.4", "public-servers": "[(&#8217;syncplay.pl:8995 (France)&#8217;, &#8217;syncplay.pl:8995&#8217;), ('syncplay.pl:8996 (France)', 'syncplay.pl:8996')]"}"#;
+        let servers = parse_server_list(body).unwrap();
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0].name, "syncplay.pl:8995 (France)");
+        assert_eq!(servers[1].address, "syncplay.pl:8996");
+
+        let servers =
+            parse_server_list(r#"[["a", "a.example:8999"], {"name": "b", "address": "b:1"}]"#)
+                .unwrap();
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[1].address, "b:1");
+
+        assert!(parse_server_list(r#"{"public-servers": "[]"}"#).is_err());
+        assert!(parse_server_list("<html>").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_and_probe_servers() {
+        let reachable = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let reachable_address = reachable.local_addr().unwrap().to_string();
+        let closed_address = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        tokio::spawn(async move { while let Ok((_stream, _)) = reachable.accept().await {} });
+
+        let body = serde_json::json!({
+            "public-servers": [["closed", closed_address], ["open", reachable_address]],
+        })
+        .to_string();
+        let http = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/checkforupdate", http.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, _) = http.accept().await.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request).await.unwrap();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let servers = fetch_public_servers(&url).await.unwrap();
+        assert_eq!(servers.len(), 2);
+        let probed = probe_servers(servers, Duration::from_secs(2)).await;
+        assert_eq!(probed[0].name, "open");
+        assert!(probed[0].latency_ms.is_some());
+        assert_eq!(probed[1].name, "closed");
+        assert_eq!(probed[1].latency_ms, None);
+    }
+}
@@ -0,0 +1,3806 @@
+// Syncplay session logic, independent of the Tauri runtime
+
+use crate::app_state::{AppState, ConnectionStatusEvent, SessionSnapshot};
+use crate::client::capabilities::ServerCapabilities;
+use crate::client::chat::ChatCommand;
+use crate::client::events::ClientEventSink;
+use crate::client::host::HeadlessHost;
+use crate::client::room_directory::{room_directory, RoomSummary};
+use crate::client::server_error::{ServerError, ServerErrorKind};
+use crate::config::{ProxyType, QueueOverflowPolicy, ServerConfig, SyncplayConfig, TlsPolicy};
+use crate::network::connection::{
+    BackpressureConfig, ConnectTimeouts, Connection, ConnectionState, ConnectionTransition,
+    KeepaliveConfig, OverflowPolicy, TransitionReason,
+};
+use crate::network::messages::{
+    ChatMessage, ClientFeatures, ControllerAuth, HelloMessage, IgnoringInfo, NewControlledRoom,
+    PingInfo, PlayState, PlaylistChange, PlaylistIndexUpdate, ProtocolMessage, ReadyState,
+    RoomInfo, SetMessage, StateMessage, TLSMessage, UserUpdate,
+};
+use crate::network::proxy::{Proxy, ProxyKind};
+use crate::network::reconnect::Backoff;
+use crate::network::recorder::TrafficRecorder;
+use crate::network::tls::{create_tls_connector, TlsTrust};
+use crate::player::controller::{
+    ensure_player_connected, load_media_by_name, load_placeholder_if_empty, send_file_update,
+    stop_player,
+};
+use crate::player::properties::PlayerState;
+use crate::server::room::generate_room_password;
+use crate::utils::{
+    format_server_address, is_controlled_room, normalize_host, parse_controlled_room_input,
+    same_filename, strip_control_password, truncate_text,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::time::{interval, sleep, Duration};
+
+const AUTOPLAY_DELAY_SECONDS: i32 = 3;
+const DIFFERENT_DURATION_THRESHOLD: f64 = 2.5;
+const WARNING_OSD_INTERVAL_SECONDS: u64 = 1;
+const OSD_MESSAGE_SEPARATOR: &str = "; ";
+const RECONNECT_INITIAL_DELAY_SECONDS: u64 = 1;
+const RECONNECT_MAX_DELAY_SECONDS: u64 = 30;
+
+struct ConnectionSnapshot<'a> {
+    host: &'a str,
+    port: u16,
+    username: &'a str,
+    room: &'a str,
+    password: Option<&'a str>,
+}
+
+/// Headless Syncplay client driving the session stored in an `AppState`
+#[derive(Clone)]
+pub struct SyncplayClient {
+    state: Arc<AppState>,
+}
+
+impl SyncplayClient {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self { state }
+    }
+
+    /// Client with its own state that reports events to `sink` and persists nothing
+    pub fn headless(config: SyncplayConfig, sink: Arc<dyn ClientEventSink>) -> Self {
+        let state = AppState::new();
+        state.sync_engine.lock().update_from_config(&config.user);
+        *state.config.lock() = config;
+        state.set_event_sink(sink);
+        state.set_host(Arc::new(HeadlessHost));
+        Self::new(state)
+    }
+
+    pub fn state(&self) -> &Arc<AppState> {
+        &self.state
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state.is_connected()
+    }
+
+    /// Send a protocol message on the current connection
+    pub fn send(&self, message: ProtocolMessage) -> Result<(), String> {
+        let Some(connection) = self.state.connection.lock().clone() else {
+            return Err("Not connected to server".to_string());
+        };
+        connection
+            .send(message)
+            .map_err(|e| format!("Failed to send message: {}", e))
+    }
+
+    /// Send a chat line, running it as a slash command if it starts with `/`
+    pub async fn send_chat(&self, message: String) -> Result<(), String> {
+        let state = &self.state;
+        let trimmed = message.trim();
+        if trimmed.is_empty() {
+            return Ok(());
+        }
+
+        let config = state.config.lock().clone();
+        if !config.user.chat_input_enabled {
+            return Err("Chat input is disabled".to_string());
+        }
+
+        let message = trimmed.to_string();
+
+        // Check if connected
+        if !state.is_connected() {
+            return Err("Not connected to server".to_string());
+        }
+        let capabilities = state.client_state.get_server_capabilities();
+
+        // Check if it's a command
+        if let Some(command) = ChatCommand::parse(&message) {
+            match command {
+                ChatCommand::Room(room) => {
+                    tracing::info!("Command: Change room to {}", room);
+                    let (normalized_room, control_password) = parse_controlled_room_input(&room);
+                    let room = normalized_room;
+                    if let Some(password) = control_password {
+                        store_control_password(state, &room, &password, true);
+                    }
+                    state.client_state.set_room(room);
+                    let set_msg = ProtocolMessage::Set {
+                        Set: Box::new(SetMessage {
+                            room: Some(RoomInfo {
+                                name: state.client_state.get_room(),
+                                password: None,
+                            }),
+                            file: None,
+                            user: None,
+                            ready: None,
+                            playlist_index: None,
+                            playlist_change: None,
+                            controller_auth: None,
+                            new_controlled_room: None,
+                            features: None,
+                        }),
+                    };
+                    self.send(set_msg)?;
+                    self.send(ProtocolMessage::List { List: None })?;
+                    reidentify_as_controller(state);
+                }
+                ChatCommand::List => {
+                    tracing::info!("Command: List users");
+                    let users = state.client_state.get_users();
+                    let user_list: Vec<String> = users
+                        .iter()
+                        .map(|u| format!("{} ({})", u.username, u.room))
+                        .collect();
+                    let message = format!("Users: {}", user_list.join(", "));
+                    state.chat.add_system_message(message.clone());
+                    state.emit_event(
+                        "chat-message-received",
+                        serde_json::json!({
+                            "timestamp": chrono::Utc::now().to_rfc3339(),
+                            "username": null,
+                            "message": message,
+                            "messageType": "system",
+                        }),
+                    );
+                }
+                ChatCommand::Help => {
+                    tracing::info!("Command: Show help");
+                    let help = ChatCommand::help_text();
+                    state.chat.add_system_message(help.clone());
+                    state.emit_event(
+                        "chat-message-received",
+                        serde_json::json!({
+                            "timestamp": chrono::Utc::now().to_rfc3339(),
+                            "username": null,
+                            "message": help,
+                            "messageType": "system",
+                        }),
+                    );
+                }
+                ChatCommand::Auth(password) => {
+                    tracing::info!("Command: Authenticate as room operator");
+                    self.authenticate_operator(&password)?;
+                }
+                ChatCommand::Create(room) => {
+                    tracing::info!("Command: Create managed room {}", room);
+                    self.create_managed_room(&room)?;
+                }
+                ChatCommand::Ready | ChatCommand::Unready if !capabilities.readiness => {
+                    return Err("The server does not support readiness".to_string());
+                }
+                ChatCommand::Ready => {
+                    tracing::info!("Command: Set ready");
+                    state.client_state.set_ready(true);
+                    let username = state.client_state.get_username();
+                    let set_msg = ProtocolMessage::Set {
+                        Set: Box::new(SetMessage {
+                            room: None,
+                            file: None,
+                            user: None,
+                            ready: Some(ReadyState {
+                                username: Some(username),
+                                is_ready: Some(true),
+                                manually_initiated: Some(true),
+                                set_by: None,
+                            }),
+                            playlist_index: None,
+                            playlist_change: None,
+                            controller_auth: None,
+                            new_controlled_room: None,
+                            features: None,
+                        }),
+                    };
+                    self.send(set_msg)?;
+                }
+                ChatCommand::Unready => {
+                    tracing::info!("Command: Set unready");
+                    state.client_state.set_ready(false);
+                    let username = state.client_state.get_username();
+                    let set_msg = ProtocolMessage::Set {
+                        Set: Box::new(SetMessage {
+                            room: None,
+                            file: None,
+                            user: None,
+                            ready: Some(ReadyState {
+                                username: Some(username),
+                                is_ready: Some(false),
+                                manually_initiated: Some(true),
+                                set_by: None,
+                            }),
+                            playlist_index: None,
+                            playlist_change: None,
+                            controller_auth: None,
+                            new_controlled_room: None,
+                            features: None,
+                        }),
+                    };
+                    self.send(set_msg)?;
+                }
+                ChatCommand::Unknown(msg) => {
+                    tracing::warn!("Unknown command: {}", msg);
+                    state.chat.add_error_message(msg.clone());
+                    state.emit_event(
+                        "chat-message-received",
+                        serde_json::json!({
+                            "timestamp": chrono::Utc::now().to_rfc3339(),
+                            "username": null,
+                            "message": msg,
+                            "messageType": "error",
+                        }),
+                    );
+                    return Err(msg);
+                }
+            }
+            Ok(())
+        } else {
+            // Regular chat message
+            if !capabilities.chat {
+                return Err("The server does not support chat".to_string());
+            }
+            let message = truncate_text(&message, capabilities.max_chat_message_length);
+            tracing::info!("Sending chat message: {}", message);
+            let chat_msg = ProtocolMessage::Chat {
+                Chat: ChatMessage::Text(message.clone()),
+            };
+            self.send(chat_msg)?;
+            Ok(())
+        }
+    }
+
+    pub async fn connect(
+        &self,
+        host: String,
+        port: u16,
+        username: String,
+        room: String,
+        password: Option<String>,
+    ) -> Result<(), String> {
+        let state = &self.state;
+        let host = normalize_host(&host);
+        let address = format_server_address(&host, port);
+        tracing::info!("Connecting to {} as {} in room {}", address, username, room);
+        emit_system_message(state, &format!("Attempting to connect to {}", address));
+
+        // Check if already connected
+        if state.is_connected() {
+            return Err("Already connected to a server".to_string());
+        }
+        if connection_attempt_in_progress(state) {
+            return Err("A connection attempt is already in progress".to_string());
+        }
+
+        let (normalized_room, control_password) = parse_controlled_room_input(&room);
+        let room = normalized_room;
+        if let Some(password) = control_password {
+            store_control_password(state, &room, &password, true);
+        }
+
+        cancel_reconnect(state);
+
+        let config = state.config.lock().clone();
+        let hello_payload = HelloMessage {
+            username: username.clone(),
+            password: password.clone(),
+            room: Some(RoomInfo {
+                name: room.clone(),
+                password: None,
+            }),
+            version: "1.2.255".to_string(),
+            realversion: "1.7.4".to_string(),
+            features: Some(advertised_features(&config)),
+            motd: None,
+        };
+        *state.last_hello.lock() = Some(hello_payload);
+
+        open_session(state, &host, port).await?;
+
+        // Update client state
+        state.client_state.set_username(username.clone());
+        state.client_state.set_room(room.clone());
+        state.sync_engine.lock().update_from_config(&config.user);
+        update_autoplay_state(state, &config);
+        maybe_autosave_connection(
+            state,
+            &config,
+            ConnectionSnapshot {
+                host: &host,
+                port,
+                username: &username,
+                room: &room,
+                password: password.as_deref(),
+            },
+        );
+
+        // Sessions opened alongside the active one only follow chat and presence
+        if state.is_playback_session() {
+            if let Err(e) = ensure_player_connected(state).await {
+                tracing::warn!("Failed to connect to player: {}", e);
+            } else if let Err(e) = load_placeholder_if_empty(state).await {
+                tracing::warn!("Failed to load placeholder: {}", e);
+            }
+        }
+        start_room_warning_loop(state.clone());
+
+        // Emit connection status event
+        state.emit_event(
+            "connection-status-changed",
+            ConnectionStatusEvent {
+                connected: true,
+                server: Some(format_server_address(&host, port)),
+                reconnecting: false,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub async fn disconnect(&self) {
+        let state = &self.state;
+        tracing::info!("Disconnecting from server");
+        cancel_reconnect(state);
+
+        // Disconnect
+        if let Some(connection) = state.connection.lock().take() {
+            connection.disconnect();
+        }
+
+        if let Err(e) = stop_player(state).await {
+            tracing::warn!("Failed to stop player: {}", e);
+        }
+
+        state.client_state.clear_users();
+        apply_server_capabilities(state, ServerCapabilities::default());
+        state.playlist.clear();
+        state.client_state.set_file(None);
+        state.client_state.set_ready(false);
+        {
+            let mut autoplay = state.autoplay.lock();
+            autoplay.countdown_active = false;
+            autoplay.countdown_remaining = 0;
+        }
+        *state.room_warning_state.lock() = crate::app_state::RoomWarningState::default();
+        *state.room_warning_task_running.lock() = false;
+        state.emit_event("user-list-updated", serde_json::json!({ "users": [] }));
+        state.emit_event(
+            "playlist-updated",
+            crate::app_state::PlaylistEvent {
+                items: Vec::new(),
+                current_index: None,
+            },
+        );
+
+        // Emit connection status event
+        state.emit_event(
+            "connection-status-changed",
+            ConnectionStatusEvent {
+                connected: false,
+                server: None,
+                reconnecting: false,
+            },
+        );
+        state.emit_event(
+            "tls-status-changed",
+            serde_json::json!({ "status": "unknown" }),
+        );
+    }
+
+    /// Abort a connection that is still dialing, handshaking or reconnecting
+    pub async fn cancel_connection(&self) -> Result<(), String> {
+        let state = &self.state;
+        let reconnecting = state.reconnect.lock().active;
+        if !connection_attempt_in_progress(state) && !reconnecting {
+            return Err("No connection attempt in progress".to_string());
+        }
+        tracing::info!("Cancelling connection attempt");
+        self.disconnect().await;
+        emit_system_message(state, "Connection attempt cancelled");
+        Ok(())
+    }
+
+    /// Per-room occupancy from the latest server user list
+    pub fn room_directory(&self) -> Vec<RoomSummary> {
+        room_directory(&self.state.client_state.get_users())
+    }
+
+    /// Rooms with a saved operator password, sorted by name
+    pub fn operator_rooms(&self) -> Vec<String> {
+        let mut rooms: Vec<String> = self
+            .state
+            .controlled_room_passwords
+            .lock()
+            .keys()
+            .cloned()
+            .collect();
+        rooms.sort();
+        rooms
+    }
+
+    /// Drop the saved operator password for `room`
+    pub fn forget_operator_password(&self, room: &str) -> Result<(), String> {
+        if self
+            .state
+            .controlled_room_passwords
+            .lock()
+            .remove(room)
+            .is_none()
+        {
+            return Err(format!("No operator password saved for {}", room));
+        }
+        if !save_room_passwords(&self.state) {
+            return Err("Failed to update the password vault".to_string());
+        }
+        Ok(())
+    }
+
+    /// Saved operator credentials as `room:password` join strings
+    pub fn export_operator_passwords(&self) -> Vec<String> {
+        let mut entries: Vec<String> = self
+            .state
+            .controlled_room_passwords
+            .lock()
+            .iter()
+            .map(|(room, password)| format!("{}:{}", room, password))
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    /// Authenticate as operator of the current managed room
+    pub fn authenticate_operator(&self, password: &str) -> Result<(), String> {
+        let state = &self.state;
+        if !state.is_connected() {
+            return Err("Not connected to server".to_string());
+        }
+        if !state.client_state.get_server_capabilities().managed_rooms {
+            return Err("The server does not support managed rooms".to_string());
+        }
+        let room = state.client_state.get_room();
+        if !is_controlled_room(&room) {
+            return Err(format!("{} is not a managed room", room));
+        }
+        let password = strip_control_password(password);
+        if password.is_empty() {
+            return Err("Operator password cannot be empty".to_string());
+        }
+        emit_system_message(state, "Identifying as room operator...");
+        *state.last_control_password_attempt.lock() = Some(password.clone());
+        send_controller_auth(state, &room, &password)
+    }
+
+    /// Ask the server to create a managed room named after `room`.
+    ///
+    /// The server answers with `newControlledRoom`, which moves us into the
+    /// room and authenticates us as its operator.
+    pub fn create_managed_room(&self, room: &str) -> Result<(), String> {
+        let state = &self.state;
+        if !state.is_connected() {
+            return Err("Not connected to server".to_string());
+        }
+        if !state.client_state.get_server_capabilities().managed_rooms {
+            return Err("The server does not support managed rooms".to_string());
+        }
+        let room = room.trim().trim_start_matches('+').trim();
+        if room.is_empty() {
+            return Err("Room name cannot be empty".to_string());
+        }
+        let room = match state
+            .client_state
+            .get_server_capabilities()
+            .max_room_name_length
+        {
+            Some(max_length) => truncate_text(room, max_length),
+            None => room.to_string(),
+        };
+
+        let password = generate_room_password();
+        tracing::info!("Requesting managed room {}", room);
+        emit_system_message(
+            state,
+            &format!("Attempting to create managed room '{}'...", room),
+        );
+        *state.last_control_password_attempt.lock() = Some(password.clone());
+        send_controller_auth(state, &room, &password)
+    }
+}
+
+/// Features this client advertises in its Hello
+pub(crate) fn advertised_features(config: &SyncplayConfig) -> ClientFeatures {
+    ClientFeatures {
+        shared_playlists: Some(config.user.shared_playlist_enabled),
+        chat: Some(true),
+        ready_state: Some(true),
+        managed_rooms: Some(true),
+        // Room persistence is not supported yet
+        persistent_rooms: Some(false),
+        extra: Default::default(),
+    }
+}
+
+/// Store the server capabilities and tell the UI which features are available
+fn apply_server_capabilities(state: &Arc<AppState>, capabilities: ServerCapabilities) {
+    state
+        .client_state
+        .set_server_capabilities(capabilities.clone());
+    state.emit_event("server-capabilities-changed", capabilities);
+}
+
+/// Open a connection, start the TLS/Hello handshake with `last_hello` and
+/// spawn the message loop.
+async fn open_session(state: &Arc<AppState>, host: &str, port: u16) -> Result<(), String> {
+    let (
+        keepalive,
+        backpressure,
+        timeouts,
+        hello_timeout,
+        directory_refresh,
+        trust,
+        policy,
+        proxy,
+        recording_dir,
+    ) = {
+        let config = state.config.lock();
+        let keepalive = KeepaliveConfig::new(
+            Duration::from_secs(config.user.keepalive_idle_timeout_seconds),
+            Duration::from_secs(config.user.keepalive_grace_period_seconds),
+        );
+        let backpressure = BackpressureConfig::new(
+            config.user.max_frame_length_bytes,
+            config.user.message_queue_capacity,
+            match config.user.queue_overflow_policy {
+                QueueOverflowPolicy::Block => OverflowPolicy::Block,
+                QueueOverflowPolicy::CoalesceState => OverflowPolicy::CoalesceState,
+                QueueOverflowPolicy::Disconnect => OverflowPolicy::Disconnect,
+            },
+        );
+        let policy = config
+            .server_for(host, port)
+            .map(|server| server.tls_policy)
+            .unwrap_or_default();
+        (
+            keepalive,
+            backpressure,
+            connect_timeouts(&config),
+            Duration::from_secs(config.user.hello_timeout_seconds),
+            Duration::from_secs(config.user.room_directory_refresh_seconds),
+            tls_trust_for(&config, host, port),
+            policy,
+            proxy_for(&config, host, port),
+            config
+                .user
+                .protocol_recording_dir
+                .clone()
+                .filter(|dir| !dir.trim().is_empty()),
+        )
+    };
+    // Misconfigured trust must not silently fall back to plaintext
+    if policy == TlsPolicy::Required || trust != TlsTrust::default() {
+        if let Err(e) = create_tls_connector(&trust) {
+            return Err(format!(
+                "Invalid TLS settings for {}:{}: {:#}",
+                host, port, e
+            ));
+        }
+    }
+    *state.tls_policy.lock() = policy;
+    let recorder = recording_dir.and_then(|dir| start_recording(state, &dir, host));
+    let connection = Arc::new(
+        Connection::new()
+            .with_keepalive(keepalive)
+            .with_backpressure(backpressure)
+            .with_tls_trust(trust.clone())
+            .with_proxy(proxy)
+            .with_recorder(recorder)
+            .with_timeouts(timeouts)
+            .with_reconnect_attempt(state.reconnect.lock().active),
+    );
+    // Registered before dialing so `cancel_connection` can abort the attempt
+    *state.connection.lock() = Some(connection.clone());
+    spawn_connection_state_forwarder(state, &connection);
+    let mut receiver = match connection.connect(host.to_string(), port).await {
+        Ok(receiver) => receiver,
+        Err(e) => {
+            tracing::error!("Failed to connect: {:#}", e);
+            let mut current = state.connection.lock();
+            if current
+                .as_ref()
+                .is_some_and(|active| Arc::ptr_eq(active, &connection))
+            {
+                *current = None;
+            }
+            return Err(format!("Connection failed: {:#}", e));
+        }
+    };
+    tracing::info!("Successfully connected to server");
+
+    *state.hello_sent.lock() = false;
+    spawn_hello_watchdog(state, &connection, hello_timeout);
+    spawn_room_directory_refresh(&connection, directory_refresh);
+
+    if policy == TlsPolicy::Disabled {
+        tracing::info!("TLS disabled for this server, sending Hello");
+        emit_system_message(state, &format!("Successfully reached {}", host));
+        emit_system_message(
+            state,
+            "TLS is disabled for this server; the connection is not encrypted",
+        );
+        state.emit_event(
+            "tls-status-changed",
+            serde_json::json!({
+                "status": "disabled",
+                "passwordInPlaintext": hello_has_password(state),
+            }),
+        );
+        send_hello(state);
+    } else if create_tls_connector(&trust).is_ok() {
+        emit_system_message(state, "Attempting secure connection");
+        emit_system_message(state, &format!("Successfully reached {}", host));
+        let tls_request = ProtocolMessage::TLS {
+            TLS: TLSMessage {
+                start_tls: Some("send".to_string()),
+            },
+        };
+        if let Err(e) = connection.send(tls_request) {
+            tracing::error!("Failed to send TLS request: {}", e);
+            downgrade_to_plaintext(state, &connection, "Failed to request TLS");
+        } else {
+            tracing::info!("Sent TLS request");
+            state.emit_event(
+                "tls-status-changed",
+                serde_json::json!({ "status": "pending" }),
+            );
+        }
+    } else {
+        tracing::info!("TLS not supported by client");
+        emit_system_message(state, &format!("Successfully reached {}", host));
+        downgrade_to_plaintext(state, &connection, "TLS is not available on this system");
+    }
+
+    // Spawn message processing task
+    let state_clone = state.clone();
+    tokio::spawn(async move {
+        while let Some(message) = receiver.recv().await {
+            tracing::debug!("Received message: {:?}", message);
+            handle_server_message(message, &state_clone).await;
+        }
+        tracing::info!("Message processing loop ended");
+        handle_connection_closed(&state_clone, &connection).await;
+    });
+
+    Ok(())
+}
+
+/// Drop the connection if the server has not answered our Hello in time
+fn spawn_hello_watchdog(state: &Arc<AppState>, connection: &Arc<Connection>, timeout: Duration) {
+    let mut states = connection.subscribe_transitions();
+    let state = state.clone();
+    let connection = connection.clone();
+    tokio::spawn(async move {
+        let answered = tokio::time::timeout(
+            timeout,
+            states.wait_for(|current| {
+                matches!(
+                    current.state,
+                    ConnectionState::Authenticated | ConnectionState::Disconnected
+                )
+            }),
+        )
+        .await
+        .is_ok();
+        let is_current = state
+            .connection
+            .lock()
+            .as_ref()
+            .is_some_and(|active| Arc::ptr_eq(active, &connection));
+        if answered || !is_current {
+            return;
+        }
+        tracing::warn!("No Hello from the server within {}s", timeout.as_secs());
+        let message = format!(
+            "The server did not complete the handshake within {}s",
+            timeout.as_secs()
+        );
+        emit_error_message(&state, &message);
+        connection.close(
+            ConnectionTransition::new(ConnectionState::Disconnected, TransitionReason::Timeout)
+                .with_detail(message),
+        );
+    });
+}
+
+/// Re-request the user list so the room directory stays current
+fn spawn_room_directory_refresh(connection: &Arc<Connection>, period: Duration) {
+    if period.is_zero() {
+        return;
+    }
+    let connection = connection.clone();
+    tokio::spawn(async move {
+        let mut ticker = interval(period);
+        // The first tick fires at once; the Hello handler already asks for the list
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            match connection.state() {
+                ConnectionState::Disconnected => return,
+                ConnectionState::Authenticated => {}
+                _ => continue,
+            }
+            if let Err(e) = connection.send(ProtocolMessage::List { List: None }) {
+                tracing::debug!("Failed to refresh the room directory: {}", e);
+            }
+        }
+    });
+}
+
+fn spawn_connection_state_forwarder(state: &Arc<AppState>, connection: &Arc<Connection>) {
+    let mut states = connection.subscribe_transitions();
+    let state = state.clone();
+    tokio::spawn(async move {
+        while states.changed().await.is_ok() {
+            let current = states.borrow_and_update().clone();
+            if current.state == ConnectionState::Stale {
+                emit_system_message(&state, "Server is not responding, checking connection");
+            }
+            state.emit_event("connection-state-changed", current);
+        }
+    });
+}
+
+pub(crate) async fn handle_server_message(message: ProtocolMessage, state: &Arc<AppState>) {
+    match message {
+        ProtocolMessage::Hello { Hello } => {
+            tracing::info!("Received hello message: {:?}", Hello);
+            let capabilities =
+                ServerCapabilities::from_hello(&Hello.realversion, Hello.features.as_ref());
+            state.client_state.set_server_version(Hello.realversion);
+            apply_server_capabilities(state, capabilities.clone());
+            let requested = state.client_state.get_username();
+            if !requested.is_empty() && Hello.username != requested {
+                emit_system_message(
+                    state,
+                    &format!(
+                        "The server renamed you from {} to {}",
+                        requested, Hello.username
+                    ),
+                );
+                state.client_state.set_username(Hello.username.clone());
+            }
+            emit_system_message(state, &format!("Hello {},", Hello.username));
+            if let Some(motd) = Hello.motd {
+                state.emit_event(
+                    "chat-message-received",
+                    serde_json::json!({
+                        "timestamp": chrono::Utc::now().to_rfc3339(),
+                        "username": null,
+                        "message": motd,
+                        "messageType": "system",
+                    }),
+                );
+            }
+            emit_system_message(state, "Successfully connected to server");
+            for (supported, feature) in [
+                (capabilities.chat, "chat"),
+                (capabilities.shared_playlists, "shared playlists"),
+                (capabilities.readiness, "readiness"),
+            ] {
+                if !supported {
+                    emit_system_message(state, &format!("The server does not support {}", feature));
+                }
+            }
+            if let Some(connection) = state.connection.lock().clone() {
+                connection.set_authenticated();
+                if let Err(e) = connection.send(ProtocolMessage::List { List: None }) {
+                    tracing::warn!("Failed to request user list: {}", e);
+                }
+            }
+            reidentify_as_controller(state);
+        }
+        ProtocolMessage::List { List } => {
+            tracing::info!("Received user list: {:?}", List);
+            if let Some(users_by_room) = List {
+                // The list does not carry join times or activity, keep what we saw
+                let previous: HashMap<String, crate::client::state::User> = state
+                    .client_state
+                    .get_users()
+                    .into_iter()
+                    .map(|user| (user.username.clone(), user))
+                    .collect();
+                state.client_state.clear_users();
+                for (room_name, room_users) in users_by_room {
+                    for (username, user_info) in room_users {
+                        let mut user = crate::client::state::User::new(username, room_name.clone());
+                        if let Some(file) = user_info.file {
+                            user.file = file.name;
+                            user.file_size = file.size;
+                            user.file_duration = file.duration;
+                        }
+                        user.is_ready = user_info.is_ready.unwrap_or(false);
+                        user.is_controller = user_info.controller.unwrap_or(false);
+                        user.features = user_info
+                            .features
+                            .and_then(|features| serde_json::to_value(features).ok());
+                        if let Some(old) = previous.get(&user.username) {
+                            user.version = old.version.clone();
+                            user.joined_at = old.joined_at;
+                            user.last_activity = old.last_activity;
+                            user.sync_offset = old.sync_offset;
+                        }
+                        if user.version.is_none() {
+                            user.version = advertised_version(None, user.features.as_ref());
+                        }
+                        state.client_state.add_user(user);
+                    }
+                }
+                emit_user_list(state);
+                state.emit_event(
+                    "room-directory-updated",
+                    serde_json::json!({ "rooms": room_directory(&state.client_state.get_users()) }),
+                );
+                restore_session(state);
+                evaluate_autoplay(state);
+                update_room_warnings(state, false);
+            }
+        }
+        ProtocolMessage::Chat { Chat } => {
+            tracing::info!("Received chat message: {:?}", Chat);
+            let config = state.config.lock().clone();
+            if !config.user.chat_output_enabled {
+                return;
+            }
+            // Transform chat message to match frontend format
+            let (username, message) = match Chat {
+                crate::network::messages::ChatMessage::Entry { username, message } => {
+                    (Some(username), message)
+                }
+                crate::network::messages::ChatMessage::Text(message) => (None, message),
+            };
+            if let Some(username) = username.as_deref() {
+                if state.client_state.touch_user(username) {
+                    emit_user_list(state);
+                }
+            }
+            let chat_msg = serde_json::json!({
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "username": username,
+                "message": message,
+                "messageType": "normal",
+            });
+            state.emit_event("chat-message-received", chat_msg);
+        }
+        ProtocolMessage::State { State: state_msg } => {
+            tracing::info!("Received state update: {:?}", state_msg);
+            let mut message_age = 0.0;
+            if let Some(ignore) = state_msg.ignoring_on_the_fly.as_ref() {
+                update_ignoring_on_the_fly(state, ignore);
+            }
+            if let Some(ping) = state_msg.ping.as_ref() {
+                if let (Some(client_latency), Some(server_rtt)) =
+                    (ping.client_latency_calculation, ping.server_rtt)
+                {
+                    state
+                        .ping_service
+                        .lock()
+                        .receive_message(client_latency, server_rtt);
+                    message_age = state.ping_service.lock().get_last_forward_delay();
+                    let rtt_ms = state.ping_service.lock().get_rtt() * 1000.0;
+                    state.emit_event("ping-updated", serde_json::json!({ "rttMs": rtt_ms }));
+                }
+                *state.last_latency_calculation.lock() = ping.latency_calculation;
+            }
+            if let Some(playstate) = state_msg.playstate {
+                if restore_pending(state) {
+                    tracing::debug!("Ignoring server playstate until session is restored");
+                } else {
+                    handle_state_update(state, playstate, message_age).await;
+                }
+            }
+            let latency_calculation = state_msg
+                .ping
+                .as_ref()
+                .and_then(|ping| ping.latency_calculation);
+            if let Err(e) = send_state_message(
+                state,
+                build_local_playstate(state),
+                latency_calculation,
+                false,
+            ) {
+                tracing::warn!("Failed to send state response: {}", e);
+            }
+        }
+        ProtocolMessage::Error { Error } => {
+            tracing::error!("Received error from server: {:?}", Error);
+            handle_server_error(state, ServerError::classify(&Error.message)).await;
+        }
+        ProtocolMessage::Set { Set } => {
+            tracing::info!("Received set message: {:?}", Set);
+            handle_set_message(state, *Set).await;
+        }
+        ProtocolMessage::TLS { TLS } => {
+            tracing::info!("Received TLS message: {:?}", TLS);
+            handle_tls_message(state, TLS).await;
+        }
+        ProtocolMessage::Unknown(name, payload) => {
+            tracing::warn!("Ignoring unknown server message {}: {}", name, payload);
+            state.emit_event(
+                "protocol-diagnostic",
+                serde_json::json!({
+                    "kind": "unknown-message",
+                    "message": name,
+                    "payload": payload,
+                }),
+            );
+        }
+    }
+}
+
+async fn handle_state_update(state: &Arc<AppState>, playstate: PlayState, message_age: f64) {
+    *state.last_global_update.lock() = Some(std::time::Instant::now());
+    let adjusted_global_position = if !playstate.paused {
+        playstate.position + message_age
+    } else {
+        playstate.position
+    };
+    let previous_global = state.client_state.get_global_state();
+    state.client_state.set_global_state(
+        adjusted_global_position,
+        playstate.paused,
+        playstate.set_by.clone(),
+    );
+    let mut users_changed = false;
+    let acted = playstate.do_seek.unwrap_or(false) || playstate.paused != previous_global.paused;
+    if acted {
+        if let Some(actor) = playstate.set_by.as_deref() {
+            users_changed |= state.client_state.touch_user(actor);
+        }
+    }
+    // Offsets are only measured for the slowest viewer, drop the old one's
+    if playstate.set_by != previous_global.set_by {
+        users_changed |= state
+            .client_state
+            .clear_sync_offsets_except(playstate.set_by.as_deref());
+    }
+    if users_changed {
+        emit_user_list(state);
+    }
+
+    let player = state.player.lock().clone();
+    let Some(player) = player else { return };
+    let mut player_state: PlayerState = player.get_state();
+    let (local_position, local_paused) = match (player_state.position, player_state.paused) {
+        (Some(pos), Some(paused)) => (pos, paused),
+        _ => {
+            if let Err(e) = player.poll_state().await {
+                tracing::warn!("Failed to refresh player state: {}", e);
+                return;
+            }
+            player_state = player.get_state();
+            match (player_state.position, player_state.paused) {
+                (Some(pos), Some(paused)) => (pos, paused),
+                _ => return,
+            }
+        }
+    };
+
+    let config = state.config.lock().clone();
+    let current_username = state.client_state.get_username();
+    let actor_name = playstate
+        .set_by
+        .clone()
+        .unwrap_or_else(|| "Unknown".to_string());
+    let do_seek = playstate.do_seek.unwrap_or(false);
+
+    // The server reports the room position of its slowest viewer, so the
+    // difference to our own player estimates how far that viewer is off.
+    // Nobody else is measured, and a paused player says nothing about drift.
+    if !do_seek && !playstate.paused && !local_paused && actor_name != current_username {
+        let offset = adjusted_global_position - local_position;
+        if state.client_state.set_user_sync_offset(&actor_name, offset) {
+            emit_user_list(state);
+        }
+    }
+    let pause_changed =
+        playstate.paused != previous_global.paused || playstate.paused != local_paused;
+
+    if do_seek {
+        if actor_name != current_username {
+            if let Err(e) = player.set_position(adjusted_global_position).await {
+                tracing::warn!("Failed to seek: {}", e);
+            }
+        }
+        let message = format!(
+            "{} jumped from {} to {}",
+            actor_name,
+            format_time(local_position),
+            format_time(adjusted_global_position)
+        );
+        emit_system_message(state, &message);
+        maybe_show_osd(state, &config, &message, config.user.show_same_room_osd);
+    }
+
+    let mut seek_action = None;
+    let mut slowdown_action = false;
+    let mut reset_speed = false;
+    let slowdown_rate = if do_seek {
+        1.0
+    } else {
+        let (actions, slowdown_rate) = {
+            let mut engine = state.sync_engine.lock();
+            let actions = engine.calculate_sync_actions(crate::client::sync::SyncInputs {
+                local_position,
+                local_paused,
+                global_position: playstate.position,
+                global_paused: playstate.paused,
+                message_age,
+                do_seek: false,
+                allow_fastforward: should_allow_fastforward(state, &config),
+            });
+            let slowdown_rate = engine.slowdown_rate();
+            (actions, slowdown_rate)
+        };
+
+        for action in actions {
+            match action {
+                crate::client::sync::SyncAction::Seek(position) => {
+                    seek_action = Some(position);
+                }
+                crate::client::sync::SyncAction::Slowdown => {
+                    slowdown_action = true;
+                }
+                crate::client::sync::SyncAction::ResetSpeed => {
+                    reset_speed = true;
+                }
+                crate::client::sync::SyncAction::SetPaused(_) => {}
+                crate::client::sync::SyncAction::None => {}
+            }
+        }
+
+        slowdown_rate
+    };
+
+    if let Some(position) = seek_action {
+        if actor_name != current_username {
+            if let Err(e) = player.set_position(position).await {
+                tracing::warn!("Failed to seek: {}", e);
+            }
+            let message = if position < local_position {
+                format!("Rewinded due to time difference with {}", actor_name)
+            } else {
+                format!("Fast-forwarded due to time difference with {}", actor_name)
+            };
+            emit_system_message(state, &message);
+            maybe_show_osd(state, &config, &message, config.user.show_same_room_osd);
+        }
+    }
+
+    if slowdown_action {
+        if actor_name != current_username {
+            if let Err(e) = player.set_speed(slowdown_rate).await {
+                tracing::warn!("Failed to set slowdown: {}", e);
+            }
+            let message = format!("Slowing down due to time difference with {}", actor_name);
+            emit_system_message(state, &message);
+            maybe_show_osd(state, &config, &message, config.user.show_slowdown_osd);
+        } else {
+            state.sync_engine.lock().reset_slowdown();
+        }
+    }
+
+    if reset_speed {
+        if let Err(e) = player.set_speed(1.0).await {
+            tracing::warn!("Failed to reset speed: {}", e);
+        }
+        let message = "Reverting speed back to normal".to_string();
+        emit_system_message(state, &message);
+        maybe_show_osd(state, &config, &message, config.user.show_slowdown_osd);
+    }
+
+    if pause_changed {
+        if playstate.paused {
+            if actor_name != current_username {
+                if let Err(e) = player.set_position(adjusted_global_position).await {
+                    tracing::warn!("Failed to sync position on pause: {}", e);
+                }
+            }
+            if let Err(e) = player.set_paused(true).await {
+                tracing::warn!("Failed to set paused: {}", e);
+            }
+            let message = format!(
+                "{} paused at {}",
+                actor_name,
+                format_time(adjusted_global_position)
+            );
+            emit_system_message(state, &message);
+            maybe_show_osd(state, &config, &message, config.user.show_same_room_osd);
+        } else {
+            *state.suppress_unpause_check.lock() = true;
+            if let Err(e) = player.set_paused(false).await {
+                tracing::warn!("Failed to set paused: {}", e);
+            }
+            let message = format!("{} unpaused", actor_name);
+            emit_system_message(state, &message);
+            maybe_show_osd(state, &config, &message, config.user.show_same_room_osd);
+        }
+    }
+
+    evaluate_autoplay(state);
+    update_room_warnings(state, false);
+}
+
+fn update_ignoring_on_the_fly(state: &Arc<AppState>, ignoring: &IgnoringInfo) {
+    let mut local = state.ignoring_on_the_fly.lock();
+    if let Some(server) = ignoring.server {
+        local.server = server;
+        local.client = 0;
+    } else if let Some(client) = ignoring.client {
+        if client == local.client {
+            local.client = 0;
+        }
+    }
+}
+
+fn build_local_playstate(state: &Arc<AppState>) -> Option<PlayState> {
+    // Sessions without the player only answer pings, their position is stale
+    if !state.is_playback_session() || state.last_global_update.lock().is_none() {
+        return None;
+    }
+    let global = state.client_state.get_global_state();
+    let local_state = state.local_playback_state.lock();
+    let (local_position, local_paused) = local_state.current()?;
+    let config = state.config.lock().clone();
+    let position = if config.user.dont_slow_down_with_me {
+        global.position
+    } else {
+        local_position
+    };
+    let do_seek = if local_state.compute_seeked(position, global.position) {
+        Some(true)
+    } else {
+        None
+    };
+    Some(PlayState {
+        position,
+        paused: local_paused,
+        do_seek,
+        set_by: None,
+    })
+}
+
+pub(crate) fn send_state_message(
+    state: &Arc<AppState>,
+    playstate: Option<PlayState>,
+    latency_calculation: Option<f64>,
+    state_change: bool,
+) -> Result<(), String> {
+    let mut ignoring = state.ignoring_on_the_fly.lock();
+    let client_ignore_is_not_set = ignoring.client == 0 || ignoring.server != 0;
+    let playstate = if client_ignore_is_not_set {
+        playstate
+    } else {
+        None
+    };
+    if state_change {
+        ignoring.client = ignoring.client.saturating_add(1);
+    }
+    let ignoring_info = if ignoring.server != 0 || ignoring.client != 0 {
+        Some(IgnoringInfo {
+            server: if ignoring.server != 0 {
+                Some(ignoring.server)
+            } else {
+                None
+            },
+            client: if ignoring.client != 0 {
+                Some(ignoring.client)
+            } else {
+                None
+            },
+        })
+    } else {
+        None
+    };
+    if ignoring.server != 0 {
+        ignoring.server = 0;
+    }
+    drop(ignoring);
+
+    let ping = PingInfo {
+        latency_calculation,
+        client_latency_calculation: Some(crate::network::ping::PingService::new_timestamp()),
+        client_rtt: Some(state.ping_service.lock().get_rtt()),
+        server_rtt: None,
+    };
+    let message = ProtocolMessage::State {
+        State: StateMessage {
+            playstate,
+            ping: Some(ping),
+            ignoring_on_the_fly: ignoring_info,
+        },
+    };
+    let Some(connection) = state.connection.lock().clone() else {
+        return Err("Not connected".to_string());
+    };
+    connection.send(message).map_err(|e| e.to_string())
+}
+
+fn emit_system_message(state: &Arc<AppState>, message: &str) {
+    state.chat.add_system_message(message.to_string());
+    state.emit_event(
+        "chat-message-received",
+        serde_json::json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "username": null,
+            "message": message,
+            "messageType": "system",
+        }),
+    );
+}
+
+fn should_allow_fastforward(state: &Arc<AppState>, config: &crate::config::SyncplayConfig) -> bool {
+    if config.user.dont_slow_down_with_me {
+        return true;
+    }
+    let username = state.client_state.get_username();
+    let can_control = state
+        .client_state
+        .get_user(&username)
+        .map(|user| user.is_controller)
+        .unwrap_or(false);
+    !can_control
+}
+
+fn emit_error_message(state: &Arc<AppState>, message: &str) {
+    state.chat.add_error_message(message.to_string());
+    state.emit_event(
+        "chat-message-received",
+        serde_json::json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "username": null,
+            "message": message,
+            "messageType": "error",
+        }),
+    );
+}
+
+fn maybe_show_osd(
+    state: &Arc<AppState>,
+    config: &crate::config::SyncplayConfig,
+    message: &str,
+    allow: bool,
+) {
+    if !allow || !config.user.show_osd {
+        return;
+    }
+    let player = state.player.lock().clone();
+    let Some(player) = player else { return };
+    if let Err(e) = player.show_osd(message, Some(config.user.osd_duration)) {
+        tracing::warn!("Failed to show OSD: {}", e);
+    }
+}
+
+fn start_room_warning_loop(state: Arc<AppState>) {
+    let mut running = state.room_warning_task_running.lock();
+    if *running {
+        return;
+    }
+    *running = true;
+    drop(running);
+
+    tokio::spawn(async move {
+        let mut ticker = interval(Duration::from_secs(WARNING_OSD_INTERVAL_SECONDS));
+        loop {
+            ticker.tick().await;
+            if !state.is_connected() {
+                *state.room_warning_task_running.lock() = false;
+                break;
+            }
+            update_room_warnings(&state, true);
+        }
+    });
+}
+
+fn update_room_warnings(state: &Arc<AppState>, osd_only: bool) {
+    let config = state.config.lock().clone();
+    let warnings = compute_room_warning_state(state, &config);
+    let mut last = state.room_warning_state.lock();
+
+    if !osd_only && warnings.alone && !last.alone {
+        emit_system_message(state, "You're alone in the room");
+    }
+
+    if config.user.show_osd_warnings {
+        show_room_warning_osd(state, &config, &warnings);
+    }
+
+    *last = warnings;
+}
+
+fn show_room_warning_osd(
+    state: &Arc<AppState>,
+    config: &crate::config::SyncplayConfig,
+    warnings: &crate::app_state::RoomWarningState,
+) {
+    if !config.user.show_osd {
+        return;
+    }
+
+    let message = if warnings.alone {
+        Some("You're alone in the room".to_string())
+    } else {
+        match (&warnings.file_differences, &warnings.not_ready) {
+            (Some(file_diff), Some(not_ready)) => Some(format!(
+                "File differences: {}{}{}",
+                file_diff, OSD_MESSAGE_SEPARATOR, not_ready
+            )),
+            (Some(file_diff), None) => Some(format!("File differences: {}", file_diff)),
+            (None, Some(not_ready)) => Some(not_ready.to_string()),
+            (None, None) => None,
+        }
+    };
+
+    let Some(message) = message else { return };
+    maybe_show_osd(state, config, &message, true);
+}
+
+fn compute_room_warning_state(
+    state: &Arc<AppState>,
+    config: &crate::config::SyncplayConfig,
+) -> crate::app_state::RoomWarningState {
+    let current_room = state.client_state.get_room();
+    let current_username = state.client_state.get_username();
+    let users = state.client_state.get_users();
+    let users_in_room: Vec<crate::client::state::User> = users
+        .into_iter()
+        .filter(|user| user.room == current_room)
+        .collect();
+
+    if users_in_room.is_empty() {
+        return crate::app_state::RoomWarningState::default();
+    }
+
+    let others_in_room: Vec<crate::client::state::User> = users_in_room
+        .iter()
+        .filter(|user| user.username != current_username)
+        .cloned()
+        .collect();
+    let alone = others_in_room.is_empty();
+
+    let current_file = state.client_state.get_file();
+    let current_size = state.client_state.get_file_size();
+    let current_duration = state.client_state.get_file_duration();
+    let mut diff_name = false;
+    let mut diff_size = false;
+    let mut diff_duration = false;
+    if let Some(current_file) = current_file.as_ref() {
+        for user in others_in_room.iter() {
+            let Some(other_file) = user.file.as_ref() else {
+                continue;
+            };
+            if !same_filename(Some(current_file), Some(other_file)) {
+                diff_name = true;
+            }
+            if !crate::utils::same_filesize(current_size.as_ref(), user.file_size.as_ref()) {
+                diff_size = true;
+            }
+            if !same_duration(
+                current_duration,
+                user.file_duration,
+                config.user.show_duration_notification,
+            ) {
+                diff_duration = true;
+            }
+        }
+    }
+
+    let mut diff_parts = Vec::new();
+    if diff_name {
+        diff_parts.push("name");
+    }
+    if diff_size {
+        diff_parts.push("size");
+    }
+    if diff_duration {
+        diff_parts.push("duration");
+    }
+    let file_differences = if diff_parts.is_empty() {
+        None
+    } else {
+        Some(diff_parts.join(", "))
+    };
+
+    let not_ready = if alone {
+        None
+    } else {
+        let not_ready_users: Vec<String> = users_in_room
+            .iter()
+            .filter(|user| !user.is_ready)
+            .map(|user| user.username.clone())
+            .collect();
+        if not_ready_users.is_empty() {
+            None
+        } else {
+            Some(format!("Not ready: {}", not_ready_users.join(", ")))
+        }
+    };
+
+    crate::app_state::RoomWarningState {
+        alone,
+        file_differences,
+        not_ready,
+    }
+}
+
+fn format_time(time_seconds: f64) -> String {
+    let mut seconds = time_seconds.round() as i64;
+    let sign = if seconds < 0 {
+        seconds = -seconds;
+        "-"
+    } else {
+        ""
+    };
+
+    let weeks = seconds / 604_800;
+    let days = (seconds % 604_800) / 86_400;
+    let hours = (seconds % 86_400) / 3_600;
+    let minutes = (seconds % 3_600) / 60;
+    let secs = seconds % 60;
+
+    if weeks > 0 {
+        format!(
+            "{}{}w, {}d, {:02}:{:02}:{:02}",
+            sign, weeks, days, hours, minutes, secs
+        )
+    } else if days > 0 {
+        format!("{}{}d, {:02}:{:02}:{:02}", sign, days, hours, minutes, secs)
+    } else if hours > 0 {
+        format!("{}{:02}:{:02}:{:02}", sign, hours, minutes, secs)
+    } else {
+        format!("{}{:02}:{:02}", sign, minutes, secs)
+    }
+}
+
+pub(crate) fn store_control_password(
+    state: &Arc<AppState>,
+    room: &str,
+    password: &str,
+    persist: bool,
+) {
+    let password = strip_control_password(password);
+    if password.is_empty() {
+        return;
+    }
+    let previous = state
+        .controlled_room_passwords
+        .lock()
+        .insert(room.to_string(), password.clone());
+
+    if !persist {
+        return;
+    }
+    if previous.as_ref() != Some(&password) {
+        save_room_passwords(state);
+    }
+    let config = state.config.lock().clone();
+    if !config.user.autosave_joins_to_list {
+        return;
+    }
+    // The password lives in the vault; the room list only keeps the name
+    if config.user.room_list.iter().any(|entry| entry == room) {
+        return;
+    }
+    let Some(host) = state.host.lock().clone() else {
+        return;
+    };
+    let mut updated = config.clone();
+    updated.user.room_list.push(room.to_string());
+    if let Err(e) = host.save_config(&updated) {
+        tracing::warn!("Failed to save room list after control password: {}", e);
+        return;
+    }
+    *state.config.lock() = updated.clone();
+    state.emit_event("config-updated", updated);
+}
+
+/// Write the operator passwords to the host's password vault
+fn save_room_passwords(state: &Arc<AppState>) -> bool {
+    let Some(host) = state.host.lock().clone() else {
+        return false;
+    };
+    let passwords = state.controlled_room_passwords.lock().clone();
+    match host.save_room_passwords(&passwords) {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::warn!("Failed to save operator passwords: {}", e);
+            false
+        }
+    }
+}
+
+/// Move `room:password` entries of the room list into the vault.
+///
+/// Returns whether `config` changed. Entries are left alone if the vault
+/// cannot be written, so no password is lost.
+pub(crate) fn move_room_passwords_to_vault(
+    state: &Arc<AppState>,
+    config: &mut SyncplayConfig,
+) -> bool {
+    let entries: Vec<(String, Option<String>)> = config
+        .user
+        .room_list
+        .iter()
+        .map(|entry| parse_controlled_room_input(entry))
+        .collect();
+    if entries.iter().all(|(_, password)| password.is_none()) {
+        return false;
+    }
+    {
+        let mut passwords = state.controlled_room_passwords.lock();
+        for (room, password) in &entries {
+            if let Some(password) = password {
+                passwords.insert(room.clone(), password.clone());
+            }
+        }
+    }
+    if !save_room_passwords(state) {
+        return false;
+    }
+    config.user.room_list.clear();
+    for (room, _) in entries {
+        if !config.user.room_list.contains(&room) {
+            config.user.room_list.push(room);
+        }
+    }
+    true
+}
+
+/// Load operator passwords saved by earlier runs
+pub fn load_saved_room_passwords(state: &Arc<AppState>) {
+    let Some(host) = state.host.lock().clone() else {
+        return;
+    };
+    match host.load_room_passwords() {
+        Ok(saved) => state.controlled_room_passwords.lock().extend(saved),
+        Err(e) => {
+            // The vault refuses writes until it can be read again
+            tracing::error!("Failed to load operator passwords: {}", e);
+            emit_error_message(
+                state,
+                &format!(
+                    "Saved operator passwords could not be read and will not be changed: {}",
+                    e
+                ),
+            );
+        }
+    }
+
+    let mut config = state.config.lock().clone();
+    if !move_room_passwords_to_vault(state, &mut config) {
+        return;
+    }
+    tracing::info!("Moved operator passwords from the room list into the vault");
+    if let Err(e) = host.save_config(&config) {
+        tracing::warn!("Failed to save room list without passwords: {}", e);
+    }
+    *state.config.lock() = config;
+}
+
+/// Tell a managed room viewer why their local change was not sent
+pub(crate) fn explain_operator_only(state: &Arc<AppState>, action: &str) {
+    let message = format!(
+        "Only room operators can {} in managed room {}",
+        action,
+        state.client_state.get_room()
+    );
+    emit_error_message(state, &message);
+    let config = state.config.lock().clone();
+    maybe_show_osd(state, &config, &message, true);
+}
+
+pub fn reidentify_as_controller(state: &Arc<AppState>) {
+    let room = state.client_state.get_room();
+    if !is_controlled_room(&room) {
+        return;
+    }
+    if !state.client_state.get_server_capabilities().managed_rooms {
+        emit_error_message(state, "The server does not support managed rooms");
+        return;
+    }
+    let password = state.controlled_room_passwords.lock().get(&room).cloned();
+    let Some(password) = password else {
+        return;
+    };
+    let message = format!(
+        "Identifying as room operator with password '{}'...",
+        password
+    );
+    emit_system_message(state, &message);
+    *state.last_control_password_attempt.lock() = Some(password.clone());
+    if let Err(e) = send_controller_auth(state, &room, &password) {
+        tracing::warn!("Failed to send controller auth: {}", e);
+    }
+}
+
+fn send_controller_auth(state: &Arc<AppState>, room: &str, password: &str) -> Result<(), String> {
+    let connection = state.connection.lock().clone();
+    let Some(connection) = connection else {
+        return Err("Not connected to server".to_string());
+    };
+    let message = ProtocolMessage::Set {
+        Set: Box::new(SetMessage {
+            room: None,
+            file: None,
+            user: None,
+            ready: None,
+            playlist_index: None,
+            playlist_change: None,
+            controller_auth: Some(ControllerAuth {
+                room: Some(room.to_string()),
+                password: Some(password.to_string()),
+                user: None,
+                success: None,
+            }),
+            new_controlled_room: None,
+            features: None,
+        }),
+    };
+    connection
+        .send(message)
+        .map_err(|e| format!("Failed to send controller auth: {}", e))
+}
+
+async fn handle_connection_closed(state: &Arc<AppState>, closed: &Arc<Connection>) {
+    let connection = {
+        let mut current = state.connection.lock();
+        match current.as_ref() {
+            Some(active) if Arc::ptr_eq(active, closed) => current.take(),
+            _ => None,
+        }
+    };
+    let Some(connection) = connection else {
+        return;
+    };
+
+    // A dial of a running reconnect loop failed; the loop owns the retry
+    if state.reconnect.lock().active {
+        return;
+    }
+    let config = state.config.lock().clone();
+    if config.user.auto_reconnect {
+        start_reconnect(state, connection.host(), connection.port());
+        return;
+    }
+
+    teardown_session(state).await;
+    match connection.last_transition().detail {
+        Some(detail) => {
+            emit_system_message(state, &format!("Disconnected from server: {}", detail))
+        }
+        None => emit_system_message(state, "Disconnected from server"),
+    }
+}
+
+async fn teardown_session(state: &Arc<AppState>) {
+    state.client_state.clear_users();
+    apply_server_capabilities(state, ServerCapabilities::default());
+    state.playlist.clear();
+    state.client_state.set_file(None);
+    state.client_state.set_ready(false);
+    {
+        let mut autoplay = state.autoplay.lock();
+        autoplay.countdown_active = false;
+        autoplay.countdown_remaining = 0;
+    }
+
+    if let Err(e) = stop_player(state).await {
+        tracing::warn!("Failed to stop player after disconnect: {}", e);
+    }
+
+    *state.room_warning_state.lock() = crate::app_state::RoomWarningState::default();
+    *state.room_warning_task_running.lock() = false;
+
+    state.emit_event("user-list-updated", serde_json::json!({ "users": [] }));
+    state.emit_event(
+        "playlist-updated",
+        crate::app_state::PlaylistEvent {
+            items: Vec::new(),
+            current_index: None,
+        },
+    );
+    state.emit_event(
+        "connection-status-changed",
+        ConnectionStatusEvent {
+            connected: false,
+            server: None,
+            reconnecting: false,
+        },
+    );
+    state.emit_event(
+        "tls-status-changed",
+        serde_json::json!({ "status": "unknown" }),
+    );
+}
+
+/// Stop any pending reconnect loop and drop the session it would restore
+fn cancel_reconnect(state: &Arc<AppState>) {
+    let mut reconnect = state.reconnect.lock();
+    reconnect.generation = reconnect.generation.wrapping_add(1);
+    reconnect.active = false;
+    reconnect.restore = None;
+}
+
+fn reconnect_is_current(state: &Arc<AppState>, generation: u64) -> bool {
+    let reconnect = state.reconnect.lock();
+    reconnect.active && reconnect.generation == generation
+}
+
+fn snapshot_session(state: &Arc<AppState>) -> SessionSnapshot {
+    let (position, paused) = match state.local_playback_state.lock().current() {
+        Some((position, paused)) => (Some(position), paused),
+        None => (None, true),
+    };
+    SessionSnapshot {
+        room: state.client_state.get_room(),
+        playlist: state
+            .playlist
+            .get_items()
+            .iter()
+            .map(|item| item.filename.clone())
+            .collect(),
+        playlist_index: state.playlist.get_current_index(),
+        position,
+        paused,
+    }
+}
+
+fn start_reconnect(state: &Arc<AppState>, host: String, port: u16) {
+    let snapshot = snapshot_session(state);
+    let generation = {
+        let mut reconnect = state.reconnect.lock();
+        reconnect.generation = reconnect.generation.wrapping_add(1);
+        reconnect.active = true;
+        reconnect.restore = Some(snapshot);
+        reconnect.generation
+    };
+
+    // Keep the player and playlist; only server-side state is stale now
+    state.client_state.clear_users();
+    *state.last_global_update.lock() = None;
+    *state.ignoring_on_the_fly.lock() = crate::app_state::IgnoringOnTheFlyState::default();
+    {
+        let mut autoplay = state.autoplay.lock();
+        autoplay.countdown_active = false;
+        autoplay.countdown_remaining = 0;
+    }
+    emit_user_list(state);
+    state.emit_event(
+        "connection-status-changed",
+        ConnectionStatusEvent {
+            connected: false,
+            server: Some(format_server_address(&host, port)),
+            reconnecting: true,
+        },
+    );
+    emit_system_message(state, "Lost connection to server");
+
+    let max_attempts = match state.config.lock().user.reconnect_max_attempts {
+        0 => None,
+        attempts => Some(attempts),
+    };
+    let state = state.clone();
+    tokio::spawn(async move {
+        let mut backoff = Backoff::new(
+            Duration::from_secs(RECONNECT_INITIAL_DELAY_SECONDS),
+            Duration::from_secs(RECONNECT_MAX_DELAY_SECONDS),
+        )
+        .with_max_attempts(max_attempts);
+
+        while let Some(delay) = backoff.next_delay() {
+            emit_system_message(
+                &state,
+                &format!(
+                    "Reconnecting in {:.1}s (attempt {})",
+                    delay.as_secs_f64(),
+                    backoff.attempt()
+                ),
+            );
+            sleep(delay).await;
+            if !reconnect_is_current(&state, generation) {
+                return;
+            }
+
+            // Rejoin whichever room we were in when the connection dropped
+            let room = state.client_state.get_room();
+            if let Some(hello) = state.last_hello.lock().as_mut() {
+                hello.room = Some(RoomInfo {
+                    name: room,
+                    password: None,
+                });
+            }
+
+            let result = match open_session(&state, &host, port).await {
+                Ok(()) => wait_for_authenticated(&state).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => {
+                    if !reconnect_is_current(&state, generation) {
+                        if let Some(connection) = state.connection.lock().take() {
+                            connection.disconnect();
+                        }
+                        return;
+                    }
+                    state.reconnect.lock().active = false;
+                    start_room_warning_loop(state.clone());
+                    state.emit_event(
+                        "connection-status-changed",
+                        ConnectionStatusEvent {
+                            connected: true,
+                            server: Some(format_server_address(&host, port)),
+                            reconnecting: false,
+                        },
+                    );
+                    emit_system_message(&state, "Reconnected to server");
+                    return;
+                }
+                Err(e) => {
+                    if !reconnect_is_current(&state, generation) {
+                        return;
+                    }
+                    tracing::warn!("Reconnect attempt {} failed: {}", backoff.attempt(), e)
+                }
+            }
+        }
+
+        if !reconnect_is_current(&state, generation) {
+            return;
+        }
+        cancel_reconnect(&state);
+        emit_system_message(&state, "Could not reconnect to server, giving up");
+        teardown_session(&state).await;
+        emit_system_message(&state, "Disconnected from server");
+    });
+}
+
+/// Wait until the server has answered the reconnect Hello. A dial that is
+/// dropped before that counts as a failed attempt of the running loop.
+async fn wait_for_authenticated(state: &Arc<AppState>) -> Result<(), String> {
+    let Some(connection) = state.connection.lock().clone() else {
+        return Err("Connection closed during the handshake".to_string());
+    };
+    let mut states = connection.subscribe_transitions();
+    let reached = states
+        .wait_for(|current| {
+            matches!(
+                current.state,
+                ConnectionState::Authenticated | ConnectionState::Disconnected
+            )
+        })
+        .await
+        .map(|current| current.clone());
+    match reached {
+        Ok(current) if current.state == ConnectionState::Authenticated => Ok(()),
+        Ok(current) => Err(current
+            .detail
+            .unwrap_or_else(|| "Connection closed during the handshake".to_string())),
+        Err(_) => Err("Connection closed during the handshake".to_string()),
+    }
+}
+
+fn restore_pending(state: &Arc<AppState>) -> bool {
+    state.reconnect.lock().restore.is_some()
+}
+
+/// Called with the first user list after a reconnect. If we are alone the
+/// server has forgotten the room, so push our playlist and position back;
+/// otherwise the users still in the room are authoritative.
+fn restore_session(state: &Arc<AppState>) {
+    let Some(snapshot) = state.reconnect.lock().restore.take() else {
+        return;
+    };
+
+    let player_state = state
+        .player
+        .lock()
+        .clone()
+        .map(|player| player.get_state())
+        .unwrap_or_default();
+    send_file_update(state, &player_state);
+
+    let username = state.client_state.get_username();
+    let others_in_room = state
+        .client_state
+        .get_users()
+        .iter()
+        .any(|user| user.room == snapshot.room && user.username != username);
+    if others_in_room {
+        tracing::info!("Rejoined an active room, following server state");
+        return;
+    }
+
+    let Some(connection) = state.connection.lock().clone() else {
+        return;
+    };
+    let config = state.config.lock().clone();
+    if config.user.shared_playlist_enabled && !snapshot.playlist.is_empty() {
+        let message = ProtocolMessage::Set {
+            Set: Box::new(SetMessage {
+                room: None,
+                file: None,
+                user: None,
+                ready: None,
+                playlist_index: None,
+                playlist_change: Some(PlaylistChange {
+                    user: Some(username.clone()),
+                    files: snapshot.playlist.clone(),
+                }),
+                controller_auth: None,
+                new_controlled_room: None,
+                features: None,
+            }),
+        };
+        if let Err(e) = connection.send(message) {
+            tracing::warn!("Failed to restore playlist: {}", e);
+        }
+        if let Some(index) = snapshot.playlist_index {
+            let message = ProtocolMessage::Set {
+                Set: Box::new(SetMessage {
+                    room: None,
+                    file: None,
+                    user: None,
+                    ready: None,
+                    playlist_index: Some(PlaylistIndexUpdate {
+                        user: Some(username),
+                        index: Some(index),
+                    }),
+                    playlist_change: None,
+                    controller_auth: None,
+                    new_controlled_room: None,
+                    features: None,
+                }),
+            };
+            if let Err(e) = connection.send(message) {
+                tracing::warn!("Failed to restore playlist index: {}", e);
+            }
+        }
+    }
+
+    if let Some(position) = snapshot.position {
+        let playstate = PlayState {
+            position,
+            paused: snapshot.paused,
+            do_seek: Some(true),
+            set_by: None,
+        };
+        if let Err(e) = send_state_message(state, Some(playstate), None, true) {
+            tracing::warn!("Failed to restore playback position: {}", e);
+        }
+    }
+    emit_system_message(state, "Restored session after reconnect");
+}
+
+async fn handle_set_message(state: &Arc<AppState>, set_msg: SetMessage) {
+    if let Some(room) = set_msg.room {
+        state.client_state.set_room(room.name);
+        reidentify_as_controller(state);
+    }
+
+    if let Some(file) = set_msg.file {
+        if let Some(name) = file.name {
+            state.client_state.set_file(Some(name.clone()));
+            state.client_state.set_file_size(file.size.clone());
+            state.client_state.set_file_duration(file.duration);
+            if let Err(e) = load_media_by_name(state, &name, false).await {
+                tracing::warn!("Failed to load file from set: {}", e);
+            }
+        }
+    }
+
+    let mut users_changed = false;
+    let mut left_in_room = false;
+    if let Some(user_updates) = set_msg.user {
+        for (username, update) in user_updates {
+            if update
+                .event
+                .as_ref()
+                .and_then(|event| event.left)
+                .unwrap_or(false)
+            {
+                if let Some(user) = state.client_state.get_user(&username) {
+                    if user.room == state.client_state.get_room() {
+                        left_in_room = true;
+                    }
+                }
+            }
+            if apply_user_update(state, username, update) {
+                users_changed = true;
+            }
+        }
+    }
+
+    if let Some(ready) = set_msg.ready {
+        if let Some(username) = ready.username {
+            let is_ready = match ready.is_ready {
+                Some(value) => value,
+                None => state
+                    .client_state
+                    .get_user(&username)
+                    .map(|user| user.is_ready)
+                    .unwrap_or(false),
+            };
+
+            if let Some(mut user) = state.client_state.get_user(&username) {
+                user.is_ready = is_ready;
+                user.last_activity = Some(chrono::Utc::now());
+                state.client_state.add_user(user);
+                users_changed = true;
+            } else {
+                let mut user = crate::client::state::User::new(
+                    username.clone(),
+                    state.client_state.get_room(),
+                );
+                user.is_ready = is_ready;
+                user.last_activity = Some(chrono::Utc::now());
+                state.client_state.add_user(user);
+                users_changed = true;
+            }
+            if ready.is_ready.is_some() && username == state.client_state.get_username() {
+                state.client_state.set_ready(is_ready);
+            }
+        } else {
+            tracing::debug!("Ready state missing username, ignoring");
+        }
+    }
+
+    if let Some(controller_auth) = set_msg.controller_auth {
+        handle_controller_auth(state, controller_auth);
+    }
+
+    if let Some(new_room) = set_msg.new_controlled_room {
+        handle_new_controlled_room(state, new_room).await;
+    }
+
+    if users_changed {
+        emit_user_list(state);
+    }
+
+    if left_in_room {
+        let config = state.config.lock().clone();
+        if config.user.pause_on_leave {
+            pause_local_player(state).await;
+        }
+    }
+
+    let mut playlist_changed = false;
+    if let Some(change) = set_msg.playlist_change {
+        if change.files.is_empty() && restore_pending(state) {
+            tracing::debug!("Keeping local playlist until session is restored");
+        } else {
+            state.playlist.set_items(change.files);
+            playlist_changed = true;
+        }
+    }
+
+    if let Some(index_update) = set_msg.playlist_index {
+        if let Some(index) = index_update.index {
+            if state.playlist.set_current_index(index) {
+                playlist_changed = true;
+            }
+        }
+    }
+
+    if playlist_changed {
+        emit_playlist_update(state);
+        if let Some(item) = state.playlist.get_current_item() {
+            if let Err(e) = load_media_by_name(state, &item.filename, false).await {
+                tracing::warn!("Failed to load playlist item: {}", e);
+            }
+        }
+    }
+
+    evaluate_autoplay(state);
+}
+
+fn handle_controller_auth(state: &Arc<AppState>, auth: ControllerAuth) {
+    let Some(success) = auth.success else {
+        return;
+    };
+    let username = auth
+        .user
+        .clone()
+        .unwrap_or_else(|| state.client_state.get_username());
+    let room = auth
+        .room
+        .clone()
+        .unwrap_or_else(|| state.client_state.get_room());
+    let current_room = state.client_state.get_room();
+    let current_username = state.client_state.get_username();
+    let config = state.config.lock().clone();
+
+    if success {
+        let changed = set_user_controller_status(state, &username, Some(&room), true);
+        if room == current_room {
+            let message = format!("{} authenticated as a room operator", username);
+            emit_system_message(state, &message);
+            maybe_show_osd(state, &config, &message, config.user.show_same_room_osd);
+        }
+        if username == current_username {
+            if let Some(password) = state.last_control_password_attempt.lock().clone() {
+                store_control_password(state, &room, &password, true);
+            }
+        }
+        if changed {
+            emit_user_list(state);
+        }
+    } else if username == current_username {
+        let message = format!("{} failed to identify as a room operator.", username);
+        emit_error_message(state, &message);
+    }
+}
+
+async fn handle_new_controlled_room(state: &Arc<AppState>, room: NewControlledRoom) {
+    let (Some(room_name), Some(password)) = (room.room_name, room.password) else {
+        return;
+    };
+    let room_with_password = format!("{}:{}", room_name, password);
+    let message = format!(
+        "Created managed room '{}' with password '{}'. Please save this information for future reference!\n\nIn managed rooms everyone is kept in sync with the room operator(s) who are the only ones who can pause, unpause, seek, and change the playlist.\n\nYou should ask regular viewers to join the room '{}' but the room operators can join the room '{}' to automatically authenticate themselves.",
+        room_name,
+        password,
+        room_name,
+        room_with_password,
+    );
+    emit_system_message(state, &message);
+    state.emit_event(
+        "managed-room-created",
+        serde_json::json!({
+            "room": room_name,
+            "password": strip_control_password(&password),
+            "operatorJoin": room_with_password,
+            "viewerJoin": room_name,
+        }),
+    );
+
+    state.client_state.set_room(room_name.clone());
+    if let Some(connection) = state.connection.lock().clone() {
+        let set_room = ProtocolMessage::Set {
+            Set: Box::new(SetMessage {
+                room: Some(RoomInfo {
+                    name: room_name.clone(),
+                    password: None,
+                }),
+                file: None,
+                user: None,
+                ready: None,
+                playlist_index: None,
+                playlist_change: None,
+                controller_auth: None,
+                new_controlled_room: None,
+                features: None,
+            }),
+        };
+        if let Err(e) = connection.send(set_room) {
+            tracing::warn!("Failed to set room after controlled room creation: {}", e);
+            return;
+        }
+        if let Err(e) = connection.send(ProtocolMessage::List { List: None }) {
+            tracing::warn!(
+                "Failed to request list after controlled room creation: {}",
+                e
+            );
+        }
+    }
+    let password = strip_control_password(&password);
+    if !password.is_empty() {
+        store_control_password(state, &room_name, &password, true);
+        *state.last_control_password_attempt.lock() = Some(password.clone());
+        if let Err(e) = send_controller_auth(state, &room_name, &password) {
+            tracing::warn!("Failed to authenticate controller after create: {}", e);
+        }
+    }
+}
+
+fn set_user_controller_status(
+    state: &Arc<AppState>,
+    username: &str,
+    room: Option<&str>,
+    is_controller: bool,
+) -> bool {
+    let mut user = state.client_state.get_user(username).unwrap_or_else(|| {
+        crate::client::state::User::new(
+            username,
+            room.map(|value| value.to_string())
+                .unwrap_or_else(|| state.client_state.get_room()),
+        )
+    });
+    if let Some(room) = room {
+        user.room = room.to_string();
+    }
+    let changed = user.is_controller != is_controller;
+    user.is_controller = is_controller;
+    state.client_state.add_user(user);
+    changed
+}
+
+async fn handle_tls_message(state: &Arc<AppState>, tls: TLSMessage) {
+    let Some(answer) = tls.start_tls.as_deref() else {
+        return;
+    };
+
+    let connection = state.connection.lock().clone();
+    let Some(connection) = connection else { return };
+
+    if answer == "true" {
+        tracing::info!("Server accepted TLS, upgrading connection");
+        let fingerprint = match connection.upgrade_tls().await {
+            Ok(fingerprint) => fingerprint,
+            Err(e) => {
+                tracing::error!("TLS upgrade failed: {:#}", e);
+                let message = format!("Secure connection failed: {:#}", e);
+                emit_error_message(state, &message);
+                abort_session(state, &connection, TransitionReason::TlsFailure, message).await;
+                return;
+            }
+        };
+        if let Some(fingerprint) = fingerprint {
+            remember_certificate(state, &connection.host(), connection.port(), &fingerprint);
+        }
+        state.emit_event(
+            "tls-status-changed",
+            serde_json::json!({ "status": "enabled" }),
+        );
+        emit_system_message(state, "Secure connection established");
+        send_hello(state);
+    } else if answer == "false" {
+        tracing::info!("Server does not support TLS");
+        downgrade_to_plaintext(state, &connection, "Server does not support TLS");
+    } else {
+        tracing::debug!("Ignoring TLS message: {}", answer);
+    }
+}
+
+/// Connect and TLS handshake limits from the user preferences
+pub(crate) fn connect_timeouts(config: &SyncplayConfig) -> ConnectTimeouts {
+    ConnectTimeouts {
+        connect: Duration::from_secs(config.user.connect_timeout_seconds),
+        tls_handshake: Duration::from_secs(config.user.tls_handshake_timeout_seconds),
+    }
+}
+
+/// Whether a connection is being dialed or awaiting the server Hello
+fn connection_attempt_in_progress(state: &Arc<AppState>) -> bool {
+    state
+        .connection
+        .lock()
+        .as_ref()
+        .is_some_and(|connection| connection.is_establishing())
+}
+
+/// TLS trust configured for `host:port`, if the server is in the saved list
+pub(crate) fn tls_trust_for(config: &SyncplayConfig, host: &str, port: u16) -> TlsTrust {
+    let Some(server) = config.server_for(host, port) else {
+        return TlsTrust::default();
+    };
+    TlsTrust {
+        ca_bundle_path: server
+            .ca_bundle_path
+            .as_deref()
+            .filter(|path| !path.trim().is_empty())
+            .map(std::path::PathBuf::from),
+        pin_sha256: server
+            .cert_pin_sha256
+            .clone()
+            .filter(|pin| !pin.trim().is_empty()),
+        trust_on_first_use: server.trust_on_first_use,
+    }
+}
+
+/// Open a new traffic recording for this session in `dir`
+fn start_recording(state: &Arc<AppState>, dir: &str, host: &str) -> Option<Arc<TrafficRecorder>> {
+    let host: String = host
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let file_name = format!(
+        "syncplay-{}-{}.jsonl",
+        host,
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    );
+    match TrafficRecorder::create(std::path::Path::new(dir).join(file_name)) {
+        Ok(recorder) => {
+            emit_system_message(
+                state,
+                &format!(
+                    "Recording protocol traffic to {}",
+                    recorder.path().display()
+                ),
+            );
+            Some(Arc::new(recorder))
+        }
+        Err(e) => {
+            emit_error_message(
+                state,
+                &format!("Failed to start protocol recording: {:#}", e),
+            );
+            None
+        }
+    }
+}
+
+/// Proxy configured for `host:port`, falling back to the global proxy
+pub(crate) fn proxy_for(config: &SyncplayConfig, host: &str, port: u16) -> Option<Proxy> {
+    let proxy = config.proxy_for(host, port)?;
+    Some(Proxy {
+        kind: match proxy.proxy_type {
+            ProxyType::Socks5 => ProxyKind::Socks5,
+            ProxyType::Http => ProxyKind::Http,
+            ProxyType::None => return None,
+        },
+        host: proxy.host.clone(),
+        port: proxy.port,
+        username: proxy.username.clone(),
+        password: proxy.password.clone(),
+    })
+}
+
+/// Pin the certificate of a trust-on-first-use server the first time we see it
+fn remember_certificate(state: &Arc<AppState>, host: &str, port: u16, fingerprint: &str) {
+    let mut updated = state.config.lock().clone();
+    let should_pin = updated
+        .server_for(host, port)
+        .map(|server| server.trust_on_first_use && server.cert_pin_sha256.is_none())
+        .unwrap_or(false);
+    if !should_pin {
+        return;
+    }
+    updated.pin_server_certificate(host, port, fingerprint);
+    emit_system_message(
+        state,
+        &format!(
+            "Trusting certificate for {}:{} (SHA-256 {})",
+            host, port, fingerprint
+        ),
+    );
+    if let Some(client_host) = state.host.lock().clone() {
+        if let Err(e) = client_host.save_config(&updated) {
+            tracing::warn!("Failed to save certificate pin: {}", e);
+        }
+    }
+    *state.config.lock() = updated.clone();
+    state.emit_event("config-updated", updated);
+}
+
+fn hello_has_password(state: &Arc<AppState>) -> bool {
+    state
+        .last_hello
+        .lock()
+        .as_ref()
+        .and_then(|hello| hello.password.as_deref())
+        .is_some_and(|password| !password.is_empty())
+}
+
+async fn handle_server_error(state: &Arc<AppState>, error: ServerError) {
+    state.emit_event("server-error", error.clone());
+    let connection = state.connection.lock().clone();
+    let Some(connection) = connection else {
+        emit_error_message(state, &error.message);
+        return;
+    };
+
+    // Servers without TLS support answer the startTLS request with an error
+    if connection.state() == ConnectionState::TlsNegotiating
+        && error.kind == ServerErrorKind::Unknown
+    {
+        emit_error_message(state, &error.message);
+        downgrade_to_plaintext(
+            state,
+            &connection,
+            "Server does not understand TLS requests",
+        );
+        return;
+    }
+
+    let explanation = match error.kind {
+        ServerErrorKind::WrongPassword => {
+            let server = format!("{}:{}", connection.host(), connection.port());
+            state.emit_event(
+                "server-password-required",
+                serde_json::json!({ "server": server, "message": error.message }),
+            );
+            format!(
+                "{} rejected the server password ({}). Enter the correct password to connect",
+                server, error.message
+            )
+        }
+        ServerErrorKind::VersionMismatch => {
+            let client_version = state
+                .last_hello
+                .lock()
+                .as_ref()
+                .map(|hello| hello.version.clone())
+                .unwrap_or_default();
+            format!(
+                "The server does not support this client's protocol version {} ({}). \
+                 Ask the server operator to update the server, or use a compatible client",
+                client_version, error.message
+            )
+        }
+        ServerErrorKind::UsernameTaken => format!(
+            "The username {} is already in use on this server ({}). Pick another name",
+            state.client_state.get_username(),
+            error.message
+        ),
+        ServerErrorKind::RoomCreationFailed => {
+            format!("Could not create the managed room: {}", error.message)
+        }
+        ServerErrorKind::Unknown => error.message.clone(),
+    };
+    emit_error_message(state, &explanation);
+
+    if error.is_fatal() {
+        // Retrying with the same Hello would fail the same way
+        abort_session(
+            state,
+            &connection,
+            TransitionReason::ServerError,
+            error.message,
+        )
+        .await;
+    }
+}
+
+/// Continue without TLS if the policy allows it, reporting the downgrade either way
+fn downgrade_to_plaintext(state: &Arc<AppState>, connection: &Arc<Connection>, reason: &str) {
+    let required = *state.tls_policy.lock() == TlsPolicy::Required;
+    let password_in_plaintext = !required && hello_has_password(state);
+    tracing::warn!("TLS downgrade: {}", reason);
+    state.emit_event(
+        "tls-status-changed",
+        serde_json::json!({
+            "status": if required { "refused" } else { "unsupported" },
+            "reason": reason,
+            "passwordInPlaintext": password_in_plaintext,
+        }),
+    );
+
+    if required {
+        emit_error_message(
+            state,
+            &format!(
+                "{}. TLS is required for this server, disconnecting without sending credentials",
+                reason
+            ),
+        );
+        let state = state.clone();
+        let connection = connection.clone();
+        let reason = reason.to_string();
+        tokio::spawn(async move {
+            abort_session(&state, &connection, TransitionReason::TlsFailure, reason).await;
+        });
+        return;
+    }
+
+    emit_error_message(state, &format!("{}. Continuing without encryption", reason));
+    if password_in_plaintext {
+        emit_error_message(state, "The server password will be sent unencrypted");
+    }
+    send_hello(state);
+}
+
+/// Drop a connection that must not be retried, such as a failed TLS handshake
+async fn abort_session(
+    state: &Arc<AppState>,
+    connection: &Arc<Connection>,
+    reason: TransitionReason,
+    detail: String,
+) {
+    {
+        let mut current = state.connection.lock();
+        if current
+            .as_ref()
+            .is_some_and(|active| Arc::ptr_eq(active, connection))
+        {
+            current.take();
+        }
+    }
+    connection.close(
+        ConnectionTransition::new(ConnectionState::Disconnected, reason).with_detail(detail),
+    );
+    cancel_reconnect(state);
+    teardown_session(state).await;
+}
+
+fn send_hello(state: &Arc<AppState>) {
+    let mut hello_sent = state.hello_sent.lock();
+    if *hello_sent {
+        return;
+    }
+
+    let Some(hello) = state.last_hello.lock().clone() else {
+        return;
+    };
+    let Some(connection) = state.connection.lock().clone() else {
+        return;
+    };
+    if *state.tls_policy.lock() == TlsPolicy::Required && !connection.is_encrypted() {
+        tracing::error!("Refusing to send Hello without TLS");
+        return;
+    }
+
+    if let Err(e) = connection.send(ProtocolMessage::Hello { Hello: hello }) {
+        tracing::error!("Failed to send Hello message: {}", e);
+        return;
+    }
+
+    *hello_sent = true;
+    tracing::info!("Sent Hello message");
+
+    let config = state.config.lock().clone();
+    if config.user.ready_at_start {
+        if let Err(e) = send_ready_state(state, true, false) {
+            tracing::warn!("Failed to send ready-at-start: {}", e);
+        }
+    }
+}
+
+fn update_autoplay_state(state: &Arc<AppState>, config: &crate::config::SyncplayConfig) {
+    let mut autoplay = state.autoplay.lock();
+    autoplay.enabled = config.user.autoplay_enabled;
+    autoplay.min_users = config.user.autoplay_min_users;
+    autoplay.require_same_filenames = config.user.autoplay_require_same_filenames;
+    autoplay.unpause_action = config.user.unpause_action.clone();
+    if !autoplay.enabled {
+        autoplay.countdown_active = false;
+        autoplay.countdown_remaining = 0;
+    }
+}
+
+fn maybe_autosave_connection(
+    state: &Arc<AppState>,
+    config: &crate::config::SyncplayConfig,
+    snapshot: ConnectionSnapshot<'_>,
+) {
+    if !config.user.autosave_joins_to_list {
+        return;
+    }
+
+    let mut updated = config.clone();
+    updated.user.username = snapshot.username.to_string();
+    updated.user.default_room = snapshot.room.to_string();
+
+    updated.add_recent_server(ServerConfig {
+        host: snapshot.host.to_string(),
+        port: snapshot.port,
+        password: snapshot.password.map(|value| value.to_string()),
+        ..ServerConfig::default()
+    });
+    updated.server = updated.recent_servers[0].clone();
+
+    if !updated
+        .user
+        .room_list
+        .iter()
+        .any(|entry| entry == snapshot.room)
+    {
+        updated.user.room_list.insert(0, snapshot.room.to_string());
+    }
+
+    let Some(host) = state.host.lock().clone() else {
+        return;
+    };
+    if let Err(e) = host.save_config(&updated) {
+        tracing::warn!("Failed to save config after connect: {}", e);
+        return;
+    }
+
+    *state.config.lock() = updated.clone();
+    state.emit_event("config-updated", updated);
+}
+
+fn send_ready_state(
+    state: &Arc<AppState>,
+    is_ready: bool,
+    manually_initiated: bool,
+) -> Result<(), String> {
+    state.client_state.set_ready(is_ready);
+    let username = state.client_state.get_username();
+    let message = ProtocolMessage::Set {
+        Set: Box::new(SetMessage {
+            room: None,
+            file: None,
+            user: None,
+            ready: Some(crate::network::messages::ReadyState {
+                username: Some(username),
+                is_ready: Some(is_ready),
+                manually_initiated: Some(manually_initiated),
+                set_by: None,
+            }),
+            playlist_index: None,
+            playlist_change: None,
+            controller_auth: None,
+            new_controlled_room: None,
+            features: None,
+        }),
+    };
+    let connection = state.connection.lock().clone();
+    let Some(connection) = connection else {
+        return Err("Not connected to server".to_string());
+    };
+    connection
+        .send(message)
+        .map_err(|e| format!("Failed to send ready state: {}", e))
+}
+
+fn autoplay_conditions_met(state: &Arc<AppState>) -> bool {
+    let config = state.config.lock().clone();
+    if !config.user.autoplay_enabled {
+        return false;
+    }
+
+    let room = state.client_state.get_room();
+    let users = state.client_state.get_users_in_room(&room);
+    if users.is_empty() {
+        return false;
+    }
+
+    let current_file = state.client_state.get_file();
+    for user in &users {
+        if !user.is_ready {
+            return false;
+        }
+        if config.user.autoplay_require_same_filenames
+            && !same_filename(current_file.as_deref(), user.file.as_deref())
+        {
+            return false;
+        }
+    }
+
+    if config.user.autoplay_min_users >= 2 && (users.len() as i32) < config.user.autoplay_min_users
+    {
+        return false;
+    }
+
+    let player_state = state.player.lock().clone().map(|player| player.get_state());
+    if let Some(player_state) = player_state {
+        if player_state.paused == Some(false) {
+            return false;
+        }
+    }
+
+    true
+}
+
+fn start_autoplay_countdown(state: Arc<AppState>) {
+    {
+        let mut autoplay = state.autoplay.lock();
+        if autoplay.countdown_active {
+            return;
+        }
+        autoplay.countdown_active = true;
+        autoplay.countdown_remaining = AUTOPLAY_DELAY_SECONDS;
+    }
+
+    tokio::spawn(async move {
+        loop {
+            let mut should_stop = false;
+            let mut should_unpause = false;
+            {
+                let mut autoplay = state.autoplay.lock();
+                if !autoplay.countdown_active {
+                    return;
+                }
+                if !autoplay_conditions_met(&state) {
+                    autoplay.countdown_active = false;
+                    autoplay.countdown_remaining = 0;
+                    return;
+                }
+                if autoplay.countdown_remaining <= 0 {
+                    autoplay.countdown_active = false;
+                    should_unpause = true;
+                } else {
+                    autoplay.countdown_remaining -= 1;
+                }
+            }
+
+            if should_unpause {
+                if let Err(e) = ensure_player_connected(&state).await {
+                    tracing::warn!("Failed to connect to player for autoplay: {}", e);
+                    return;
+                }
+                let player = state.player.lock().clone();
+                if let Some(player) = player {
+                    if let Err(e) = player.set_paused(false).await {
+                        tracing::warn!("Failed to autoplay unpause: {}", e);
+                    }
+                }
+                should_stop = true;
+            }
+
+            if should_stop {
+                return;
+            }
+
+            sleep(Duration::from_secs(1)).await;
+        }
+    });
+}
+
+fn evaluate_autoplay(state: &Arc<AppState>) {
+    if autoplay_conditions_met(state) {
+        start_autoplay_countdown(state.clone());
+    } else {
+        let mut autoplay = state.autoplay.lock();
+        autoplay.countdown_active = false;
+        autoplay.countdown_remaining = 0;
+    }
+}
+
+async fn pause_local_player(state: &Arc<AppState>) {
+    if let Err(e) = ensure_player_connected(state).await {
+        tracing::warn!("Failed to connect to player for pause: {}", e);
+        return;
+    }
+    let player = state.player.lock().clone();
+    if let Some(player) = player {
+        if let Err(e) = player.set_paused(true).await {
+            tracing::warn!("Failed to pause player: {}", e);
+        }
+    }
+}
+
+fn apply_user_update(state: &Arc<AppState>, username: String, update: UserUpdate) -> bool {
+    let config = state.config.lock().clone();
+    let current_username = state.client_state.get_username();
+    let current_room = state.client_state.get_room();
+    let old_user = state.client_state.get_user(&username);
+
+    if let Some(event) = update.event.as_ref() {
+        if event.left.unwrap_or(false) {
+            if let Some(old_user) = old_user.as_ref() {
+                let allow_osd = if old_user.room == current_room {
+                    config.user.show_same_room_osd
+                } else {
+                    config.user.show_different_room_osd
+                };
+                let message = format!("{} has left", username);
+                emit_system_message(state, &message);
+                maybe_show_osd(state, &config, &message, allow_osd);
+            }
+            state.client_state.remove_user(&username);
+            return true;
+        }
+    }
+
+    let mut user = state.client_state.get_user(&username).unwrap_or_else(|| {
+        crate::client::state::User::new(username.clone(), state.client_state.get_room())
+    });
+    let now = chrono::Utc::now();
+    user.last_activity = Some(now);
+
+    if let Some(event) = update.event.as_ref() {
+        if event.joined.unwrap_or(false) {
+            user.joined_at = Some(now);
+        }
+    }
+    if let Some(features) = update.features {
+        user.features = Some(features);
+    }
+    if let Some(version) = advertised_version(
+        update.event.as_ref().map(|event| &event.extra),
+        user.features.as_ref(),
+    ) {
+        user.version = Some(version);
+    }
+
+    if let Some(room) = update.room {
+        user.room = room.name;
+    }
+
+    let mut updated_file = None;
+    if let Some(file) = update.file {
+        user.file = file.name;
+        user.file_size = file.size;
+        user.file_duration = file.duration;
+        updated_file = Some(());
+    }
+    if let Some(is_ready) = update.is_ready {
+        user.is_ready = is_ready;
+    }
+    if let Some(controller) = update.controller {
+        user.is_controller = controller;
+    }
+
+    let room_changed = old_user
+        .as_ref()
+        .map(|old| old.room != user.room)
+        .unwrap_or(true);
+    let file_changed = if updated_file.is_some() {
+        !is_same_file(old_user.as_ref(), &user, &config)
+    } else {
+        false
+    };
+
+    if updated_file.is_some() && file_changed {
+        if let Some(file_name) = user.file.as_ref() {
+            let duration = user.file_duration.unwrap_or(0.0);
+            let duration_text = if duration > 0.0 {
+                format_time(duration)
+            } else {
+                "--:--".to_string()
+            };
+            let mut message = format!(
+                "{} is playing '{}' ({})",
+                username, file_name, duration_text
+            );
+            if current_room != user.room || username == current_username {
+                message.push_str(&format!(" in room: '{}'", user.room));
+            }
+            emit_system_message(state, &message);
+            let allow_osd = allow_osd_for_user(&config, &current_room, old_user.as_ref(), &user);
+            maybe_show_osd(state, &config, &message, allow_osd);
+
+            if username != current_username {
+                if let Some(diff) = file_differences(state, &user, &config) {
+                    let message = format!("Your file differs in the following way(s): {}", diff);
+                    emit_system_message(state, &message);
+                }
+            }
+        }
+    } else if room_changed {
+        let message = format!("{} has joined the room: '{}'", username, user.room);
+        emit_system_message(state, &message);
+        let allow_osd = allow_osd_for_user(&config, &current_room, old_user.as_ref(), &user);
+        maybe_show_osd(state, &config, &message, allow_osd);
+    }
+
+    state.client_state.add_user(user);
+    true
+}
+
+/// Client version from a user event, falling back to the advertised features
+fn advertised_version(
+    event_extra: Option<&HashMap<String, serde_json::Value>>,
+    features: Option<&serde_json::Value>,
+) -> Option<String> {
+    event_extra
+        .and_then(|extra| extra.get("version"))
+        .or_else(|| features.and_then(|features| features.get("version")))
+        .and_then(|version| version.as_str())
+        .map(str::to_string)
+}
+
+fn allow_osd_for_user(
+    config: &crate::config::SyncplayConfig,
+    current_room: &str,
+    old_user: Option<&crate::client::state::User>,
+    user: &crate::client::state::User,
+) -> bool {
+    let was_in_room = old_user
+        .map(|old| old.room == current_room)
+        .unwrap_or(false);
+    let is_in_room = user.room == current_room;
+    let allow = if was_in_room || is_in_room {
+        config.user.show_same_room_osd
+    } else {
+        config.user.show_different_room_osd
+    };
+
+    if !config.user.show_non_controller_osd && !user.is_controller {
+        return false;
+    }
+
+    allow
+}
+
+fn is_same_file(
+    old_user: Option<&crate::client::state::User>,
+    new_user: &crate::client::state::User,
+    config: &crate::config::SyncplayConfig,
+) -> bool {
+    let Some(old_user) = old_user else {
+        return false;
+    };
+    let same_name = same_filename(old_user.file.as_deref(), new_user.file.as_deref());
+    let same_size =
+        crate::utils::same_filesize(old_user.file_size.as_ref(), new_user.file_size.as_ref());
+    let same_duration = same_duration(
+        old_user.file_duration,
+        new_user.file_duration,
+        config.user.show_duration_notification,
+    );
+    same_name && same_size && same_duration
+}
+
+fn same_duration(a: Option<f64>, b: Option<f64>, allow: bool) -> bool {
+    if !allow {
+        return true;
+    }
+    let (Some(a), Some(b)) = (a, b) else {
+        return false;
+    };
+    (a.round() - b.round()).abs() < DIFFERENT_DURATION_THRESHOLD
+}
+
+fn file_differences(
+    state: &Arc<AppState>,
+    user: &crate::client::state::User,
+    config: &crate::config::SyncplayConfig,
+) -> Option<String> {
+    if user.room != state.client_state.get_room() {
+        return None;
+    }
+    let current_file = state.client_state.get_file();
+    let current_size = state.client_state.get_file_size();
+    let current_duration = state.client_state.get_file_duration();
+    let (Some(current_file), Some(other_file)) = (current_file.as_ref(), user.file.as_ref()) else {
+        return None;
+    };
+
+    let mut differences = Vec::new();
+    if !same_filename(Some(current_file), Some(other_file)) {
+        differences.push("name");
+    }
+    if !crate::utils::same_filesize(current_size.as_ref(), user.file_size.as_ref()) {
+        differences.push("size");
+    }
+    if !same_duration(
+        current_duration,
+        user.file_duration,
+        config.user.show_duration_notification,
+    ) {
+        differences.push("duration");
+    }
+
+    if differences.is_empty() {
+        None
+    } else {
+        Some(differences.join(", "))
+    }
+}
+
+fn emit_user_list(state: &Arc<AppState>) {
+    let users = state
+        .client_state
+        .get_users()
+        .into_iter()
+        .map(|u| crate::app_state::UserInfo {
+            username: u.username,
+            room: u.room,
+            file: u.file,
+            is_ready: u.is_ready,
+            is_controller: u.is_controller,
+            features: u.features,
+            version: u.version,
+            joined_at: u.joined_at.map(|time| time.to_rfc3339()),
+            last_activity: u.last_activity.map(|time| time.to_rfc3339()),
+            sync_offset: u.sync_offset,
+        })
+        .collect();
+    state.emit_event(
+        "user-list-updated",
+        crate::app_state::UserListEvent { users },
+    );
+}
+
+fn emit_playlist_update(state: &Arc<AppState>) {
+    let items: Vec<String> = state
+        .playlist
+        .get_items()
+        .iter()
+        .map(|item| item.filename.clone())
+        .collect();
+    state.emit_event(
+        "playlist-updated",
+        crate::app_state::PlaylistEvent {
+            items,
+            current_index: state.playlist.get_current_index(),
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::events::{ChannelEventSink, ClientEvent};
+    use crate::client::replay::{PlayerCall, ReplayPlayer};
+    use crate::network::messages::{ErrorMessage, UserEvent, UserInfo};
+    use crate::network::mock_server::{self, MockPeer, MockServer};
+    use crate::player::backend::PlayerBackend;
+    use tokio::sync::mpsc;
+
+    fn build_client(
+        auto_reconnect: bool,
+    ) -> (SyncplayClient, mpsc::UnboundedReceiver<ClientEvent>) {
+        let mut config = SyncplayConfig::default();
+        config.user.autosave_joins_to_list = false;
+        config.user.auto_reconnect = auto_reconnect;
+        config.user.shared_playlist_enabled = true;
+        config.player.player_path = "/nonexistent/test-player".to_string();
+        let (sink, events) = ChannelEventSink::new();
+        (SyncplayClient::headless(config, Arc::new(sink)), events)
+    }
+
+    async fn wait_until(mut condition: impl FnMut() -> bool) {
+        for _ in 0..250 {
+            if condition() {
+                return;
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+        panic!("condition not met in time");
+    }
+
+    async fn connect(client: &SyncplayClient, server: &MockServer) -> MockPeer {
+        client
+            .connect(
+                "127.0.0.1".to_string(),
+                server.port(),
+                "alice".to_string(),
+                "lobby".to_string(),
+                None,
+            )
+            .await
+            .unwrap();
+        server.accept().await
+    }
+
+    /// Complete the handshake and answer the client's List request
+    async fn handshake(peer: &mut MockPeer, users: &[&str]) {
+        let hello = peer.accept_hello("false").await;
+        assert_eq!(hello.username, "alice");
+        assert_eq!(hello.room.unwrap().name, "lobby");
+        peer.send(mock_server::hello_reply("alice", "lobby")).await;
+        peer.expect(|message| match message {
+            ProtocolMessage::List { List: None } => Some(()),
+            _ => None,
+        })
+        .await;
+        peer.send(mock_server::list(room_list(users))).await;
+    }
+
+    fn room_list(users: &[&str]) -> crate::network::messages::ListResponse {
+        let room = users
+            .iter()
+            .map(|name| {
+                (
+                    name.to_string(),
+                    UserInfo {
+                        file: None,
+                        controller: None,
+                        is_ready: Some(false),
+                        features: None,
+                    },
+                )
+            })
+            .collect();
+        HashMap::from([("lobby".to_string(), room)])
+    }
+
+    #[tokio::test]
+    async fn test_connect_handshake_and_user_list() {
+        let server = MockServer::bind().await;
+        let (client, mut events) = build_client(false);
+        let state = client.state().clone();
+        let mut peer = connect(&client, &server).await;
+
+        handshake(&mut peer, &["alice", "bob"]).await;
+        wait_until(|| state.client_state.get_users().len() == 2).await;
+        assert_eq!(
+            state.client_state.get_server_version().as_deref(),
+            Some("1.7.4")
+        );
+        assert!(client.is_connected());
+
+        let mut names = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            names.push(event.name);
+        }
+        assert!(names.iter().any(|name| name == "connection-status-changed"));
+        assert!(names.iter().any(|name| name == "user-list-updated"));
+    }
+
+    #[tokio::test]
+    async fn test_set_messages_update_client_state() {
+        let server = MockServer::bind().await;
+        let (client, _events) = build_client(false);
+        let state = client.state().clone();
+        let mut peer = connect(&client, &server).await;
+        handshake(&mut peer, &["alice"]).await;
+        wait_until(|| state.client_state.get_users().len() == 1).await;
+
+        peer.send(mock_server::set(SetMessage {
+            user: Some(HashMap::from([(
+                "bob".to_string(),
+                UserUpdate {
+                    room: Some(RoomInfo {
+                        name: "lobby".to_string(),
+                        password: None,
+                    }),
+                    file: None,
+                    event: Some(UserEvent {
+                        joined: Some(true),
+                        left: None,
+                        extra: Default::default(),
+                    }),
+                    controller: None,
+                    is_ready: None,
+                    features: None,
+                },
+            )])),
+            ..Default::default()
+        }))
+        .await;
+        wait_until(|| state.client_state.get_user("bob").is_some()).await;
+
+        peer.send(mock_server::set(SetMessage {
+            playlist_change: Some(PlaylistChange {
+                user: Some("bob".to_string()),
+                files: vec!["a.mkv".to_string(), "b.mkv".to_string()],
+            }),
+            ..Default::default()
+        }))
+        .await;
+        peer.send(mock_server::set(SetMessage {
+            playlist_index: Some(PlaylistIndexUpdate {
+                user: Some("bob".to_string()),
+                index: Some(1),
+            }),
+            ..Default::default()
+        }))
+        .await;
+        wait_until(|| state.playlist.get_current_index() == Some(1)).await;
+        assert_eq!(state.playlist.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_user_updates_keep_peer_status() {
+        let server = MockServer::bind().await;
+        let (client, mut events) = build_client(false);
+        let state = client.state().clone();
+        let mut peer = connect(&client, &server).await;
+        handshake(&mut peer, &["alice"]).await;
+        wait_until(|| state.client_state.get_users().len() == 1).await;
+
+        peer.send(mock_server::set(SetMessage {
+            user: Some(HashMap::from([(
+                "bob".to_string(),
+                UserUpdate {
+                    room: Some(RoomInfo {
+                        name: "lobby".to_string(),
+                        password: None,
+                    }),
+                    file: None,
+                    event: Some(UserEvent {
+                        joined: Some(true),
+                        left: None,
+                        extra: HashMap::from([("version".to_string(), serde_json::json!("1.6.9"))]),
+                    }),
+                    controller: None,
+                    is_ready: None,
+                    features: Some(serde_json::json!({ "sharedPlaylists": true })),
+                },
+            )])),
+            ..Default::default()
+        }))
+        .await;
+        wait_until(|| state.client_state.get_user("bob").is_some()).await;
+
+        let bob = state.client_state.get_user("bob").unwrap();
+        assert_eq!(bob.version.as_deref(), Some("1.6.9"));
+        assert_eq!(
+            bob.features,
+            Some(serde_json::json!({ "sharedPlaylists": true }))
+        );
+        assert!(bob.joined_at.is_some());
+        assert!(bob.last_activity.is_some());
+        assert!(state.client_state.set_user_sync_offset("bob", -2.0));
+        assert!(!state.client_state.set_user_sync_offset("bob", -2.05));
+
+        // A later user list keeps what only the updates told us
+        peer.send(mock_server::list(room_list(&["alice", "bob"])))
+            .await;
+        wait_until(|| {
+            let mut payload = None;
+            while let Ok(event) = events.try_recv() {
+                if event.name == "user-list-updated" {
+                    payload = Some(event.payload);
+                }
+            }
+            payload.is_some_and(|payload| {
+                payload["users"]
+                    .as_array()
+                    .is_some_and(|users| users.iter().any(|user| user["syncOffset"] == -2.0))
+            })
+        })
+        .await;
+        let bob = state.client_state.get_user("bob").unwrap();
+        assert_eq!(bob.version.as_deref(), Some("1.6.9"));
+        assert!(bob.joined_at.is_some());
+
+        // Another viewer becoming the slowest makes bob's offset stale
+        peer.send(ProtocolMessage::State {
+            State: StateMessage {
+                playstate: Some(PlayState {
+                    position: 12.0,
+                    paused: false,
+                    do_seek: None,
+                    set_by: Some("carol".to_string()),
+                }),
+                ping: None,
+                ignoring_on_the_fly: None,
+            },
+        })
+        .await;
+        wait_until(|| {
+            state
+                .client_state
+                .get_user("bob")
+                .is_some_and(|bob| bob.sync_offset.is_none())
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_create_command_requests_managed_room() {
+        let server = MockServer::bind().await;
+        let (client, mut events) = build_client(false);
+        let state = client.state().clone();
+        let mut peer = connect(&client, &server).await;
+        handshake(&mut peer, &["alice"]).await;
+        wait_until(|| state.client_state.get_users().len() == 1).await;
+
+        client.send_chat("/create movie".to_string()).await.unwrap();
+        let auth = peer
+            .expect(|message| match message {
+                ProtocolMessage::Set { Set } => Set.controller_auth,
+                _ => None,
+            })
+            .await;
+        assert_eq!(auth.room.as_deref(), Some("movie"));
+        let password = auth.password.unwrap();
+
+        peer.send(mock_server::set(SetMessage {
+            new_controlled_room: Some(NewControlledRoom {
+                password: Some(password.clone()),
+                room_name: Some("+movie:ABCDEF123456".to_string()),
+            }),
+            ..Default::default()
+        }))
+        .await;
+        let room = peer
+            .expect(|message| match message {
+                ProtocolMessage::Set { Set } => Set.room,
+                _ => None,
+            })
+            .await;
+        assert_eq!(room.name, "+movie:ABCDEF123456");
+        assert_eq!(
+            state
+                .controlled_room_passwords
+                .lock()
+                .get("+movie:ABCDEF123456"),
+            Some(&password)
+        );
+
+        sleep(Duration::from_millis(50)).await;
+        let created = std::iter::from_fn(|| events.try_recv().ok())
+            .find(|event| event.name == "managed-room-created")
+            .expect("managed-room-created event");
+        assert_eq!(
+            created.payload["operatorJoin"],
+            format!("+movie:ABCDEF123456:{}", password)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_only_operators_control_managed_rooms() {
+        let server = MockServer::bind().await;
+        let (client, _events) = build_client(false);
+        let state = client.state().clone();
+        let mut peer = connect(&client, &server).await;
+        handshake(&mut peer, &["alice"]).await;
+        wait_until(|| state.client_state.get_users().len() == 1).await;
+        assert!(state.client_state.can_control());
+
+        let room = "+movie:ABCDEF123456";
+        client.send_chat(format!("/room {}", room)).await.unwrap();
+        peer.send(mock_server::list(HashMap::from([(
+            room.to_string(),
+            room_list(&["alice"]).remove("lobby").unwrap(),
+        )])))
+        .await;
+        wait_until(|| state.client_state.get_user("alice").unwrap().room == room).await;
+        assert!(!state.client_state.can_control());
+
+        peer.send(mock_server::set(SetMessage {
+            controller_auth: Some(ControllerAuth {
+                room: Some(room.to_string()),
+                password: None,
+                user: Some("alice".to_string()),
+                success: Some(true),
+            }),
+            ..Default::default()
+        }))
+        .await;
+        wait_until(|| state.client_state.can_control()).await;
+    }
+
+    #[tokio::test]
+    async fn test_viewer_changes_are_rolled_back_locally() {
+        let server = MockServer::bind().await;
+        let (client, mut events) = build_client(false);
+        let state = client.state().clone();
+        let mut peer = connect(&client, &server).await;
+        handshake(&mut peer, &["alice"]).await;
+        wait_until(|| state.client_state.get_users().len() == 1).await;
+
+        let room = "+movie:ABCDEF123456";
+        client.send_chat(format!("/room {}", room)).await.unwrap();
+        peer.send(mock_server::list(HashMap::from([(
+            room.to_string(),
+            room_list(&["alice"]).remove("lobby").unwrap(),
+        )])))
+        .await;
+        wait_until(|| !state.client_state.can_control()).await;
+
+        let player = Arc::new(ReplayPlayer::new(None));
+        player.set_position(10.0).await.unwrap();
+        player.set_paused(false).await.unwrap();
+        *state.player.lock() = Some(player.clone());
+        crate::player::controller::spawn_player_state_loop(state.clone());
+
+        peer.send(mock_server::state(10.0, false, 1.0)).await;
+        peer.expect(|message| match message {
+            ProtocolMessage::State { State } => State.ping,
+            _ => None,
+        })
+        .await;
+        sleep(Duration::from_millis(800)).await;
+
+        // The room kept playing, so the seek is undone to where it is now
+        let seeked = player.calls().len();
+        player.set_position(60.0).await.unwrap();
+        let rollback = || {
+            player.calls()[seeked..].iter().find_map(|call| match call {
+                PlayerCall::Seek { position } if *position < 59.0 => Some(*position),
+                _ => None,
+            })
+        };
+        wait_until(|| rollback().is_some()).await;
+        let undone = rollback().unwrap();
+        assert!(undone > 10.5 && undone < 15.0, "rolled back to {}", undone);
+
+        let paused = player.calls().len();
+        player.set_paused(true).await.unwrap();
+        wait_until(|| player.calls()[paused..].contains(&PlayerCall::SetPaused { paused: false }))
+            .await;
+        sleep(Duration::from_millis(300)).await;
+        assert!(!player.get_state().paused.unwrap());
+
+        let mut messages = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if event.name == "chat-message-received" {
+                messages.push(event.payload["message"].as_str().unwrap_or("").to_string());
+            }
+        }
+        for action in ["seek", "pause"] {
+            let expected = format!(
+                "Only room operators can {} in managed room {}",
+                action, room
+            );
+            assert!(messages.contains(&expected), "missing {:?}", expected);
+        }
+
+        client.disconnect().await;
+        let sent = peer.expect_closed().await;
+        assert!(!sent.iter().any(|message| match message {
+            ProtocolMessage::Set { .. } => true,
+            ProtocolMessage::State { State } => State
+                .playstate
+                .as_ref()
+                .is_some_and(|playstate| playstate.paused || playstate.do_seek.is_some()),
+            _ => false,
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_state_reply_echoes_latency() {
+        let server = MockServer::bind().await;
+        let (client, _events) = build_client(false);
+        let mut peer = connect(&client, &server).await;
+        handshake(&mut peer, &["alice"]).await;
+
+        peer.send(mock_server::state(10.0, true, 123.5)).await;
+        let ping = peer
+            .expect(|message| match message {
+                ProtocolMessage::State { State } => State.ping,
+                _ => None,
+            })
+            .await;
+        assert_eq!(ping.latency_calculation, Some(123.5));
+        assert!(ping.client_latency_calculation.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_start_tls_error_falls_back_to_hello() {
+        let server = MockServer::bind().await;
+        let (client, _events) = build_client(false);
+        let mut peer = connect(&client, &server).await;
+
+        peer.expect(|message| match message {
+            ProtocolMessage::TLS { .. } => Some(()),
+            _ => None,
+        })
+        .await;
+        peer.send(ProtocolMessage::Error {
+            Error: ErrorMessage {
+                message: "Unknown command startTLS".to_string(),
+            },
+        })
+        .await;
+        let hello = peer
+            .expect(|message| match message {
+                ProtocolMessage::Hello { Hello } => Some(Hello),
+                _ => None,
+            })
+            .await;
+        assert_eq!(hello.username, "alice");
+    }
+
+    #[tokio::test]
+    async fn test_wrong_password_stops_reconnecting() {
+        let server = MockServer::bind().await;
+        let (client, mut events) = build_client(true);
+        let state = client.state().clone();
+        let mut peer = connect_with_password(&client, &server).await;
+        peer.accept_hello("false").await;
+        peer.send(ProtocolMessage::Error {
+            Error: ErrorMessage {
+                message: "Wrong password supplied".to_string(),
+            },
+        })
+        .await;
+
+        wait_until(|| !state.is_connected()).await;
+        assert!(!state.reconnect.lock().active);
+        let mut names = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if event.name == "server-error" {
+                assert_eq!(event.payload["kind"], "wrong_password");
+            }
+            names.push(event.name);
+        }
+        assert!(names.iter().any(|name| name == "server-password-required"));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_connection_during_handshake() {
+        let server = MockServer::bind().await;
+        let (client, _events) = build_client(true);
+        let state = client.state().clone();
+        let mut peer = connect(&client, &server).await;
+        peer.accept_hello("false").await;
+        assert!(client
+            .connect(
+                "127.0.0.1".to_string(),
+                server.port(),
+                "alice".to_string(),
+                "lobby".to_string(),
+                None,
+            )
+            .await
+            .is_err());
+
+        client.cancel_connection().await.unwrap();
+        assert!(!state.is_connected());
+        assert!(!state.reconnect.lock().active);
+        assert!(state
+            .chat
+            .get_messages()
+            .iter()
+            .any(|message| message.message == "Connection attempt cancelled"));
+        assert_eq!(
+            client.cancel_connection().await.unwrap_err(),
+            "No connection attempt in progress"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_hello_reply_times_out() {
+        let server = MockServer::bind().await;
+        let mut config = SyncplayConfig::default();
+        config.user.autosave_joins_to_list = false;
+        config.user.auto_reconnect = false;
+        config.user.hello_timeout_seconds = 1;
+        let (sink, mut events) = ChannelEventSink::new();
+        let client = SyncplayClient::headless(config, Arc::new(sink));
+        let state = client.state().clone();
+        let mut peer = connect(&client, &server).await;
+        peer.accept_hello("false").await;
+
+        wait_until(|| !state.is_connected()).await;
+        assert!(state
+            .chat
+            .get_messages()
+            .iter()
+            .any(|message| message.message.contains("did not complete the handshake")));
+
+        sleep(Duration::from_millis(50)).await;
+        let mut transitions = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if event.name == "connection-state-changed" {
+                transitions.push((
+                    event.payload["state"].clone(),
+                    event.payload["reason"].clone(),
+                ));
+            }
+        }
+        assert_eq!(transitions.first().unwrap().0, "connecting");
+        assert!(transitions.contains(&("tls_negotiating".into(), "handshake".into())));
+        assert_eq!(
+            transitions.last().unwrap(),
+            &("disconnected".into(), "timeout".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_server_hangup_disconnects() {
+        let server = MockServer::bind().await;
+        let (client, _events) = build_client(false);
+        let state = client.state().clone();
+        let mut peer = connect(&client, &server).await;
+        handshake(&mut peer, &["alice", "bob"]).await;
+        wait_until(|| state.client_state.get_users().len() == 2).await;
+
+        drop(peer);
+        wait_until(|| !state.is_connected()).await;
+        assert!(state.client_state.get_users().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_restores_playlist() {
+        let server = MockServer::bind().await;
+        let (client, _events) = build_client(true);
+        let state = client.state().clone();
+        let mut peer = connect(&client, &server).await;
+        handshake(&mut peer, &["alice"]).await;
+        peer.send(mock_server::set(SetMessage {
+            playlist_change: Some(PlaylistChange {
+                user: Some("alice".to_string()),
+                files: vec!["a.mkv".to_string()],
+            }),
+            ..Default::default()
+        }))
+        .await;
+        wait_until(|| state.playlist.len() == 1).await;
+
+        drop(peer);
+        let mut peer = server.accept().await;
+        handshake(&mut peer, &["alice"]).await;
+        let files = peer
+            .expect(|message| match message {
+                ProtocolMessage::Set { Set } => Set.playlist_change.map(|change| change.files),
+                _ => None,
+            })
+            .await;
+        assert_eq!(files, vec!["a.mkv".to_string()]);
+        cancel_reconnect(&state);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_counts_dropped_handshakes() {
+        let server = MockServer::bind().await;
+        let (client, mut events) = build_client(true);
+        client.state().config.lock().user.reconnect_max_attempts = 2;
+        let state = client.state().clone();
+        let mut peer = connect(&client, &server).await;
+        handshake(&mut peer, &["alice"]).await;
+        wait_until(|| state.client_state.get_users().len() == 1).await;
+
+        // Each reconnect reaches the server but is dropped before the Hello reply
+        drop(peer);
+        for _ in 0..2 {
+            let mut peer = server.accept().await;
+            peer.accept_hello("false").await;
+        }
+        wait_until(|| !state.reconnect.lock().active).await;
+        assert!(!state.is_connected());
+
+        let mut messages = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if event.name == "chat-message-received" {
+                messages.push(event.payload["message"].as_str().unwrap_or("").to_string());
+            }
+        }
+        assert!(!messages
+            .iter()
+            .any(|message| message == "Reconnected to server"));
+        assert!(messages
+            .iter()
+            .any(|message| message == "Could not reconnect to server, giving up"));
+    }
+
+    fn set_tls_policy(client: &SyncplayClient, server: &MockServer, policy: TlsPolicy) {
+        client
+            .state()
+            .config
+            .lock()
+            .add_recent_server(ServerConfig {
+                host: "127.0.0.1".to_string(),
+                port: server.port(),
+                tls_policy: policy,
+                ..ServerConfig::default()
+            });
+    }
+
+    async fn connect_with_password(client: &SyncplayClient, server: &MockServer) -> MockPeer {
+        client
+            .connect(
+                "127.0.0.1".to_string(),
+                server.port(),
+                "alice".to_string(),
+                "lobby".to_string(),
+                Some("secret".to_string()),
+            )
+            .await
+            .unwrap();
+        server.accept().await
+    }
+
+    fn tls_statuses(events: &mut mpsc::UnboundedReceiver<ClientEvent>) -> Vec<serde_json::Value> {
+        let mut statuses = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if event.name == "tls-status-changed" {
+                statuses.push(event.payload);
+            }
+        }
+        statuses
+    }
+
+    #[tokio::test]
+    async fn test_required_tls_refuses_plaintext_hello() {
+        let server = MockServer::bind().await;
+        let (client, mut events) = build_client(true);
+        set_tls_policy(&client, &server, TlsPolicy::Required);
+        let mut peer = connect_with_password(&client, &server).await;
+
+        peer.expect(|message| match message {
+            ProtocolMessage::TLS { .. } => Some(()),
+            _ => None,
+        })
+        .await;
+        peer.send(mock_server::tls_answer("false")).await;
+        let received = peer.expect_closed().await;
+        assert!(!received
+            .iter()
+            .any(|message| matches!(message, ProtocolMessage::Hello { .. })));
+
+        let state = client.state().clone();
+        wait_until(|| !state.is_connected()).await;
+        assert!(!state.reconnect.lock().active);
+        let statuses = tls_statuses(&mut events);
+        assert!(statuses.iter().any(|status| status["status"] == "refused"));
+    }
+
+    #[tokio::test]
+    async fn test_preferred_tls_reports_plaintext_password() {
+        let server = MockServer::bind().await;
+        let (client, mut events) = build_client(false);
+        let mut peer = connect_with_password(&client, &server).await;
+
+        let hello = peer.accept_hello("false").await;
+        assert_eq!(hello.password.as_deref(), Some("secret"));
+        let statuses = tls_statuses(&mut events);
+        let downgrade = statuses
+            .iter()
+            .find(|status| status["status"] == "unsupported")
+            .unwrap();
+        assert_eq!(downgrade["passwordInPlaintext"], true);
+        assert_eq!(downgrade["reason"], "Server does not support TLS");
+    }
+
+    #[tokio::test]
+    async fn test_disabled_tls_skips_handshake() {
+        let server = MockServer::bind().await;
+        let (client, _events) = build_client(false);
+        set_tls_policy(&client, &server, TlsPolicy::Disabled);
+        let mut peer = connect(&client, &server).await;
+
+        match peer.recv().await {
+            ProtocolMessage::Hello { Hello } => assert_eq!(Hello.username, "alice"),
+            other => panic!("Expected Hello, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_capabilities_gate_chat() {
+        let server = MockServer::bind().await;
+        let (client, _events) = build_client(false);
+        let state = client.state().clone();
+        let mut peer = connect(&client, &server).await;
+
+        peer.accept_hello("false").await;
+        let mut reply = mock_server::hello_reply("alice", "lobby");
+        if let ProtocolMessage::Hello { Hello } = &mut reply {
+            let features = Hello.features.as_mut().unwrap();
+            features
+                .extra
+                .insert("maxChatMessageLength".to_string(), 5.into());
+            features.extra.insert("readiness".to_string(), false.into());
+        }
+        peer.send(reply).await;
+        wait_until(|| {
+            state
+                .client_state
+                .get_server_capabilities()
+                .max_chat_message_length
+                == 5
+        })
+        .await;
+
+        client.send_chat("hello world".to_string()).await.unwrap();
+        let text = peer
+            .expect(|message| match message {
+                ProtocolMessage::Chat {
+                    Chat: ChatMessage::Text(text),
+                } => Some(text),
+                _ => None,
+            })
+            .await;
+        assert_eq!(text.chars().count(), 5);
+        assert_eq!(
+            client.send_chat("/ready".to_string()).await.unwrap_err(),
+            "The server does not support readiness"
+        );
+
+        client.disconnect().await;
+        assert!(state.client_state.get_server_capabilities().readiness);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_server_message_keeps_session() {
+        let server = MockServer::bind().await;
+        let (client, mut events) = build_client(false);
+        let state = client.state().clone();
+        let mut peer = connect(&client, &server).await;
+        handshake(&mut peer, &["alice"]).await;
+        wait_until(|| state.client_state.get_users().len() == 1).await;
+
+        peer.send(ProtocolMessage::Unknown(
+            "Future".to_string(),
+            serde_json::json!({ "x": 1 }),
+        ))
+        .await;
+        peer.send(mock_server::list(room_list(&["alice", "bob"])))
+            .await;
+        wait_until(|| state.client_state.get_users().len() == 2).await;
+        assert!(client.is_connected());
+
+        let mut diagnostic = None;
+        while let Ok(event) = events.try_recv() {
+            if event.name == "protocol-diagnostic" {
+                diagnostic = Some(event.payload);
+            }
+        }
+        assert_eq!(diagnostic.unwrap()["message"], "Future");
+    }
+}
@@ -0,0 +1,342 @@
+// Registry of concurrent server sessions
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+
+use super::session::SyncplayClient;
+use crate::app_state::AppState;
+use crate::player::controller::load_media_by_name;
+use crate::utils::format_server_address;
+
+/// Id of the session driven by the application-wide `AppState`
+pub const DEFAULT_SESSION_ID: &str = "default";
+
+/// Sessions sharing one client and which of them owns the media player
+pub struct SessionRegistry {
+    /// Held weakly since the default state owns this registry
+    default: Mutex<Weak<AppState>>,
+    /// Sessions opened next to the default one
+    sessions: Mutex<Vec<Arc<AppState>>>,
+    /// Session that drives playback; the others only follow chat and presence
+    active: Mutex<String>,
+    next_id: AtomicU64,
+}
+
+impl SessionRegistry {
+    pub fn new() -> Self {
+        Self {
+            default: Mutex::new(Weak::new()),
+            sessions: Mutex::new(Vec::new()),
+            active: Mutex::new(DEFAULT_SESSION_ID.to_string()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<AppState>> {
+        if id == DEFAULT_SESSION_ID {
+            return self.default.lock().upgrade();
+        }
+        self.sessions
+            .lock()
+            .iter()
+            .find(|session| session.session_id == id)
+            .cloned()
+    }
+
+    /// All sessions, the default one first
+    pub fn all(&self) -> Vec<Arc<AppState>> {
+        let mut sessions: Vec<_> = self.default.lock().upgrade().into_iter().collect();
+        sessions.extend(self.sessions.lock().iter().cloned());
+        sessions
+    }
+
+    pub fn is_open(&self, id: &str) -> bool {
+        id == DEFAULT_SESSION_ID || self.get(id).is_some()
+    }
+
+    pub fn active_id(&self) -> String {
+        self.active.lock().clone()
+    }
+
+    pub fn is_active(&self, id: &str) -> bool {
+        *self.active.lock() == id
+    }
+
+    fn remember_default(&self, state: &Arc<AppState>) {
+        if state.session_id == DEFAULT_SESSION_ID {
+            *self.default.lock() = Arc::downgrade(state);
+        }
+    }
+
+    fn allocate_id(&self) -> String {
+        format!("session-{}", self.next_id.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn remove(&self, id: &str) {
+        self.sessions
+            .lock()
+            .retain(|session| session.session_id != id);
+    }
+}
+
+impl Default for SessionRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What the UI shows for a session in the session switcher
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionSummary {
+    pub id: String,
+    pub server: Option<String>,
+    pub username: String,
+    pub room: String,
+    pub connected: bool,
+    pub active: bool,
+}
+
+fn summarize(state: &Arc<AppState>) -> SessionSummary {
+    let server = state
+        .connection
+        .lock()
+        .as_ref()
+        .map(|connection| format_server_address(&connection.host(), connection.port()));
+    SessionSummary {
+        id: state.session_id.clone(),
+        server,
+        username: state.client_state.get_username(),
+        room: state.client_state.get_room(),
+        connected: state.is_connected(),
+        active: state.is_playback_session(),
+    }
+}
+
+/// Move the player and its process from one session to another
+fn hand_over_player(from: &AppState, to: &AppState) {
+    *to.player.lock() = from.player.lock().take();
+    *to.player_process.lock() = from.player_process.lock().take();
+    *to.mpv_runtime_dir.lock() = from.mpv_runtime_dir.lock().take();
+    *to.mpv_socket_path.lock() = from.mpv_socket_path.lock().take();
+    *to.last_player_spawn.lock() = from.last_player_spawn.lock().take();
+    *to.last_player_kind.lock() = from.last_player_kind.lock().take();
+}
+
+impl SyncplayClient {
+    /// Open another session sharing this client's config, events and player
+    pub fn open_session(&self) -> SyncplayClient {
+        let state = self.state();
+        state.sessions.remember_default(state);
+        let session = state.for_session(state.sessions.allocate_id());
+        session
+            .sync_engine
+            .lock()
+            .update_from_config(&session.config.lock().user);
+        state.sessions.sessions.lock().push(session.clone());
+        tracing::info!("Opened session {}", session.session_id);
+        SyncplayClient::new(session)
+    }
+
+    /// Client for session `id`
+    pub fn session(&self, id: &str) -> Result<SyncplayClient, String> {
+        if id == self.state().session_id {
+            return Ok(self.clone());
+        }
+        self.state()
+            .sessions
+            .get(id)
+            .map(SyncplayClient::new)
+            .ok_or_else(|| format!("Unknown session {}", id))
+    }
+
+    /// Client for the session that currently drives playback
+    pub fn playback_session(&self) -> SyncplayClient {
+        SyncplayClient::new(self.state().playback_session())
+    }
+
+    pub fn sessions(&self) -> Vec<SessionSummary> {
+        let sessions = self.state().sessions.all();
+        if sessions.is_empty() {
+            // No session has been opened yet, so this is the only one
+            return vec![summarize(self.state())];
+        }
+        sessions.iter().map(summarize).collect()
+    }
+
+    /// Give the player to session `id` and load whatever it is watching
+    pub async fn set_active_session(&self, id: &str) -> Result<(), String> {
+        let next = self.session(id)?;
+        let previous = self.state().playback_session();
+        if Arc::ptr_eq(&previous, next.state()) {
+            return Ok(());
+        }
+        tracing::info!(
+            "Switching playback from session {} to {}",
+            previous.session_id,
+            id
+        );
+        hand_over_player(&previous, next.state());
+        *self.state().sessions.active.lock() = id.to_string();
+        next.state().emit_event(
+            "active-session-changed",
+            serde_json::json!({ "sessionId": id }),
+        );
+
+        let state = next.state();
+        let current = state
+            .playlist
+            .get_current_item()
+            .map(|item| item.filename)
+            .or_else(|| state.client_state.get_file());
+        if let Some(filename) = current {
+            if let Err(e) = load_media_by_name(state, &filename, false).await {
+                tracing::warn!("Failed to load {} for session {}: {}", filename, id, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Disconnect session `id` and forget it; the default session stays open
+    pub async fn close_session(&self, id: &str) -> Result<(), String> {
+        if id == DEFAULT_SESSION_ID {
+            return Err("The default session cannot be closed".to_string());
+        }
+        let session = self.session(id)?;
+        if session.state().is_playback_session() {
+            self.set_active_session(DEFAULT_SESSION_ID).await?;
+        }
+        session.disconnect().await;
+        self.state().sessions.remove(id);
+        tracing::info!("Closed session {}", id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::events::{ChannelEventSink, ClientEvent};
+    use crate::config::SyncplayConfig;
+    use crate::network::messages::ProtocolMessage;
+    use crate::network::mock_server::{self, MockServer};
+    use tokio::sync::mpsc;
+
+    fn drain(events: &mut mpsc::UnboundedReceiver<ClientEvent>) -> Vec<ClientEvent> {
+        let mut drained = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            drained.push(event);
+        }
+        drained
+    }
+
+    #[tokio::test]
+    async fn test_sessions_connect_independently() {
+        let mut config = SyncplayConfig::default();
+        config.user.autosave_joins_to_list = false;
+        config.user.auto_reconnect = false;
+        config.player.player_path = "/nonexistent/test-player".to_string();
+        let (sink, mut events) = ChannelEventSink::new();
+        let client = SyncplayClient::headless(config, Arc::new(sink));
+        let second = client.open_session();
+        assert_eq!(second.state().session_id, "session-1");
+
+        let first_server = MockServer::bind().await;
+        let second_server = MockServer::bind().await;
+        for (session, server, room) in [
+            (&client, &first_server, "lobby"),
+            (&second, &second_server, "movies"),
+        ] {
+            session
+                .connect(
+                    "127.0.0.1".to_string(),
+                    server.port(),
+                    "alice".to_string(),
+                    room.to_string(),
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        let mut first_peer = first_server.accept().await;
+        let mut second_peer = second_server.accept().await;
+        first_peer.accept_hello("false").await;
+        let hello = second_peer.accept_hello("false").await;
+        assert_eq!(hello.room.unwrap().name, "movies");
+        second_peer
+            .send(mock_server::hello_reply("alice", "movies"))
+            .await;
+
+        let summaries = client.sessions();
+        assert_eq!(summaries.len(), 2);
+        assert!(summaries[0].active);
+        assert_eq!(summaries[1].room, "movies");
+        assert!(summaries.iter().all(|summary| summary.connected));
+
+        // Every event names the session it came from
+        let drained = drain(&mut events);
+        assert!(drained
+            .iter()
+            .all(|event| event.payload.get("sessionId").is_some()));
+        assert!(drained.iter().any(|event| {
+            event.name == "connection-status-changed" && event.payload["sessionId"] == "session-1"
+        }));
+
+        client.set_active_session("session-1").await.unwrap();
+        assert!(second.state().is_playback_session());
+        assert!(!client.state().is_playback_session());
+        assert!(client.set_active_session("session-9").await.is_err());
+
+        client.close_session("session-1").await.unwrap();
+        assert!(client.state().is_playback_session());
+        assert!(!client.state().sessions.is_open("session-1"));
+        assert!(client.is_connected());
+        assert!(client.close_session(DEFAULT_SESSION_ID).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_monitor_session_only_answers_pings() {
+        let mut config = SyncplayConfig::default();
+        config.user.autosave_joins_to_list = false;
+        config.user.auto_reconnect = false;
+        config.player.player_path = "/nonexistent/test-player".to_string();
+        let (sink, _events) = ChannelEventSink::new();
+        let client = SyncplayClient::headless(config, Arc::new(sink));
+        let second = client.open_session();
+        assert!(!second.state().is_playback_session());
+
+        let server = MockServer::bind().await;
+        second
+            .connect(
+                "127.0.0.1".to_string(),
+                server.port(),
+                "alice".to_string(),
+                "movies".to_string(),
+                None,
+            )
+            .await
+            .unwrap();
+        let mut peer = server.accept().await;
+        peer.accept_hello("false").await;
+        peer.send(mock_server::hello_reply("alice", "movies")).await;
+
+        // The room is playing past the point where a local playstate would seek
+        peer.send(mock_server::state(30.0, false, 42.0)).await;
+        let reply = peer
+            .expect(|message| match message {
+                ProtocolMessage::State { State }
+                    if State
+                        .ping
+                        .as_ref()
+                        .is_some_and(|ping| ping.latency_calculation == Some(42.0)) =>
+                {
+                    Some(State)
+                }
+                _ => None,
+            })
+            .await;
+        assert!(reply.playstate.is_none());
+    }
+}
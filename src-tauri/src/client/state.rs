@@ -1,8 +1,12 @@
+use chrono::{DateTime, Utc};
 use parking_lot::RwLock;
+use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use super::capabilities::ServerCapabilities;
 use crate::network::messages::FileSizeInfo;
+use crate::utils::is_controlled_room;
 
 /// User information
 #[derive(Debug, Clone)]
@@ -14,8 +18,43 @@ pub struct User {
     pub file_duration: Option<f64>,
     pub is_ready: bool,
     pub is_controller: bool,
+    /// Features the user's client advertised, as sent by the server
+    pub features: Option<Value>,
+    /// Client version reported when the user joined
+    pub version: Option<String>,
+    /// When the user joined, if it happened while we were connected
+    pub joined_at: Option<DateTime<Utc>>,
+    /// Last time the user changed anything we were told about
+    pub last_activity: Option<DateTime<Utc>>,
+    /// Seconds the user's playback is ahead of ours, negative when behind.
+    /// Estimated from the room position the server reports on their behalf,
+    /// which it only does for the slowest viewer; everyone else is `None`.
+    pub sync_offset: Option<f64>,
 }
 
+impl User {
+    /// A user known only by name and room
+    pub fn new(username: impl Into<String>, room: impl Into<String>) -> Self {
+        Self {
+            username: username.into(),
+            room: room.into(),
+            file: None,
+            file_size: None,
+            file_duration: None,
+            is_ready: false,
+            is_controller: false,
+            features: None,
+            version: None,
+            joined_at: None,
+            last_activity: None,
+            sync_offset: None,
+        }
+    }
+}
+
+/// Smallest change in a sync offset worth reporting, in seconds
+const SYNC_OFFSET_RESOLUTION: f64 = 0.1;
+
 /// Global playback state
 #[derive(Debug, Clone)]
 pub struct GlobalPlayState {
@@ -42,6 +81,8 @@ pub struct ClientState {
     is_ready: RwLock<bool>,
     /// Server version
     server_version: RwLock<Option<String>>,
+    /// Features advertised by the server
+    server_capabilities: RwLock<ServerCapabilities>,
 }
 
 impl ClientState {
@@ -60,6 +101,7 @@ impl ClientState {
             }),
             is_ready: RwLock::new(false),
             server_version: RwLock::new(None),
+            server_capabilities: RwLock::new(ServerCapabilities::default()),
         })
     }
 
@@ -136,6 +178,59 @@ impl ClientState {
         self.users.write().clear();
     }
 
+    /// Record that `username` just did something; returns whether they are known
+    pub fn touch_user(&self, username: &str) -> bool {
+        match self.users.write().get_mut(username) {
+            Some(user) => {
+                user.last_activity = Some(Utc::now());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Store a new sync offset estimate for `username`.
+    /// Returns true when it differs noticeably from the previous one.
+    pub fn set_user_sync_offset(&self, username: &str, offset: f64) -> bool {
+        let mut users = self.users.write();
+        let Some(user) = users.get_mut(username) else {
+            return false;
+        };
+        let changed = !user
+            .sync_offset
+            .is_some_and(|previous| (previous - offset).abs() < SYNC_OFFSET_RESOLUTION);
+        if changed {
+            user.sync_offset = Some(offset);
+        }
+        changed
+    }
+
+    /// Forget the sync offsets of everyone but `keep`.
+    /// Returns true if any offset was cleared.
+    pub fn clear_sync_offsets_except(&self, keep: Option<&str>) -> bool {
+        let mut cleared = false;
+        for user in self.users.write().values_mut() {
+            if Some(user.username.as_str()) != keep && user.sync_offset.take().is_some() {
+                cleared = true;
+            }
+        }
+        cleared
+    }
+
+    /// Whether the local user may pause, seek and edit the playlist.
+    /// Managed rooms only accept those changes from their operators.
+    pub fn can_control(&self) -> bool {
+        let room = self.get_room();
+        if !is_controlled_room(&room) {
+            return true;
+        }
+        let username = self.get_username();
+        self.users
+            .read()
+            .get(&username)
+            .is_some_and(|user| user.room == room && user.is_controller)
+    }
+
     // Global state methods
     pub fn get_global_state(&self) -> GlobalPlayState {
         self.global_state.read().clone()
@@ -165,6 +260,15 @@ impl ClientState {
     pub fn set_server_version(&self, version: String) {
         *self.server_version.write() = Some(version);
     }
+
+    // Server capability methods
+    pub fn get_server_capabilities(&self) -> ServerCapabilities {
+        self.server_capabilities.read().clone()
+    }
+
+    pub fn set_server_capabilities(&self, capabilities: ServerCapabilities) {
+        *self.server_capabilities.write() = capabilities;
+    }
 }
 
 impl Default for ClientState {
@@ -183,6 +287,7 @@ impl Default for ClientState {
             }),
             is_ready: RwLock::new(false),
             server_version: RwLock::new(None),
+            server_capabilities: RwLock::new(ServerCapabilities::default()),
         }
     }
 }
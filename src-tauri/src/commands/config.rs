@@ -1,6 +1,7 @@
 // Configuration command handlers
 
 use crate::app_state::AppState;
+use crate::client::session::move_room_passwords_to_vault;
 use crate::config::{save_config, SyncplayConfig};
 use std::sync::Arc;
 use tauri::{AppHandle, Runtime, State};
@@ -15,10 +16,11 @@ pub async fn get_config(state: State<'_, Arc<AppState>>) -> Result<SyncplayConfi
 #[tauri::command]
 pub async fn update_config<R: Runtime>(
     app: AppHandle<R>,
-    config: SyncplayConfig,
+    mut config: SyncplayConfig,
     state: State<'_, Arc<AppState>>,
 ) -> Result<(), String> {
     tracing::info!("Updating configuration");
+    move_room_passwords_to_vault(&state, &mut config);
 
     // Validate config
     config.validate().map_err(|e| {
@@ -4,6 +4,8 @@ pub mod connection;
 pub mod player;
 pub mod playlist;
 pub mod room;
+pub mod server;
+pub mod sessions;
 
 pub use chat::*;
 pub use config::*;
@@ -11,3 +13,5 @@ pub use connection::*;
 pub use player::*;
 pub use playlist::*;
 pub use room::*;
+pub use server::*;
+pub use sessions::*;
@@ -1,6 +1,7 @@
 // Playlist command handlers
 
 use crate::app_state::{AppState, PlaylistEvent};
+use crate::client::session::explain_operator_only;
 use crate::network::messages::{PlaylistChange, PlaylistIndexUpdate, ProtocolMessage, SetMessage};
 use crate::player::controller::load_media_by_name;
 use std::sync::Arc;
@@ -13,10 +14,22 @@ pub async fn update_playlist(
     state: State<'_, Arc<AppState>>,
 ) -> Result<(), String> {
     tracing::info!("Playlist action: {} for file: {:?}", action, filename);
+    let state = state.playback_session();
     let config = state.config.lock().clone();
     if !config.user.shared_playlist_enabled {
         return Err("Shared playlists are disabled".to_string());
     }
+    if !state
+        .client_state
+        .get_server_capabilities()
+        .shared_playlists
+    {
+        return Err("The server does not support shared playlists".to_string());
+    }
+    if !state.client_state.can_control() {
+        explain_operator_only(&state, "change the playlist");
+        return Err("Only room operators can change the playlist".to_string());
+    }
     let previous_index = state.playlist.get_current_index();
 
     match action.as_str() {
@@ -105,7 +118,7 @@ pub async fn update_playlist(
 
     if current_index != previous_index {
         if let Some(item) = state.playlist.get_current_item() {
-            if let Err(e) = load_media_by_name(state.inner(), &item.filename, true).await {
+            if let Err(e) = load_media_by_name(&state, &item.filename, true).await {
                 tracing::warn!("Failed to load playlist item: {}", e);
             }
         }
@@ -123,10 +136,7 @@ pub async fn update_playlist(
     Ok(())
 }
 
-fn send_to_server(
-    state: &State<'_, Arc<AppState>>,
-    message: ProtocolMessage,
-) -> Result<(), String> {
+fn send_to_server(state: &Arc<AppState>, message: ProtocolMessage) -> Result<(), String> {
     let connection = state.connection.lock().clone();
     let Some(connection) = connection else {
         return Err("Not connected to server".to_string());
@@ -1,20 +1,26 @@
 // Room command handlers
 
 use crate::app_state::AppState;
-use crate::commands::connection::{reidentify_as_controller, store_control_password};
+use crate::client::room_directory::RoomSummary;
+use crate::client::session::{reidentify_as_controller, store_control_password};
+use crate::commands::sessions::session_client;
 use crate::config::save_config;
+use crate::config::vault::write_private;
 use crate::network::messages::{ProtocolMessage, ReadyState, RoomInfo, SetMessage};
 use crate::utils::parse_controlled_room_input;
+use std::path::Path;
 use std::sync::Arc;
 use tauri::{AppHandle, Runtime, State};
 
 #[tauri::command]
 pub async fn change_room<R: Runtime>(
     room: String,
+    session_id: Option<String>,
     app: AppHandle<R>,
     state: State<'_, Arc<AppState>>,
 ) -> Result<(), String> {
     tracing::info!("Changing to room: {}", room);
+    let state = session_client(&state, session_id)?.state().clone();
 
     // Check if connected
     if !state.is_connected() {
@@ -24,7 +30,7 @@ pub async fn change_room<R: Runtime>(
     let (normalized_room, control_password) = parse_controlled_room_input(&room);
     let room = normalized_room;
     if let Some(password) = control_password {
-        store_control_password(state.inner(), &room, &password, true);
+        store_control_password(&state, &room, &password, true);
     }
 
     // Update client state
@@ -48,7 +54,7 @@ pub async fn change_room<R: Runtime>(
     };
     send_to_server(&state, message)?;
     send_to_server(&state, ProtocolMessage::List { List: None })?;
-    reidentify_as_controller(state.inner());
+    reidentify_as_controller(&state);
 
     let config = state.config.lock().clone();
     if config.user.autosave_joins_to_list {
@@ -67,14 +73,71 @@ pub async fn change_room<R: Runtime>(
     Ok(())
 }
 
+/// Create a managed room and become its operator
 #[tauri::command]
-pub async fn set_ready(is_ready: bool, state: State<'_, Arc<AppState>>) -> Result<(), String> {
+pub async fn create_managed_room(
+    room: String,
+    session_id: Option<String>,
+    state: State<'_, Arc<AppState>>,
+) -> Result<(), String> {
+    session_client(&state, session_id)?.create_managed_room(&room)
+}
+
+/// Rooms on the server with their occupancy and what is playing
+#[tauri::command]
+pub async fn get_room_directory(
+    session_id: Option<String>,
+    state: State<'_, Arc<AppState>>,
+) -> Result<Vec<RoomSummary>, String> {
+    Ok(session_client(&state, session_id)?.room_directory())
+}
+
+/// Rooms with a saved operator password
+#[tauri::command]
+pub async fn list_operator_passwords(
+    state: State<'_, Arc<AppState>>,
+) -> Result<Vec<String>, String> {
+    Ok(session_client(&state, None)?.operator_rooms())
+}
+
+#[tauri::command]
+pub async fn forget_operator_password(
+    room: String,
+    state: State<'_, Arc<AppState>>,
+) -> Result<(), String> {
+    session_client(&state, None)?.forget_operator_password(&room)
+}
+
+/// Write the saved operator credentials to `path` as `room:password` lines
+#[tauri::command]
+pub async fn export_operator_passwords(
+    path: String,
+    state: State<'_, Arc<AppState>>,
+) -> Result<usize, String> {
+    let entries = session_client(&state, None)?.export_operator_passwords();
+    let mut contents = entries.join("\n");
+    contents.push('\n');
+    write_private(Path::new(&path), contents.as_bytes())
+        .map_err(|e| format!("Failed to export operator passwords: {:#}", e))?;
+    Ok(entries.len())
+}
+
+#[tauri::command]
+pub async fn set_ready(
+    is_ready: bool,
+    session_id: Option<String>,
+    state: State<'_, Arc<AppState>>,
+) -> Result<(), String> {
     tracing::info!("Setting ready state to: {}", is_ready);
+    let state = session_client(&state, session_id)?.state().clone();
 
     // Check if connected
     if !state.is_connected() {
         return Err("Not connected to server".to_string());
     }
+    if !state.client_state.get_server_capabilities().readiness {
+        return Err("The server does not support readiness".to_string());
+    }
 
     // Update client state
     state.client_state.set_ready(is_ready);
@@ -103,10 +166,7 @@ pub async fn set_ready(is_ready: bool, state: State<'_, Arc<AppState>>) -> Resul
     Ok(())
 }
 
-fn send_to_server(
-    state: &State<'_, Arc<AppState>>,
-    message: ProtocolMessage,
-) -> Result<(), String> {
+fn send_to_server(state: &Arc<AppState>, message: ProtocolMessage) -> Result<(), String> {
     let connection = state.connection.lock().clone();
     let Some(connection) = connection else {
         return Err("Not connected to server".to_string());
@@ -0,0 +1,56 @@
+// Local server command handlers
+
+use crate::app_state::AppState;
+use crate::server::{ServerOptions, SyncplayServer};
+use std::sync::Arc;
+use tauri::State;
+
+#[tauri::command]
+pub async fn start_local_server(
+    port: u16,
+    password: Option<String>,
+    motd: Option<String>,
+    state: State<'_, Arc<AppState>>,
+) -> Result<String, String> {
+    if state.local_server.lock().is_some() {
+        return Err("Local server is already running".to_string());
+    }
+
+    let options = ServerOptions {
+        password: password.filter(|value| !value.is_empty()),
+        motd: motd.filter(|value| !value.is_empty()),
+        ..Default::default()
+    };
+    let server = SyncplayServer::bind(&format!("0.0.0.0:{}", port), options)
+        .await
+        .map_err(|e| format!("Failed to start local server: {}", e))?;
+    let address = server.local_addr().to_string();
+
+    let rejected = {
+        let mut slot = state.local_server.lock();
+        if slot.is_some() {
+            Some(server)
+        } else {
+            *slot = Some(server);
+            None
+        }
+    };
+    if let Some(server) = rejected {
+        server.shutdown().await;
+        return Err("Local server is already running".to_string());
+    }
+    tracing::info!("Local server started on {}", address);
+    Ok(address)
+}
+
+#[tauri::command]
+pub async fn stop_local_server(state: State<'_, Arc<AppState>>) -> Result<(), String> {
+    let server = state.local_server.lock().take();
+    match server {
+        Some(server) => {
+            server.shutdown().await;
+            Ok(())
+        }
+        None => Err("Local server is not running".to_string()),
+    }
+}
@@ -0,0 +1,57 @@
+// Session command handlers
+
+use crate::app_state::AppState;
+use crate::client::session::SyncplayClient;
+use crate::client::sessions::SessionSummary;
+use std::sync::Arc;
+use tauri::State;
+
+/// Client for `session_id`, or for the active session when none is given
+pub(crate) fn session_client(
+    state: &State<'_, Arc<AppState>>,
+    session_id: Option<String>,
+) -> Result<SyncplayClient, String> {
+    let client = SyncplayClient::new(state.inner().clone());
+    match session_id {
+        Some(id) => client.session(&id),
+        None => Ok(client.playback_session()),
+    }
+}
+
+#[tauri::command]
+pub async fn list_sessions(state: State<'_, Arc<AppState>>) -> Result<Vec<SessionSummary>, String> {
+    Ok(SyncplayClient::new(state.inner().clone()).sessions())
+}
+
+/// Open a session that can connect to another server or room
+#[tauri::command]
+pub async fn open_session(state: State<'_, Arc<AppState>>) -> Result<SessionSummary, String> {
+    let client = SyncplayClient::new(state.inner().clone());
+    let session = client.open_session();
+    client
+        .sessions()
+        .into_iter()
+        .find(|summary| summary.id == session.state().session_id)
+        .ok_or_else(|| "Failed to open session".to_string())
+}
+
+#[tauri::command]
+pub async fn close_session(
+    session_id: String,
+    state: State<'_, Arc<AppState>>,
+) -> Result<(), String> {
+    SyncplayClient::new(state.inner().clone())
+        .close_session(&session_id)
+        .await
+}
+
+/// Hand the player to `session_id`
+#[tauri::command]
+pub async fn set_active_session(
+    session_id: String,
+    state: State<'_, Arc<AppState>>,
+) -> Result<(), String> {
+    SyncplayClient::new(state.inner().clone())
+        .set_active_session(&session_id)
+        .await
+}
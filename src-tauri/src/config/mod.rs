@@ -1,8 +1,10 @@
 pub mod persistence;
 pub mod settings;
+pub mod vault;
 
 pub use persistence::{get_config_path, load_config, save_config};
 pub use settings::{
-    ChatInputPosition, ChatOutputMode, PrivacyMode, PublicServer, ServerConfig, SyncplayConfig,
-    UnpauseAction, UserPreferences,
+    ChatInputPosition, ChatOutputMode, PrivacyMode, ProxyConfig, ProxyType, PublicServer,
+    QueueOverflowPolicy, ServerConfig, SyncplayConfig, TlsPolicy, UnpauseAction, UserPreferences,
 };
+pub use vault::PasswordVault;
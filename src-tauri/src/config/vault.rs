@@ -0,0 +1,209 @@
+// Operator password vault
+// Room operator passwords, kept out of the config store. The key file sits
+// next to the ciphertext, so this only hides the passwords from casual
+// reading: anyone who can read the app data directory can decrypt them.
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const VAULT_FILE: &str = "operator-passwords.vault";
+const KEY_FILE: &str = "operator-passwords.key";
+const VAULT_VERSION: u32 = 1;
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+/// On-disk layout of the vault file
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Store of controlled room passwords, keyed by room name.
+/// Obfuscated rather than protected, see the note at the top of the file.
+pub struct PasswordVault {
+    path: PathBuf,
+    key_path: PathBuf,
+}
+
+impl PasswordVault {
+    /// Vault kept in `dir`, next to its key file
+    pub fn in_dir(dir: &Path) -> Self {
+        Self {
+            path: dir.join(VAULT_FILE),
+            key_path: dir.join(KEY_FILE),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Load the saved passwords; a missing vault is empty
+    pub fn load(&self) -> Result<HashMap<String, String>> {
+        if !self.path.exists() {
+            return Ok(HashMap::new());
+        }
+        let contents = fs::read_to_string(&self.path).context("Failed to read password vault")?;
+        let file: VaultFile =
+            serde_json::from_str(&contents).context("Failed to parse password vault")?;
+        if file.version != VAULT_VERSION {
+            anyhow::bail!("Unsupported password vault version {}", file.version);
+        }
+        let engine = base64::engine::general_purpose::STANDARD;
+        let nonce = engine
+            .decode(&file.nonce)
+            .context("Invalid password vault nonce")?;
+        if nonce.len() != NONCE_LENGTH {
+            anyhow::bail!("Invalid password vault nonce length");
+        }
+        let ciphertext = engine
+            .decode(&file.ciphertext)
+            .context("Invalid password vault contents")?;
+        let key = fs::read(&self.key_path).context("Failed to read password vault key")?;
+        if key.len() != KEY_LENGTH {
+            anyhow::bail!("Invalid password vault key length");
+        }
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt password vault"))?;
+        serde_json::from_slice(&plaintext).context("Failed to parse decrypted password vault")
+    }
+
+    /// Encrypt and write `passwords`, creating the key on first use.
+    /// An existing vault that cannot be read is never replaced.
+    pub fn save(&self, passwords: &HashMap<String, String>) -> Result<()> {
+        if self.path.exists() {
+            self.load()
+                .context("Refusing to overwrite a password vault that cannot be read")?;
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).context("Failed to create password vault directory")?;
+        }
+        let key = self.load_or_create_key()?;
+        let mut nonce = [0u8; NONCE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let plaintext = serde_json::to_vec(passwords).context("Failed to serialize passwords")?;
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt password vault"))?;
+
+        let engine = base64::engine::general_purpose::STANDARD;
+        let file = VaultFile {
+            version: VAULT_VERSION,
+            nonce: engine.encode(nonce),
+            ciphertext: engine.encode(ciphertext),
+        };
+        let contents = serde_json::to_vec(&file).context("Failed to serialize password vault")?;
+        // Write then rename so a crash never leaves a truncated vault
+        let temp_path = self.path.with_extension("vault.tmp");
+        write_private(&temp_path, &contents)?;
+        fs::rename(&temp_path, &self.path).context("Failed to replace password vault")?;
+        Ok(())
+    }
+
+    fn load_or_create_key(&self) -> Result<Vec<u8>> {
+        match fs::read(&self.key_path) {
+            Ok(key) if key.len() == KEY_LENGTH => return Ok(key),
+            Ok(_) => anyhow::bail!("Invalid password vault key length"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).context("Failed to read password vault key"),
+        }
+        // A new key could never decrypt the passwords already saved
+        if self.path.exists() {
+            anyhow::bail!("Password vault key is missing");
+        }
+        let mut key = vec![0u8; KEY_LENGTH];
+        rand::thread_rng().fill_bytes(&mut key);
+        write_private(&self.key_path, &key)?;
+        Ok(key)
+    }
+}
+
+/// Write a file only the current user can read
+pub(crate) fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    // The mode above only applies to new files
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("Failed to restrict {}", path.display()))?;
+    }
+    file.write_all(contents)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vault_round_trip_is_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let vault = PasswordVault::in_dir(dir.path());
+        assert!(vault.load().unwrap().is_empty());
+
+        let passwords =
+            HashMap::from([("+movie:ABCDEF123456".to_string(), "AB-123-456".to_string())]);
+        vault.save(&passwords).unwrap();
+        assert_eq!(vault.load().unwrap(), passwords);
+
+        let contents = fs::read_to_string(vault.path()).unwrap();
+        assert!(!contents.contains("AB-123-456"));
+        assert!(!contents.contains("+movie"));
+    }
+
+    #[test]
+    fn test_vault_rejects_a_different_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let vault = PasswordVault::in_dir(dir.path());
+        vault
+            .save(&HashMap::from([(
+                "room".to_string(),
+                "AB-123-456".to_string(),
+            )]))
+            .unwrap();
+        fs::write(dir.path().join(KEY_FILE), [7u8; KEY_LENGTH]).unwrap();
+        assert!(vault.load().is_err());
+    }
+
+    #[test]
+    fn test_vault_is_not_replaced_when_unreadable() {
+        let dir = tempfile::tempdir().unwrap();
+        let vault = PasswordVault::in_dir(dir.path());
+        vault
+            .save(&HashMap::from([(
+                "room".to_string(),
+                "AB-123-456".to_string(),
+            )]))
+            .unwrap();
+        let saved = fs::read(vault.path()).unwrap();
+
+        fs::write(dir.path().join(KEY_FILE), [7u8; 3]).unwrap();
+        assert!(vault.save(&HashMap::new()).is_err());
+        fs::remove_file(dir.path().join(KEY_FILE)).unwrap();
+        assert!(vault.save(&HashMap::new()).is_err());
+        assert!(!dir.path().join(KEY_FILE).exists());
+        assert_eq!(fs::read(vault.path()).unwrap(), saved);
+    }
+}
@@ -0,0 +1,90 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+pub mod app_state;
+pub mod client;
+pub mod commands;
+pub mod config;
+pub mod network;
+pub mod player;
+pub mod server;
+pub mod tauri_host;
+pub mod utils;
+
+use app_state::AppState;
+use std::sync::Arc;
+use tauri_host::TauriHost;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Build and run the Tauri application
+pub fn run() {
+    // Initialize tracing
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "syncplay_tauri=debug,tower_http=debug".into()),
+        )
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+
+    // Create global app state
+    let app_state = AppState::new();
+
+    tauri::Builder::default()
+        .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_store::Builder::default().build())
+        .manage(app_state.clone())
+        .setup(move |app| {
+            // Route client events and config writes through Tauri
+            let host = Arc::new(TauriHost::new(app.handle().clone()));
+            app_state.set_event_sink(host.clone());
+            app_state.set_host(host);
+            let config = crate::config::load_config(app.handle()).unwrap_or_else(|e| {
+                tracing::error!("Failed to load config: {}", e);
+                crate::config::SyncplayConfig::default()
+            });
+            *app_state.config.lock() = config.clone();
+            crate::client::session::load_saved_room_passwords(&app_state);
+            app_state
+                .sync_engine
+                .lock()
+                .update_from_config(&config.user);
+            let state = app_state.clone();
+            tauri::async_runtime::spawn(async move {
+                crate::player::controller::spawn_player_state_loop(state);
+            });
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            commands::connection::connect_to_server,
+            commands::connection::disconnect_from_server,
+            commands::connection::cancel_connection,
+            commands::sessions::list_sessions,
+            commands::sessions::open_session,
+            commands::sessions::close_session,
+            commands::sessions::set_active_session,
+            commands::connection::get_connection_status,
+            commands::connection::get_connection_metrics,
+            commands::connection::refresh_public_servers,
+            commands::connection::diagnose_server,
+            commands::chat::send_chat_message,
+            commands::room::change_room,
+            commands::room::create_managed_room,
+            commands::room::get_room_directory,
+            commands::room::list_operator_passwords,
+            commands::room::forget_operator_password,
+            commands::room::export_operator_passwords,
+            commands::room::set_ready,
+            commands::playlist::update_playlist,
+            commands::config::get_config,
+            commands::config::update_config,
+            commands::config::get_config_path,
+            commands::player::detect_available_players,
+            commands::player::get_cached_players,
+            commands::player::refresh_player_detection,
+            commands::server::start_local_server,
+            commands::server::stop_local_server,
+        ])
+        .run(tauri::generate_context!())
+        .expect("error while running tauri application");
+}
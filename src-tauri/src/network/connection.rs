@@ -1,30 +1,250 @@
 use anyhow::{Context, Result};
 use futures::{SinkExt, StreamExt};
 use parking_lot::Mutex;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use tokio::net::TcpStream;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot, watch};
 use tokio::time::{Duration, Instant};
-use tokio_util::codec::Framed;
+use tokio_util::codec::{Framed, LinesCodecError};
 use tracing::{debug, error, info, warn};
 
-use super::messages::ProtocolMessage;
-use super::protocol::SyncplayCodec;
-use super::tls::upgrade_to_tls;
+use super::messages::{PingInfo, ProtocolMessage, StateMessage};
+use super::ping::PingService;
+use super::protocol::{SyncplayCodec, DEFAULT_MAX_FRAME_LENGTH};
+use super::proxy::{dial, Proxy};
+use super::recorder::{Direction, TrafficRecorder};
+use super::tls::{upgrade_to_tls, TlsTrust};
 
 /// Connection state
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ConnectionState {
     Disconnected,
     Connecting,
-    Connected,
+    /// Dialing again after the previous connection dropped
+    Reconnecting,
+    /// StartTLS sent; waiting for the answer and the TLS handshake
+    TlsNegotiating,
+    /// Transport is up and the Hello has not been answered yet
+    Authenticating,
     Authenticated,
+    /// No traffic within the idle timeout; a probe is outstanding
+    Stale,
+}
+
+/// Why a connection moved to its current state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionReason {
+    /// Connect, disconnect or cancel asked for by the user
+    UserRequest,
+    /// Next step of the connection handshake
+    Handshake,
+    /// Reconnect attempt after the previous connection dropped
+    Reconnect,
+    /// The server reported an error, then closed the connection
+    ServerError,
+    /// A frame from the server could not be parsed
+    ProtocolError,
+    IoError,
+    TlsFailure,
+    /// A connect, handshake or keepalive deadline passed
+    Timeout,
+}
+
+/// State change published by a `Connection`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConnectionTransition {
+    pub state: ConnectionState,
+    pub reason: TransitionReason,
+    /// Error message or other detail behind the transition
+    pub detail: Option<String>,
+}
+
+impl ConnectionTransition {
+    pub fn new(state: ConnectionState, reason: TransitionReason) -> Self {
+        Self {
+            state,
+            reason,
+            detail: None,
+        }
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+/// Liveness policy for an established connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepaliveConfig {
+    /// Idle time before a probe is sent; zero disables keepalive
+    pub idle_timeout: Duration,
+    /// Time to wait for any traffic after a probe before giving up
+    pub grace_period: Duration,
+}
+
+impl KeepaliveConfig {
+    pub fn new(idle_timeout: Duration, grace_period: Duration) -> Self {
+        Self {
+            idle_timeout,
+            grace_period,
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        !self.idle_timeout.is_zero()
+    }
+
+    fn tick_interval(&self) -> Duration {
+        let shortest = if self.grace_period.is_zero() {
+            self.idle_timeout
+        } else {
+            self.idle_timeout.min(self.grace_period)
+        };
+        (shortest / 2).max(Duration::from_millis(100))
+    }
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(10), Duration::from_secs(5))
+    }
+}
+
+/// Limits on establishing a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectTimeouts {
+    /// TCP connect, including any proxy handshake
+    pub connect: Duration,
+    /// TLS handshake once the server accepted startTLS
+    pub tls_handshake: Duration,
+}
+
+impl Default for ConnectTimeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(10),
+            tls_handshake: Duration::from_secs(10),
+        }
+    }
+}
+
+/// What to do with incoming messages when the client falls behind
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Stop reading from the server until the queue drains. Sends, disconnects
+    /// and the keepalive timer keep running, but server silence is not timed
+    /// while reading is paused
+    Block,
+    /// Keep only the newest pending State message; other messages wait as with `Block`
+    #[default]
+    CoalesceState,
+    /// Drop the connection
+    Disconnect,
+}
+
+/// Buffering limits for a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackpressureConfig {
+    /// Longest accepted protocol line in bytes
+    pub max_frame_length: usize,
+    /// Messages buffered in each direction
+    pub queue_capacity: usize,
+    pub overflow_policy: OverflowPolicy,
+}
+
+impl BackpressureConfig {
+    pub fn new(
+        max_frame_length: usize,
+        queue_capacity: usize,
+        overflow_policy: OverflowPolicy,
+    ) -> Self {
+        Self {
+            max_frame_length,
+            queue_capacity: queue_capacity.max(1),
+            overflow_policy,
+        }
+    }
+}
+
+impl Default for BackpressureConfig {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_FRAME_LENGTH, 256, OverflowPolicy::default())
+    }
+}
+
+/// Counters for messages that were not delivered as sent
+#[derive(Debug, Default)]
+struct ConnectionMetrics {
+    states_coalesced: AtomicU64,
+    states_dropped: AtomicU64,
+    queue_overflows: AtomicU64,
+}
+
+/// Snapshot of a connection's backpressure counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionMetricsSnapshot {
+    /// Incoming State messages replaced by a newer one while the queue was full
+    pub states_coalesced: u64,
+    /// Outgoing State messages dropped because the send queue was full
+    pub states_dropped: u64,
+    /// Times a queue was full when a message arrived
+    pub queue_overflows: u64,
+}
+
+impl ConnectionMetrics {
+    fn snapshot(&self) -> ConnectionMetricsSnapshot {
+        ConnectionMetricsSnapshot {
+            states_coalesced: self.states_coalesced.load(Ordering::Relaxed),
+            states_dropped: self.states_dropped.load(Ordering::Relaxed),
+            queue_overflows: self.queue_overflows.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn keepalive_probe() -> ProtocolMessage {
+    ProtocolMessage::State {
+        State: StateMessage {
+            playstate: None,
+            ping: Some(PingInfo {
+                latency_calculation: None,
+                client_latency_calculation: Some(PingService::new_timestamp()),
+                client_rtt: None,
+                server_rtt: None,
+            }),
+            ignoring_on_the_fly: None,
+        },
+    }
+}
+
+/// Socket failures are I/O errors; anything the codec rejected is a protocol error
+fn receive_failure_reason(error: &anyhow::Error) -> TransitionReason {
+    let io_failure = error.chain().any(|cause| {
+        cause.is::<std::io::Error>()
+            || matches!(
+                cause.downcast_ref::<LinesCodecError>(),
+                Some(LinesCodecError::Io(_))
+            )
+    });
+    if io_failure {
+        TransitionReason::IoError
+    } else {
+        TransitionReason::ProtocolError
+    }
 }
 
 enum ConnectionCommand {
     Send(Box<ProtocolMessage>),
     UpgradeTls {
         domain: String,
-        response: oneshot::Sender<Result<()>>,
+        trust: Box<TlsTrust>,
+        response: oneshot::Sender<Result<Option<String>>>,
     },
     Disconnect,
 }
@@ -53,18 +273,26 @@ impl Transport {
         }
     }
 
-    async fn upgrade_tls(&mut self, domain: &str) -> Result<()> {
+    async fn upgrade_tls(
+        &mut self,
+        domain: &str,
+        trust: &TlsTrust,
+        max_frame_length: usize,
+    ) -> Result<Option<String>> {
         match std::mem::replace(self, Transport::Empty) {
             Transport::Plain(framed) => {
                 let framed = *framed;
                 let stream = framed.into_inner();
-                let tls_stream = upgrade_to_tls(stream, domain).await?;
-                *self = Transport::Tls(Box::new(Framed::new(tls_stream, SyncplayCodec::new())));
-                Ok(())
+                let (tls_stream, fingerprint) = upgrade_to_tls(stream, domain, trust).await?;
+                *self = Transport::Tls(Box::new(Framed::new(
+                    tls_stream,
+                    SyncplayCodec::with_max_length(max_frame_length),
+                )));
+                Ok(fingerprint)
             }
             Transport::Tls(framed) => {
                 *self = Transport::Tls(framed);
-                Ok(())
+                Ok(None)
             }
             Transport::Empty => anyhow::bail!("Transport not initialized"),
         }
@@ -73,24 +301,118 @@ impl Transport {
 
 /// Connection manager for Syncplay protocol
 pub struct Connection {
-    state: Mutex<ConnectionState>,
+    state: Arc<watch::Sender<ConnectionTransition>>,
     host: Mutex<String>,
     port: Mutex<u16>,
-    tx: Mutex<Option<mpsc::UnboundedSender<ConnectionCommand>>>,
+    keepalive: KeepaliveConfig,
+    backpressure: BackpressureConfig,
+    timeouts: ConnectTimeouts,
+    metrics: Mutex<Arc<ConnectionMetrics>>,
+    tls_trust: TlsTrust,
+    proxy: Option<Proxy>,
+    recorder: Option<Arc<TrafficRecorder>>,
+    reconnect_attempt: bool,
+    encrypted: Mutex<bool>,
+    tx: Mutex<Option<mpsc::Sender<ConnectionCommand>>>,
 }
 
 impl Connection {
     pub fn new() -> Self {
+        let (state, _) = watch::channel(ConnectionTransition::new(
+            ConnectionState::Disconnected,
+            TransitionReason::UserRequest,
+        ));
         Self {
-            state: Mutex::new(ConnectionState::Disconnected),
+            state: Arc::new(state),
             host: Mutex::new(String::new()),
             port: Mutex::new(0),
+            keepalive: KeepaliveConfig::default(),
+            backpressure: BackpressureConfig::default(),
+            timeouts: ConnectTimeouts::default(),
+            metrics: Mutex::new(Arc::new(ConnectionMetrics::default())),
+            tls_trust: TlsTrust::default(),
+            proxy: None,
+            recorder: None,
+            reconnect_attempt: false,
+            encrypted: Mutex::new(false),
             tx: Mutex::new(None),
         }
     }
 
+    pub fn with_keepalive(mut self, keepalive: KeepaliveConfig) -> Self {
+        self.keepalive = keepalive;
+        self
+    }
+
+    pub fn with_backpressure(mut self, backpressure: BackpressureConfig) -> Self {
+        self.backpressure = backpressure;
+        self
+    }
+
+    pub fn with_timeouts(mut self, timeouts: ConnectTimeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Backpressure counters for the current connection
+    pub fn metrics(&self) -> ConnectionMetricsSnapshot {
+        self.metrics.lock().snapshot()
+    }
+
+    /// Certificate policy used when the server accepts TLS
+    pub fn with_tls_trust(mut self, trust: TlsTrust) -> Self {
+        self.tls_trust = trust;
+        self
+    }
+
+    /// Proxy to dial through instead of connecting directly
+    pub fn with_proxy(mut self, proxy: Option<Proxy>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Record every frame sent and received on this connection
+    pub fn with_recorder(mut self, recorder: Option<Arc<TrafficRecorder>>) -> Self {
+        self.recorder = recorder;
+        self
+    }
+
+    /// Report `Reconnecting` instead of `Connecting` while dialing
+    pub fn with_reconnect_attempt(mut self, reconnect_attempt: bool) -> Self {
+        self.reconnect_attempt = reconnect_attempt;
+        self
+    }
+
     pub fn state(&self) -> ConnectionState {
-        self.state.lock().clone()
+        self.state.borrow().state
+    }
+
+    /// Latest state change and the reason for it
+    pub fn last_transition(&self) -> ConnectionTransition {
+        self.state.borrow().clone()
+    }
+
+    /// Watch state changes, including keepalive transitions
+    pub fn subscribe_transitions(&self) -> watch::Receiver<ConnectionTransition> {
+        self.state.subscribe()
+    }
+
+    fn transition(&self, transition: ConnectionTransition) {
+        debug!(
+            "Connection state {:?} ({:?})",
+            transition.state, transition.reason
+        );
+        self.state.send_replace(transition);
+    }
+
+    /// Host of the last connection attempt
+    pub fn host(&self) -> String {
+        self.host.lock().clone()
+    }
+
+    /// Port of the last connection attempt
+    pub fn port(&self) -> u16 {
+        *self.port.lock()
     }
 
     /// Connect to a Syncplay server
@@ -98,135 +420,352 @@ impl Connection {
         &self,
         host: String,
         port: u16,
-    ) -> Result<mpsc::UnboundedReceiver<ProtocolMessage>> {
+    ) -> Result<mpsc::Receiver<ProtocolMessage>> {
         info!("Connecting to {}:{}", host, port);
-        *self.state.lock() = ConnectionState::Connecting;
+        self.transition(if self.reconnect_attempt {
+            ConnectionTransition::new(ConnectionState::Reconnecting, TransitionReason::Reconnect)
+        } else {
+            ConnectionTransition::new(ConnectionState::Connecting, TransitionReason::UserRequest)
+        });
         *self.host.lock() = host.clone();
         *self.port.lock() = port;
+        *self.encrypted.lock() = false;
 
-        // Connect TCP stream
-        let stream = TcpStream::connect(format!("{}:{}", host, port))
-            .await
-            .context("Failed to connect to server")?;
+        // Connect TCP stream; `disconnect` while connecting aborts the attempt
+        let mut cancelled = self.state.subscribe();
+        let connect_timeout = self.timeouts.connect;
+        let stream = tokio::select! {
+            result = tokio::time::timeout(connect_timeout, dial(self.proxy.as_ref(), &host, port)) => {
+                match result {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => {
+                        let error = e.context("Failed to connect to server");
+                        self.transition(
+                            ConnectionTransition::new(ConnectionState::Disconnected, TransitionReason::IoError)
+                                .with_detail(format!("{:#}", error)),
+                        );
+                        return Err(error);
+                    }
+                    Err(_) => {
+                        let detail = format!(
+                            "Timed out connecting to server after {}s",
+                            connect_timeout.as_secs()
+                        );
+                        self.transition(
+                            ConnectionTransition::new(ConnectionState::Disconnected, TransitionReason::Timeout)
+                                .with_detail(detail.clone()),
+                        );
+                        anyhow::bail!(detail);
+                    }
+                }
+            }
+            _ = cancelled.wait_for(|transition| transition.state == ConnectionState::Disconnected) => {
+                info!("Connection attempt cancelled");
+                anyhow::bail!("Connection attempt cancelled");
+            }
+        };
 
         info!("TCP connection established");
-        *self.state.lock() = ConnectionState::Connected;
+        self.transition(ConnectionTransition::new(
+            ConnectionState::Authenticating,
+            TransitionReason::Handshake,
+        ));
 
         // Create framed stream with codec
-        let framed = Framed::new(stream, SyncplayCodec::new());
+        let backpressure = self.backpressure;
+        let framed = Framed::new(
+            stream,
+            SyncplayCodec::with_max_length(backpressure.max_frame_length),
+        );
         let mut transport = Transport::Plain(Box::new(framed));
 
-        // Create channels for bidirectional communication
-        let (cmd_tx, mut cmd_rx) = mpsc::unbounded_channel::<ConnectionCommand>();
-        let (msg_tx, msg_rx) = mpsc::unbounded_channel::<ProtocolMessage>();
+        // Create bounded channels for bidirectional communication
+        let (cmd_tx, mut cmd_rx) = mpsc::channel::<ConnectionCommand>(backpressure.queue_capacity);
+        let (msg_tx, msg_rx) = mpsc::channel::<ProtocolMessage>(backpressure.queue_capacity);
 
         *self.tx.lock() = Some(cmd_tx);
+        let metrics = Arc::new(ConnectionMetrics::default());
+        *self.metrics.lock() = metrics.clone();
 
+        let state = self.state.clone();
+        let keepalive = self.keepalive;
+        let tls_timeout = self.timeouts.tls_handshake;
+        let recorder = self.recorder.clone();
+        let record = move |direction: Direction, message: &ProtocolMessage| {
+            if let Some(recorder) = &recorder {
+                if let Err(e) = recorder.record(direction, message) {
+                    warn!("Failed to record frame: {}", e);
+                }
+            }
+        };
         tokio::spawn(async move {
             info!("Connection loop started");
-            let mut idle_tick = tokio::time::interval(Duration::from_secs(10));
+            let mut idle_tick = tokio::time::interval(keepalive.tick_interval());
             let mut last_received = Instant::now();
-            loop {
+            let mut probe_sent_at: Option<Instant> = None;
+            let mut state_before_stale = state.borrow().clone();
+            let mut pending_state: Option<ProtocolMessage> = None;
+            // Message waiting for queue space; the server is not read meanwhile
+            let mut blocked: Option<ProtocolMessage> = None;
+            // Last Error message from the server, which usually hangs up right after
+            let mut server_error: Option<String> = None;
+            let closed_by = loop {
                 tokio::select! {
-                    Some(cmd) = cmd_rx.recv() => {
+                    cmd = cmd_rx.recv() => {
+                        let Some(cmd) = cmd else {
+                            debug!("Connection handle dropped");
+                            break ConnectionTransition::new(ConnectionState::Disconnected, TransitionReason::UserRequest);
+                        };
                         match cmd {
                             ConnectionCommand::Send(msg) => {
+                                record(Direction::Sent, &msg);
                                 if let Err(e) = transport.send(*msg).await {
                                     error!("Failed to send message: {}", e);
-                                    break;
+                                    break ConnectionTransition::new(ConnectionState::Disconnected, TransitionReason::IoError)
+                                        .with_detail(format!("Failed to send message: {:#}", e));
                                 }
                             }
-                            ConnectionCommand::UpgradeTls { domain, response } => {
-                                let result = transport.upgrade_tls(&domain).await;
+                            ConnectionCommand::UpgradeTls { domain, trust, response } => {
+                                let result = tokio::time::timeout(
+                                    tls_timeout,
+                                    transport.upgrade_tls(&domain, &trust, backpressure.max_frame_length),
+                                )
+                                .await
+                                .unwrap_or_else(|_| {
+                                    Err(anyhow::anyhow!(
+                                        "TLS handshake timed out after {}s",
+                                        tls_timeout.as_secs()
+                                    ))
+                                });
                                 let _ = response.send(result);
                             }
                             ConnectionCommand::Disconnect => {
-                                break;
+                                break ConnectionTransition::new(ConnectionState::Disconnected, TransitionReason::UserRequest);
                             }
                         }
                     }
-                    message = transport.next_message() => {
+                    message = transport.next_message(), if blocked.is_none() => {
                         match message {
                             Some(Ok(msg)) => {
+                                record(Direction::Received, &msg);
                                 last_received = Instant::now();
-                                if msg_tx.send(msg).is_err() {
+                                if let ProtocolMessage::Error { Error } = &msg {
+                                    server_error = Some(Error.message.clone());
+                                }
+                                if probe_sent_at.take().is_some() {
+                                    info!("Server traffic resumed");
+                                    state.send_replace(state_before_stale.clone());
+                                }
+                                let forwarded = forward_message(
+                                    &msg_tx,
+                                    msg,
+                                    &mut pending_state,
+                                    &mut blocked,
+                                    backpressure.overflow_policy,
+                                    &metrics,
+                                );
+                                if !forwarded {
                                     warn!("Failed to forward received message");
-                                    break;
+                                    break ConnectionTransition::new(ConnectionState::Disconnected, TransitionReason::UserRequest)
+                                        .with_detail("Received messages are no longer read");
                                 }
                             }
                             Some(Err(e)) => {
                                 error!("Failed to receive message: {}", e);
-                                break;
+                                break match server_error.take() {
+                                    Some(message) => ConnectionTransition::new(ConnectionState::Disconnected, TransitionReason::ServerError)
+                                        .with_detail(message),
+                                    None => ConnectionTransition::new(ConnectionState::Disconnected, receive_failure_reason(&e))
+                                        .with_detail(format!("{:#}", e)),
+                                };
                             }
                             None => {
                                 debug!("Receive task terminated");
-                                break;
+                                break match server_error.take() {
+                                    Some(message) => ConnectionTransition::new(ConnectionState::Disconnected, TransitionReason::ServerError)
+                                        .with_detail(message),
+                                    None => ConnectionTransition::new(ConnectionState::Disconnected, TransitionReason::IoError)
+                                        .with_detail("Server closed the connection"),
+                                };
                             }
                         }
                     }
-                    _ = idle_tick.tick() => {
-                        let idle = last_received.elapsed().as_secs();
-                        if idle >= 10 {
-                            debug!("No server messages received for {}s", idle);
+                    permit = msg_tx.reserve(), if pending_state.is_some() || blocked.is_some() => {
+                        // The coalesced State is older than the blocked message
+                        match (permit, pending_state.take().or_else(|| blocked.take())) {
+                            (Ok(permit), Some(message)) => permit.send(message),
+                            _ => break ConnectionTransition::new(ConnectionState::Disconnected, TransitionReason::UserRequest),
+                        }
+                    }
+                    _ = idle_tick.tick(), if keepalive.enabled() => {
+                        if blocked.is_some() {
+                            // Unread server traffic is not silence
+                            last_received = Instant::now();
+                        } else if let Some(sent_at) = probe_sent_at {
+                            if sent_at.elapsed() >= keepalive.grace_period {
+                                warn!(
+                                    "No response to keepalive within {:?}, dropping connection",
+                                    keepalive.grace_period
+                                );
+                                break ConnectionTransition::new(ConnectionState::Disconnected, TransitionReason::Timeout)
+                                    .with_detail("Server stopped responding");
+                            }
+                        } else if last_received.elapsed() >= keepalive.idle_timeout {
+                            debug!(
+                                "No server messages received for {}s, sending keepalive",
+                                last_received.elapsed().as_secs()
+                            );
+                            let probe = keepalive_probe();
+                            record(Direction::Sent, &probe);
+                            if let Err(e) = transport.send(probe).await {
+                                error!("Failed to send keepalive: {}", e);
+                                break ConnectionTransition::new(ConnectionState::Disconnected, TransitionReason::IoError)
+                                    .with_detail(format!("Failed to send keepalive: {:#}", e));
+                            }
+                            probe_sent_at = Some(Instant::now());
+                            state_before_stale = state.borrow().clone();
+                            state.send_replace(
+                                ConnectionTransition::new(ConnectionState::Stale, TransitionReason::Timeout)
+                                    .with_detail(format!(
+                                        "No traffic for {}s",
+                                        last_received.elapsed().as_secs()
+                                    )),
+                            );
                         }
                     }
                 }
-            }
+            };
+            // `close` already published its own reason
+            state.send_if_modified(|current| {
+                if current.state == ConnectionState::Disconnected {
+                    return false;
+                }
+                *current = closed_by;
+                true
+            });
         });
 
         Ok(msg_rx)
     }
 
     /// Send a message to the server
+    ///
+    /// When the send queue is full, State messages are dropped (the next one
+    /// supersedes them) and anything else fails.
     pub fn send(&self, message: ProtocolMessage) -> Result<()> {
-        if let Some(tx) = self.tx.lock().as_ref() {
-            tx.send(ConnectionCommand::Send(Box::new(message)))
-                .context("Failed to send message to connection")?;
-            Ok(())
-        } else {
+        let Some(tx) = self.tx.lock().clone() else {
             anyhow::bail!("Not connected");
+        };
+        if self.is_establishing() {
+            match &message {
+                ProtocolMessage::TLS { .. } => self.transition(ConnectionTransition::new(
+                    ConnectionState::TlsNegotiating,
+                    TransitionReason::Handshake,
+                )),
+                ProtocolMessage::Hello { .. } => self.transition(ConnectionTransition::new(
+                    ConnectionState::Authenticating,
+                    TransitionReason::Handshake,
+                )),
+                _ => {}
+            }
+        }
+        match tx.try_send(ConnectionCommand::Send(Box::new(message))) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(command)) => {
+                let metrics = self.metrics.lock().clone();
+                metrics.queue_overflows.fetch_add(1, Ordering::Relaxed);
+                match command {
+                    ConnectionCommand::Send(message)
+                        if matches!(*message, ProtocolMessage::State { .. }) =>
+                    {
+                        metrics.states_dropped.fetch_add(1, Ordering::Relaxed);
+                        debug!("Send queue full, dropping State message");
+                        Ok(())
+                    }
+                    _ => anyhow::bail!("Send queue is full"),
+                }
+            }
+            Err(TrySendError::Closed(_)) => {
+                anyhow::bail!("Failed to send message to connection")
+            }
         }
     }
 
-    /// Upgrade connection to TLS
-    pub async fn upgrade_tls(&self) -> Result<()> {
+    /// Upgrade connection to TLS, returning the server certificate fingerprint
+    pub async fn upgrade_tls(&self) -> Result<Option<String>> {
         let (tx, rx) = oneshot::channel();
         let domain = self.host.lock().clone();
-        if let Some(cmd_tx) = self.tx.lock().as_ref() {
-            cmd_tx
-                .send(ConnectionCommand::UpgradeTls {
-                    domain,
-                    response: tx,
-                })
-                .context("Failed to send upgrade TLS command")?;
-        } else {
+        let Some(cmd_tx) = self.tx.lock().clone() else {
             anyhow::bail!("Not connected");
-        }
+        };
+        cmd_tx
+            .send(ConnectionCommand::UpgradeTls {
+                domain,
+                trust: Box::new(self.tls_trust.clone()),
+                response: tx,
+            })
+            .await
+            .map_err(|_| anyhow::anyhow!("Failed to send upgrade TLS command"))?;
+
+        let fingerprint = rx.await.context("TLS upgrade response dropped")??;
+        *self.encrypted.lock() = true;
+        Ok(fingerprint)
+    }
 
-        rx.await.context("TLS upgrade response dropped")?
+    /// Whether the transport has been upgraded to TLS
+    pub fn is_encrypted(&self) -> bool {
+        *self.encrypted.lock()
     }
 
-    /// Disconnect from the server
+    /// Disconnect from the server at the user's request
     pub fn disconnect(&self) {
-        info!("Disconnecting from server");
-        if let Some(tx) = self.tx.lock().as_ref() {
-            let _ = tx.send(ConnectionCommand::Disconnect);
+        self.close(ConnectionTransition::new(
+            ConnectionState::Disconnected,
+            TransitionReason::UserRequest,
+        ));
+    }
+
+    /// Disconnect from the server, publishing `transition` as the cause
+    pub fn close(&self, transition: ConnectionTransition) {
+        info!("Disconnecting from server ({:?})", transition.reason);
+        // Dropping the sender also stops the loop if the queue is full
+        if let Some(tx) = self.tx.lock().take() {
+            let _ = tx.try_send(ConnectionCommand::Disconnect);
         }
-        *self.tx.lock() = None;
-        *self.state.lock() = ConnectionState::Disconnected;
+        self.transition(ConnectionTransition {
+            state: ConnectionState::Disconnected,
+            ..transition
+        });
+    }
+
+    /// Whether the connection is still being established (no Hello reply yet)
+    pub fn is_establishing(&self) -> bool {
+        matches!(
+            self.state(),
+            ConnectionState::Connecting
+                | ConnectionState::Reconnecting
+                | ConnectionState::TlsNegotiating
+                | ConnectionState::Authenticating
+        )
     }
 
     /// Check if connected
     pub fn is_connected(&self) -> bool {
         matches!(
-            *self.state.lock(),
-            ConnectionState::Connected | ConnectionState::Authenticated
+            self.state(),
+            ConnectionState::TlsNegotiating
+                | ConnectionState::Authenticating
+                | ConnectionState::Authenticated
+                | ConnectionState::Stale
         )
     }
 
-    /// Mark as authenticated
+    /// Mark as authenticated once the server has answered the Hello
     pub fn set_authenticated(&self) {
-        *self.state.lock() = ConnectionState::Authenticated;
+        self.transition(ConnectionTransition::new(
+            ConnectionState::Authenticated,
+            TransitionReason::Handshake,
+        ));
     }
 }
 
@@ -235,3 +774,386 @@ impl Default for Connection {
         Self::new()
     }
 }
+
+/// Queue a received message for the client, applying the overflow policy.
+/// A message that has to wait is parked in `blocked` for the connection loop
+/// to deliver once there is room. Returns false when the connection should close.
+fn forward_message(
+    msg_tx: &mpsc::Sender<ProtocolMessage>,
+    mut message: ProtocolMessage,
+    pending_state: &mut Option<ProtocolMessage>,
+    blocked: &mut Option<ProtocolMessage>,
+    policy: OverflowPolicy,
+    metrics: &ConnectionMetrics,
+) -> bool {
+    // A coalesced State must go out before anything newer
+    if let Some(pending) = pending_state.take() {
+        match msg_tx.try_send(pending) {
+            Ok(()) => {}
+            Err(TrySendError::Full(pending)) => *pending_state = Some(pending),
+            Err(TrySendError::Closed(_)) => return false,
+        }
+    }
+    if pending_state.is_none() {
+        match msg_tx.try_send(message) {
+            Ok(()) => return true,
+            Err(TrySendError::Full(rejected)) => message = rejected,
+            Err(TrySendError::Closed(_)) => return false,
+        }
+    }
+
+    metrics.queue_overflows.fetch_add(1, Ordering::Relaxed);
+    match policy {
+        OverflowPolicy::Disconnect => {
+            warn!("Receive queue is full, dropping connection");
+            false
+        }
+        OverflowPolicy::CoalesceState if matches!(message, ProtocolMessage::State { .. }) => {
+            if pending_state.replace(message).is_some() {
+                metrics.states_coalesced.fetch_add(1, Ordering::Relaxed);
+            }
+            true
+        }
+        _ => {
+            *blocked = Some(message);
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::proxy::ProxyKind;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn fast_keepalive() -> KeepaliveConfig {
+        KeepaliveConfig::new(Duration::from_millis(100), Duration::from_millis(200))
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_drops_silent_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut lines = BufReader::new(socket).lines();
+            // Hand the socket back so it stays open until the keepalive gives up
+            (lines.next_line().await.unwrap(), lines)
+        });
+
+        let connection = Connection::new().with_keepalive(fast_keepalive());
+        let mut states = connection.subscribe_transitions();
+        let mut receiver = connection
+            .connect("127.0.0.1".to_string(), port)
+            .await
+            .unwrap();
+
+        let (probe, _socket) = server.await.unwrap();
+        let probe = probe.expect("probe line");
+        assert!(probe.contains("\"State\""));
+        assert!(probe.contains("clientLatencyCalculation"));
+
+        states
+            .wait_for(|transition| transition.state == ConnectionState::Stale)
+            .await
+            .unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_secs(2), receiver.recv())
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let transition = connection.last_transition();
+        assert_eq!(transition.state, ConnectionState::Disconnected);
+        assert_eq!(transition.reason, TransitionReason::Timeout);
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_recovers_when_server_answers() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (read, mut write) = socket.into_split();
+            let mut lines = BufReader::new(read).lines();
+            while let Ok(Some(_)) = lines.next_line().await {
+                let reply = "{\"State\": {\"ping\": {\"latencyCalculation\": 1.0}}}\r\n";
+                write.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+
+        let connection = Connection::new().with_keepalive(fast_keepalive());
+        let mut states = connection.subscribe_transitions();
+        let mut receiver = connection
+            .connect("127.0.0.1".to_string(), port)
+            .await
+            .unwrap();
+
+        states
+            .wait_for(|transition| transition.state == ConnectionState::Stale)
+            .await
+            .unwrap();
+        assert!(receiver.recv().await.is_some());
+        assert!(connection.is_connected());
+        assert_ne!(connection.state(), ConnectionState::Disconnected);
+        connection.disconnect();
+    }
+
+    /// Server writing `lines` as soon as a client connects, then staying open
+    async fn flood_server(lines: Vec<String>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            for line in lines {
+                socket.write_all(line.as_bytes()).await.unwrap();
+            }
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+        port
+    }
+
+    fn state_line(position: u32) -> String {
+        format!(
+            "{{\"State\": {{\"playstate\": {{\"position\": {}, \"paused\": true}}}}}}\r\n",
+            position
+        )
+    }
+
+    fn limited(capacity: usize, policy: OverflowPolicy) -> Connection {
+        Connection::new()
+            .with_keepalive(KeepaliveConfig::new(Duration::ZERO, Duration::ZERO))
+            .with_backpressure(BackpressureConfig::new(1024, capacity, policy))
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_coalesces_state_messages() {
+        let mut lines: Vec<String> = (1..=10).map(state_line).collect();
+        lines.push("{\"Chat\": {\"username\": \"bob\", \"message\": \"hi\"}}\r\n".to_string());
+        let port = flood_server(lines).await;
+
+        let connection = limited(2, OverflowPolicy::CoalesceState);
+        let mut receiver = connection
+            .connect("127.0.0.1".to_string(), port)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let mut received = Vec::new();
+        for _ in 0..4 {
+            let message = tokio::time::timeout(Duration::from_secs(2), receiver.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            received.push(match message {
+                ProtocolMessage::State { State } => State.playstate.unwrap().position.to_string(),
+                ProtocolMessage::Chat { .. } => "chat".to_string(),
+                other => panic!("Unexpected message {:?}", other),
+            });
+        }
+        assert_eq!(received, vec!["1", "2", "10", "chat"]);
+        assert_eq!(connection.metrics().states_coalesced, 7);
+        assert!(connection.is_connected());
+        connection.disconnect();
+    }
+
+    #[tokio::test]
+    async fn test_coalesced_state_is_flushed_when_server_goes_quiet() {
+        let port = flood_server((1..=10).map(state_line).collect()).await;
+
+        let connection = limited(2, OverflowPolicy::CoalesceState);
+        let mut receiver = connection
+            .connect("127.0.0.1".to_string(), port)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            let message = tokio::time::timeout(Duration::from_secs(2), receiver.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            match message {
+                ProtocolMessage::State { State } => {
+                    received.push(State.playstate.unwrap().position)
+                }
+                other => panic!("Unexpected message {:?}", other),
+            }
+        }
+        assert_eq!(received, vec![1.0, 2.0, 10.0]);
+        connection.disconnect();
+    }
+
+    #[tokio::test]
+    async fn test_blocked_queue_still_sends() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sent_tx, sent_rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = socket.into_split();
+            for position in 1..=5 {
+                writer
+                    .write_all(state_line(position).as_bytes())
+                    .await
+                    .unwrap();
+            }
+            let mut line = String::new();
+            BufReader::new(reader).read_line(&mut line).await.unwrap();
+            let _ = sent_tx.send(line);
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+
+        let connection = limited(1, OverflowPolicy::Block);
+        let _receiver = connection
+            .connect("127.0.0.1".to_string(), port)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        connection.send(keepalive_probe()).unwrap();
+        let line = tokio::time::timeout(Duration::from_secs(2), sent_rx)
+            .await
+            .expect("send stalled behind a full receive queue")
+            .unwrap();
+        assert!(line.contains("State"), "{}", line);
+        connection.disconnect();
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_disconnects_with_disconnect_policy() {
+        let port = flood_server((1..=5).map(state_line).collect()).await;
+
+        let connection = limited(1, OverflowPolicy::Disconnect);
+        let mut states = connection.subscribe_transitions();
+        let mut receiver = connection
+            .connect("127.0.0.1".to_string(), port)
+            .await
+            .unwrap();
+        states
+            .wait_for(|transition| transition.state == ConnectionState::Disconnected)
+            .await
+            .unwrap();
+        assert!(receiver.recv().await.is_some());
+        assert!(receiver.recv().await.is_none());
+        assert!(connection.metrics().queue_overflows >= 1);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frame_drops_connection() {
+        let line = format!("{{\"Chat\": \"{}\"}}\r\n", "x".repeat(2048));
+        let port = flood_server(vec![line]).await;
+
+        let connection = limited(4, OverflowPolicy::Block);
+        let mut receiver = connection
+            .connect("127.0.0.1".to_string(), port)
+            .await
+            .unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_secs(2), receiver.recv())
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            connection.last_transition().reason,
+            TransitionReason::ProtocolError
+        );
+    }
+
+    #[tokio::test]
+    async fn test_server_error_is_reported_as_close_reason() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let line = "{\"Error\": {\"message\": \"Wrong password\"}}\r\n";
+            socket.write_all(line.as_bytes()).await.unwrap();
+        });
+
+        let connection = limited(4, OverflowPolicy::Block);
+        let mut states = connection.subscribe_transitions();
+        let mut receiver = connection
+            .connect("127.0.0.1".to_string(), port)
+            .await
+            .unwrap();
+        assert_eq!(connection.state(), ConnectionState::Authenticating);
+        assert!(receiver.recv().await.is_some());
+        let transition = states
+            .wait_for(|transition| transition.state == ConnectionState::Disconnected)
+            .await
+            .unwrap()
+            .clone();
+        assert_eq!(transition.reason, TransitionReason::ServerError);
+        assert_eq!(transition.detail.as_deref(), Some("Wrong password"));
+    }
+
+    /// SOCKS5 proxy that accepts TCP connections and never answers
+    async fn silent_proxy() -> Proxy {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+        Proxy {
+            kind: ProxyKind::Socks5,
+            host: "127.0.0.1".to_string(),
+            port,
+            username: None,
+            password: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connect_times_out_and_can_be_cancelled() {
+        let connection = Connection::new()
+            .with_proxy(Some(silent_proxy().await))
+            .with_timeouts(ConnectTimeouts {
+                connect: Duration::from_millis(200),
+                ..ConnectTimeouts::default()
+            });
+        let error = connection
+            .connect("syncplay.example".to_string(), 8999)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Timed out"), "{:#}", error);
+        assert_eq!(connection.state(), ConnectionState::Disconnected);
+        assert_eq!(
+            connection.last_transition().reason,
+            TransitionReason::Timeout
+        );
+
+        let connection = Arc::new(Connection::new().with_proxy(Some(silent_proxy().await)));
+        let mut states = connection.subscribe_transitions();
+        let attempt = tokio::spawn({
+            let connection = connection.clone();
+            async move {
+                connection
+                    .connect("syncplay.example".to_string(), 8999)
+                    .await
+            }
+        });
+        states
+            .wait_for(|transition| transition.state == ConnectionState::Connecting)
+            .await
+            .unwrap();
+        assert!(connection.is_establishing());
+        connection.disconnect();
+        let error = tokio::time::timeout(Duration::from_secs(2), attempt)
+            .await
+            .expect("cancel did not abort the attempt")
+            .unwrap()
+            .unwrap_err();
+        assert!(error.to_string().contains("cancelled"), "{:#}", error);
+        assert_eq!(
+            connection.last_transition().reason,
+            TransitionReason::UserRequest
+        );
+    }
+}
@@ -0,0 +1,154 @@
+// RFC 8305 style connection establishment across all resolved addresses
+
+use anyhow::{Context, Result};
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{lookup_host, TcpStream};
+use tracing::debug;
+
+/// Head start each attempt gets before the next address is tried
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Resolve `host` and connect to whichever A/AAAA record answers first
+pub async fn connect(host: &str, port: u16) -> Result<TcpStream> {
+    let addresses: Vec<SocketAddr> = lookup_host((host, port))
+        .await
+        .with_context(|| format!("Failed to resolve {}", host))?
+        .collect();
+    if addresses.is_empty() {
+        anyhow::bail!("{} did not resolve to any address", host);
+    }
+    connect_addresses(interleave_families(addresses), CONNECTION_ATTEMPT_DELAY).await
+}
+
+/// Alternate address families, starting with the resolver's first choice
+fn interleave_families(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let prefer_v6 = addresses[0].is_ipv6();
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) = addresses
+        .into_iter()
+        .partition(|address| address.is_ipv6() == prefer_v6);
+    let mut ordered = Vec::with_capacity(preferred.len() + other.len());
+    preferred.reverse();
+    other.reverse();
+    loop {
+        match (preferred.pop(), other.pop()) {
+            (None, None) => break,
+            (first, second) => ordered.extend(first.into_iter().chain(second)),
+        }
+    }
+    ordered
+}
+
+async fn attempt(address: SocketAddr) -> (SocketAddr, io::Result<TcpStream>) {
+    (address, TcpStream::connect(address).await)
+}
+
+/// Start an attempt per address, each `delay` after the previous one or as
+/// soon as it fails, and keep the first stream that connects
+async fn connect_addresses(addresses: Vec<SocketAddr>, delay: Duration) -> Result<TcpStream> {
+    let mut pending = addresses.into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = None;
+    loop {
+        if attempts.is_empty() {
+            match pending.next() {
+                Some(address) => attempts.push(attempt(address)),
+                None => break,
+            }
+        }
+        tokio::select! {
+            Some((address, result)) = attempts.next() => match result {
+                Ok(stream) => {
+                    debug!("Connected to {}", address);
+                    return Ok(stream);
+                }
+                Err(e) => {
+                    debug!("Connection attempt to {} failed: {}", address, e);
+                    last_error = Some(anyhow::Error::new(e).context(format!("Failed to connect to {}", address)));
+                    if let Some(address) = pending.next() {
+                        attempts.push(attempt(address));
+                    }
+                }
+            },
+            _ = tokio::time::sleep(delay), if pending.len() > 0 => {
+                if let Some(address) = pending.next() {
+                    attempts.push(attempt(address));
+                }
+            }
+        }
+    }
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No addresses to connect to")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use tokio::net::TcpListener;
+
+    fn address(value: &str) -> SocketAddr {
+        value.parse().unwrap()
+    }
+
+    async fn closed_port() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    #[test]
+    fn test_interleave_families() {
+        let ordered = interleave_families(vec![
+            address("[2001:db8::1]:8999"),
+            address("[2001:db8::2]:8999"),
+            address("[2001:db8::3]:8999"),
+            address("192.0.2.1:8999"),
+        ]);
+        assert_eq!(
+            ordered,
+            vec![
+                address("[2001:db8::1]:8999"),
+                address("192.0.2.1:8999"),
+                address("[2001:db8::2]:8999"),
+                address("[2001:db8::3]:8999"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_skips_unreachable_addresses() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap();
+
+        // TEST-NET-1 never answers (or fails fast without a route)
+        let started = Instant::now();
+        let stream = connect_addresses(
+            vec![address("192.0.2.1:8999"), closed_port().await, open],
+            Duration::from_millis(50),
+        )
+        .await
+        .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), open);
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        let error = connect_addresses(
+            vec![closed_port().await, closed_port().await],
+            Duration::from_millis(50),
+        )
+        .await
+        .unwrap_err();
+        assert!(format!("{:#}", error).contains("Failed to connect to 127.0.0.1"));
+    }
+
+    #[tokio::test]
+    async fn test_connect_to_ipv6_literal() {
+        // Not every sandbox has IPv6 loopback
+        let Ok(listener) = TcpListener::bind("[::1]:0").await else {
+            return;
+        };
+        let port = listener.local_addr().unwrap().port();
+        let stream = connect("::1", port).await.unwrap();
+        assert!(stream.peer_addr().unwrap().is_ipv6());
+    }
+}
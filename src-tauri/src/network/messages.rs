@@ -1,8 +1,9 @@
-use serde::de::{self, Deserializer};
+use serde::de::{self, Deserializer, MapAccess, Visitor};
 use serde::ser::{SerializeMap, Serializer};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::fmt;
 
 /// Main protocol message envelope
 #[derive(Debug, Clone)]
@@ -30,6 +31,8 @@ pub enum ProtocolMessage {
     List {
         List: Option<ListResponse>,
     },
+    /// Message type this client does not know, kept as raw JSON
+    Unknown(String, Value),
 }
 
 impl Serialize for ProtocolMessage {
@@ -46,20 +49,94 @@ impl Serialize for ProtocolMessage {
             ProtocolMessage::Error { Error } => map.serialize_entry("Error", Error)?,
             ProtocolMessage::TLS { TLS } => map.serialize_entry("TLS", TLS)?,
             ProtocolMessage::List { List } => map.serialize_entry("List", List)?,
+            ProtocolMessage::Unknown(name, value) => map.serialize_entry(name, value)?,
         }
         map.end()
     }
 }
 
+impl ProtocolMessage {
+    /// Decode a protocol frame into one message per top-level key, so a new
+    /// key next to a known one does not break the whole frame
+    pub fn decode_frame(line: &str) -> serde_json::Result<Vec<Self>> {
+        let FrameEntries(entries) = serde_json::from_str(line)?;
+        entries
+            .into_iter()
+            .map(|(key, value)| Self::from_entry(key, value))
+            .collect()
+    }
+
+    fn from_entry(key: String, value: Value) -> serde_json::Result<Self> {
+        Ok(match key.as_str() {
+            "Hello" => ProtocolMessage::Hello {
+                Hello: serde_json::from_value(value)?,
+            },
+            "Set" => ProtocolMessage::Set {
+                Set: Box::new(serde_json::from_value(value)?),
+            },
+            "State" => ProtocolMessage::State {
+                State: serde_json::from_value(value)?,
+            },
+            "Chat" => ProtocolMessage::Chat {
+                Chat: serde_json::from_value(value)?,
+            },
+            "Error" => ProtocolMessage::Error {
+                Error: serde_json::from_value(value)?,
+            },
+            "TLS" => ProtocolMessage::TLS {
+                TLS: serde_json::from_value(value)?,
+            },
+            "List" if value.is_null() => ProtocolMessage::List { List: None },
+            "List" => ProtocolMessage::List {
+                List: Some(serde_json::from_value(value)?),
+            },
+            _ => ProtocolMessage::Unknown(key, value),
+        })
+    }
+}
+
+/// Top-level entries of a frame in wire order; `Value::Object` would sort them
+struct FrameEntries(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for FrameEntries {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct EntriesVisitor;
+
+        impl<'de> Visitor<'de> for EntriesVisitor {
+            type Value = FrameEntries;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a protocol message object")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(FrameEntries(entries))
+            }
+        }
+
+        deserializer.deserialize_map(EntriesVisitor)
+    }
+}
+
 impl<'de> Deserialize<'de> for ProtocolMessage {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
         let value = Value::deserialize(deserializer)?;
-        let obj = value
-            .as_object()
-            .ok_or_else(|| de::Error::custom("Protocol message must be a JSON object"))?;
+        let Value::Object(obj) = value else {
+            return Err(de::Error::custom("Protocol message must be a JSON object"));
+        };
 
         if obj.len() != 1 {
             return Err(de::Error::custom(
@@ -67,47 +144,8 @@ impl<'de> Deserialize<'de> for ProtocolMessage {
             ));
         }
 
-        let (key, val) = obj.iter().next().unwrap();
-        match key.as_str() {
-            "Hello" => {
-                let message = serde_json::from_value(val.clone()).map_err(de::Error::custom)?;
-                Ok(ProtocolMessage::Hello { Hello: message })
-            }
-            "Set" => {
-                let message = serde_json::from_value(val.clone()).map_err(de::Error::custom)?;
-                Ok(ProtocolMessage::Set {
-                    Set: Box::new(message),
-                })
-            }
-            "State" => {
-                let message = serde_json::from_value(val.clone()).map_err(de::Error::custom)?;
-                Ok(ProtocolMessage::State { State: message })
-            }
-            "Chat" => {
-                let message = serde_json::from_value(val.clone()).map_err(de::Error::custom)?;
-                Ok(ProtocolMessage::Chat { Chat: message })
-            }
-            "Error" => {
-                let message = serde_json::from_value(val.clone()).map_err(de::Error::custom)?;
-                Ok(ProtocolMessage::Error { Error: message })
-            }
-            "TLS" => {
-                let message = serde_json::from_value(val.clone()).map_err(de::Error::custom)?;
-                Ok(ProtocolMessage::TLS { TLS: message })
-            }
-            "List" => {
-                if val.is_null() {
-                    Ok(ProtocolMessage::List { List: None })
-                } else {
-                    let list = serde_json::from_value(val.clone()).map_err(de::Error::custom)?;
-                    Ok(ProtocolMessage::List { List: Some(list) })
-                }
-            }
-            _ => Err(de::Error::custom(format!(
-                "Unknown protocol message: {}",
-                key
-            ))),
-        }
+        let (key, val) = obj.into_iter().next().unwrap();
+        Self::from_entry(key, val).map_err(de::Error::custom)
     }
 }
 
@@ -147,10 +185,13 @@ pub struct ClientFeatures {
     pub managed_rooms: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub persistent_rooms: Option<bool>,
+    /// Server-side feature flags (isolateRooms, maxChatMessageLength, ...)
+    #[serde(flatten, skip_serializing_if = "HashMap::is_empty", default)]
+    pub extra: HashMap<String, Value>,
 }
 
 /// Set message - update settings
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SetMessage {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -398,4 +439,39 @@ mod tests {
             _ => panic!("Unexpected message type"),
         }
     }
+
+    #[test]
+    fn test_decode_frame_keeps_wire_order() {
+        let messages = ProtocolMessage::decode_frame(
+            r#"{"State": {"ping": {"latencyCalculation": 1.5}}, "Set": {}, "Hello": {"username": "bob", "version": "1.2.7", "realversion": "1.7.4"}, "Chat": {"username": "bob", "message": "hi"}}"#,
+        )
+        .unwrap();
+        let order: Vec<&str> = messages
+            .iter()
+            .map(|message| match message {
+                ProtocolMessage::State { .. } => "State",
+                ProtocolMessage::Set { .. } => "Set",
+                ProtocolMessage::Hello { .. } => "Hello",
+                ProtocolMessage::Chat { .. } => "Chat",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(order, vec!["State", "Set", "Hello", "Chat"]);
+        assert!(ProtocolMessage::decode_frame("[1]").is_err());
+    }
+
+    #[test]
+    fn test_unknown_message_round_trip() {
+        let json = r#"{"Ignoring":{"feature":"future","level":2}}"#;
+        let message: ProtocolMessage = serde_json::from_str(json).unwrap();
+        match &message {
+            ProtocolMessage::Unknown(name, payload) => {
+                assert_eq!(name, "Ignoring");
+                assert_eq!(payload["level"], 2);
+            }
+            _ => panic!("Unexpected message type"),
+        }
+        let value = serde_json::to_value(&message).unwrap();
+        assert_eq!(value, serde_json::from_str::<Value>(json).unwrap());
+    }
 }
@@ -0,0 +1,201 @@
+// Scriptable Syncplay peer for protocol tests
+
+use futures::{SinkExt, StreamExt};
+use std::net::SocketAddr;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{timeout, Duration};
+use tokio_util::codec::Framed;
+
+use super::messages::{
+    ClientFeatures, HelloMessage, ListResponse, PingInfo, PlayState, ProtocolMessage, RoomInfo,
+    SetMessage, StateMessage, TLSMessage,
+};
+use super::protocol::SyncplayCodec;
+
+const RECV_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Fake server listening on a local port
+pub struct MockServer {
+    listener: TcpListener,
+}
+
+impl MockServer {
+    pub async fn bind() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind mock server");
+        Self { listener }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.listener.local_addr().expect("no local address").port()
+    }
+
+    /// Wait for the client under test to connect
+    pub async fn accept(&self) -> MockPeer {
+        let (stream, _) = timeout(RECV_TIMEOUT, self.listener.accept())
+            .await
+            .expect("timed out waiting for client")
+            .expect("failed to accept client");
+        MockPeer::new(stream)
+    }
+}
+
+/// One end of a Syncplay connection driven by a test
+pub struct MockPeer {
+    framed: Framed<TcpStream, SyncplayCodec>,
+}
+
+impl MockPeer {
+    pub fn new(stream: TcpStream) -> Self {
+        Self {
+            framed: Framed::new(stream, SyncplayCodec::new()),
+        }
+    }
+
+    pub async fn connect(addr: SocketAddr) -> Self {
+        let stream = TcpStream::connect(addr)
+            .await
+            .expect("failed to connect mock peer");
+        Self::new(stream)
+    }
+
+    pub async fn send(&mut self, message: ProtocolMessage) {
+        self.framed
+            .send(message)
+            .await
+            .expect("failed to send frame");
+    }
+
+    /// Send a raw line, for frames the typed model cannot express
+    pub async fn send_raw(&mut self, line: &str) {
+        let stream = self.framed.get_mut();
+        stream
+            .write_all(format!("{}\r\n", line).as_bytes())
+            .await
+            .expect("failed to send raw frame");
+    }
+
+    pub async fn recv(&mut self) -> ProtocolMessage {
+        timeout(RECV_TIMEOUT, self.framed.next())
+            .await
+            .expect("timed out waiting for frame")
+            .expect("connection closed")
+            .expect("invalid frame")
+    }
+
+    /// Skip frames until `matcher` accepts one
+    pub async fn expect<T>(&mut self, mut matcher: impl FnMut(ProtocolMessage) -> Option<T>) -> T {
+        let wait = async {
+            loop {
+                let message = self
+                    .framed
+                    .next()
+                    .await
+                    .expect("connection closed")
+                    .expect("invalid frame");
+                if let Some(value) = matcher(message) {
+                    return value;
+                }
+            }
+        };
+        timeout(RECV_TIMEOUT, wait)
+            .await
+            .expect("timed out waiting for matching frame")
+    }
+
+    /// Wait until the other side hangs up, returning the frames received meanwhile
+    pub async fn expect_closed(&mut self) -> Vec<ProtocolMessage> {
+        let wait = async {
+            let mut received = Vec::new();
+            loop {
+                match self.framed.next().await {
+                    None | Some(Err(_)) => return received,
+                    Some(Ok(message)) => received.push(message),
+                }
+            }
+        };
+        timeout(RECV_TIMEOUT, wait)
+            .await
+            .expect("connection was not closed")
+    }
+
+    /// Answer an optional startTLS request and return the client's Hello
+    pub async fn accept_hello(&mut self, start_tls_answer: &str) -> HelloMessage {
+        match self.recv().await {
+            ProtocolMessage::TLS { .. } => {
+                self.send(tls_answer(start_tls_answer)).await;
+                self.expect(|message| match message {
+                    ProtocolMessage::Hello { Hello } => Some(Hello),
+                    _ => None,
+                })
+                .await
+            }
+            ProtocolMessage::Hello { Hello } => Hello,
+            other => panic!("Expected TLS or Hello, got {:?}", other),
+        }
+    }
+}
+
+pub fn tls_answer(answer: &str) -> ProtocolMessage {
+    ProtocolMessage::TLS {
+        TLS: TLSMessage {
+            start_tls: Some(answer.to_string()),
+        },
+    }
+}
+
+pub fn hello_reply(username: &str, room: &str) -> ProtocolMessage {
+    ProtocolMessage::Hello {
+        Hello: HelloMessage {
+            username: username.to_string(),
+            password: None,
+            room: Some(RoomInfo {
+                name: room.to_string(),
+                password: None,
+            }),
+            version: "1.2.255".to_string(),
+            realversion: "1.7.4".to_string(),
+            features: Some(ClientFeatures {
+                shared_playlists: None,
+                chat: Some(true),
+                ready_state: None,
+                managed_rooms: Some(true),
+                persistent_rooms: Some(false),
+                extra: Default::default(),
+            }),
+            motd: None,
+        },
+    }
+}
+
+pub fn list(users: ListResponse) -> ProtocolMessage {
+    ProtocolMessage::List { List: Some(users) }
+}
+
+pub fn set(message: SetMessage) -> ProtocolMessage {
+    ProtocolMessage::Set {
+        Set: Box::new(message),
+    }
+}
+
+pub fn state(position: f64, paused: bool, latency_calculation: f64) -> ProtocolMessage {
+    ProtocolMessage::State {
+        State: StateMessage {
+            playstate: Some(PlayState {
+                position,
+                paused,
+                do_seek: None,
+                set_by: None,
+            }),
+            ping: Some(PingInfo {
+                latency_calculation: Some(latency_calculation),
+                client_latency_calculation: None,
+                client_rtt: None,
+                server_rtt: Some(0.0),
+            }),
+            ignoring_on_the_fly: None,
+        },
+    }
+}
@@ -1,5 +1,11 @@
 pub mod connection;
+pub mod happy_eyeballs;
 pub mod messages;
+#[cfg(test)]
+pub mod mock_server;
 pub mod ping;
 pub mod protocol;
+pub mod proxy;
+pub mod reconnect;
+pub mod recorder;
 pub mod tls;
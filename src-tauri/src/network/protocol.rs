@@ -1,20 +1,34 @@
 use anyhow::{Context, Result};
 use bytes::BytesMut;
 use serde_json;
-use tokio_util::codec::{Decoder, Encoder, LinesCodec};
+use std::collections::VecDeque;
+use tokio_util::codec::{Decoder, Encoder, LinesCodec, LinesCodecError};
 
 use super::messages::ProtocolMessage;
 
+/// Longest protocol line accepted by default
+pub const DEFAULT_MAX_FRAME_LENGTH: usize = 256 * 1024;
+
 /// Syncplay JSON protocol codec
 /// Messages are newline-delimited JSON
 pub struct SyncplayCodec {
     lines_codec: LinesCodec,
+    max_length: usize,
+    /// Further messages from a frame with several top-level keys
+    pending: VecDeque<ProtocolMessage>,
 }
 
 impl SyncplayCodec {
     pub fn new() -> Self {
+        Self::with_max_length(DEFAULT_MAX_FRAME_LENGTH)
+    }
+
+    /// Codec rejecting lines longer than `max_length` bytes
+    pub fn with_max_length(max_length: usize) -> Self {
         Self {
-            lines_codec: LinesCodec::new(),
+            lines_codec: LinesCodec::new_with_max_length(max_length),
+            max_length,
+            pending: VecDeque::new(),
         }
     }
 }
@@ -30,23 +44,35 @@ impl Decoder for SyncplayCodec {
     type Error = anyhow::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>> {
-        // Decode line
-        let line = match self.lines_codec.decode(src)? {
-            Some(line) => line,
-            None => return Ok(None),
-        };
-
-        // Skip empty lines
-        if line.trim().is_empty() {
-            return Ok(None);
+        if let Some(message) = self.pending.pop_front() {
+            return Ok(Some(message));
+        }
+        loop {
+            // Decode line
+            let line = match self.lines_codec.decode(src) {
+                Ok(Some(line)) => line,
+                Ok(None) => return Ok(None),
+                Err(LinesCodecError::MaxLineLengthExceeded) => {
+                    anyhow::bail!("Protocol message exceeds {} bytes", self.max_length)
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            // Skip empty lines; keep going so buffered messages are not stalled
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            // Parse JSON
+            let messages =
+                ProtocolMessage::decode_frame(&line).context("Failed to parse protocol message")?;
+
+            tracing::debug!("Received: {}", line);
+            self.pending.extend(messages);
+            if let Some(message) = self.pending.pop_front() {
+                return Ok(Some(message));
+            }
         }
-
-        // Parse JSON
-        let message: ProtocolMessage =
-            serde_json::from_str(&line).context("Failed to parse protocol message")?;
-
-        tracing::debug!("Received: {}", line);
-        Ok(Some(message))
     }
 }
 
@@ -89,6 +115,7 @@ mod tests {
                     ready_state: Some(true),
                     managed_rooms: Some(false),
                     persistent_rooms: Some(false),
+                    extra: Default::default(),
                 }),
                 motd: None,
             },
@@ -100,4 +127,57 @@ mod tests {
         let parsed: ProtocolMessage = serde_json::from_str(&json).unwrap();
         println!("Parsed: {:?}", parsed);
     }
+
+    #[test]
+    fn test_decode_skips_blank_lines_and_keeps_unknown_messages() {
+        let mut codec = SyncplayCodec::new();
+        let mut buffer =
+            BytesMut::from("\r\n{\"Future\":{\"x\":1}}\r\n\r\n{\"List\":null}\r\n".as_bytes());
+
+        match codec.decode(&mut buffer).unwrap() {
+            Some(ProtocolMessage::Unknown(name, _)) => assert_eq!(name, "Future"),
+            other => panic!("Expected unknown message, got {:?}", other),
+        }
+        match codec.decode(&mut buffer).unwrap() {
+            Some(ProtocolMessage::List { List: None }) => {}
+            other => panic!("Expected List, got {:?}", other),
+        }
+        assert!(codec.decode(&mut buffer).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_decode_splits_known_and_unknown_keys() {
+        let mut codec = SyncplayCodec::new();
+        let mut buffer = BytesMut::from(
+            "{\"State\":{\"ping\":{\"latencyCalculation\":1.5}},\"Extension\":{\"x\":1}}\r\n"
+                .as_bytes(),
+        );
+
+        let mut messages = Vec::new();
+        while let Some(message) = codec.decode(&mut buffer).unwrap() {
+            messages.push(message);
+        }
+        assert_eq!(messages.len(), 2);
+        for message in messages {
+            match message {
+                ProtocolMessage::State { State } => {
+                    assert_eq!(State.ping.unwrap().latency_calculation, Some(1.5));
+                }
+                ProtocolMessage::Unknown(name, payload) => {
+                    assert_eq!(name, "Extension");
+                    assert_eq!(payload["x"], 1);
+                }
+                other => panic!("Unexpected message {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_rejects_oversized_lines() {
+        let mut codec = SyncplayCodec::with_max_length(16);
+        let mut buffer =
+            BytesMut::from(format!("{{\"Chat\":\"{}\"}}\r\n", "x".repeat(32)).as_bytes());
+        let error = codec.decode(&mut buffer).unwrap_err();
+        assert!(error.to_string().contains("16 bytes"), "{}", error);
+    }
 }
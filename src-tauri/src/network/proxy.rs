@@ -0,0 +1,388 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use std::net::IpAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::info;
+
+use super::happy_eyeballs;
+
+/// Largest HTTP CONNECT response header we are willing to read
+const MAX_HTTP_RESPONSE: usize = 8 * 1024;
+
+/// Proxy protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyKind {
+    Socks5,
+    Http,
+}
+
+/// Proxy the transport dials through before talking to the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proxy {
+    pub kind: ProxyKind,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Proxy {
+    fn credentials(&self) -> Option<(&str, &str)> {
+        self.username
+            .as_deref()
+            .filter(|username| !username.is_empty())
+            .map(|username| (username, self.password.as_deref().unwrap_or_default()))
+    }
+}
+
+/// Open a TCP stream to `host:port`, directly or through `proxy`
+pub async fn dial(proxy: Option<&Proxy>, host: &str, port: u16) -> Result<TcpStream> {
+    let Some(proxy) = proxy else {
+        return happy_eyeballs::connect(host, port).await;
+    };
+
+    info!(
+        "Connecting to {}:{} through {:?} proxy {}:{}",
+        host, port, proxy.kind, proxy.host, proxy.port
+    );
+    let mut stream = happy_eyeballs::connect(&proxy.host, proxy.port)
+        .await
+        .with_context(|| format!("Failed to reach proxy {}:{}", proxy.host, proxy.port))?;
+    match proxy.kind {
+        ProxyKind::Socks5 => socks5_connect(&mut stream, proxy, host, port).await?,
+        ProxyKind::Http => http_connect(&mut stream, proxy, host, port).await?,
+    }
+    Ok(stream)
+}
+
+async fn socks5_connect(
+    stream: &mut TcpStream,
+    proxy: &Proxy,
+    host: &str,
+    port: u16,
+) -> Result<()> {
+    let credentials = proxy.credentials();
+    let greeting: &[u8] = if credentials.is_some() {
+        &[0x05, 0x02, 0x00, 0x02]
+    } else {
+        &[0x05, 0x01, 0x00]
+    };
+    stream.write_all(greeting).await?;
+
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    if choice[0] != 0x05 {
+        anyhow::bail!("Proxy is not a SOCKS5 server");
+    }
+    match (choice[1], credentials) {
+        (0x00, _) => {}
+        (0x02, Some((username, password))) => {
+            if username.len() > 255 || password.len() > 255 {
+                anyhow::bail!("SOCKS5 credentials are too long");
+            }
+            let mut request = vec![0x01, username.len() as u8];
+            request.extend_from_slice(username.as_bytes());
+            request.push(password.len() as u8);
+            request.extend_from_slice(password.as_bytes());
+            stream.write_all(&request).await?;
+
+            let mut status = [0u8; 2];
+            stream.read_exact(&mut status).await?;
+            if status[1] != 0x00 {
+                anyhow::bail!("SOCKS5 proxy rejected the credentials");
+            }
+        }
+        (0x02, None) => anyhow::bail!("SOCKS5 proxy requires a username and password"),
+        _ => anyhow::bail!("SOCKS5 proxy offered no acceptable authentication method"),
+    }
+
+    let mut request = vec![0x05, 0x01, 0x00];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(0x01);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(0x04);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            if host.len() > 255 {
+                anyhow::bail!("Host name is too long for SOCKS5: {}", host);
+            }
+            request.push(0x03);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0x00 {
+        anyhow::bail!(
+            "SOCKS5 proxy could not connect to {}:{}: {}",
+            host,
+            port,
+            socks5_error(reply[1])
+        );
+    }
+    // Skip the bound address
+    let address_len = match reply[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => stream.read_u8().await? as usize,
+        other => anyhow::bail!("SOCKS5 proxy returned unknown address type {}", other),
+    };
+    let mut bound = vec![0u8; address_len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+fn socks5_error(code: u8) -> &'static str {
+    match code {
+        0x01 => "general failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+async fn http_connect(stream: &mut TcpStream, proxy: &Proxy, host: &str, port: u16) -> Result<()> {
+    let authority = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(_)) => format!("[{}]:{}", host, port),
+        _ => format!("{}:{}", host, port),
+    };
+    let mut request = format!(
+        "CONNECT {0} HTTP/1.1\r\nHost: {0}\r\nProxy-Connection: Keep-Alive\r\n",
+        authority
+    );
+    if let Some((username, password)) = proxy.credentials() {
+        let token =
+            base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", username, password));
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read byte by byte so no server data is consumed past the header
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_HTTP_RESPONSE {
+            anyhow::bail!("HTTP proxy response header is too large");
+        }
+        response.push(
+            stream
+                .read_u8()
+                .await
+                .context("HTTP proxy closed the connection")?,
+        );
+    }
+
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok());
+    match status {
+        Some(code) if (200..300).contains(&code) => Ok(()),
+        Some(407) => anyhow::bail!("HTTP proxy requires authentication ({})", status_line),
+        _ => anyhow::bail!(
+            "HTTP proxy refused CONNECT to {}: {}",
+            authority,
+            status_line
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncBufReadExt;
+    use tokio::net::TcpListener;
+
+    /// Target server greeting every client, standing in for a Syncplay server
+    async fn spawn_target() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let _ = socket.write_all(b"hello").await;
+            }
+        });
+        port
+    }
+
+    async fn read_greeting(mut stream: TcpStream) -> String {
+        let mut greeting = [0u8; 5];
+        stream.read_exact(&mut greeting).await.unwrap();
+        String::from_utf8_lossy(&greeting).to_string()
+    }
+
+    /// Minimal SOCKS5 proxy requiring `alice:secret` and only resolving `localhost`
+    async fn spawn_socks5_proxy() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut client, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut header = [0u8; 2];
+                    client.read_exact(&mut header).await.unwrap();
+                    let mut methods = vec![0u8; header[1] as usize];
+                    client.read_exact(&mut methods).await.unwrap();
+                    if !methods.contains(&0x02) {
+                        client.write_all(&[0x05, 0xFF]).await.unwrap();
+                        return;
+                    }
+                    client.write_all(&[0x05, 0x02]).await.unwrap();
+
+                    let mut auth = [0u8; 2];
+                    client.read_exact(&mut auth).await.unwrap();
+                    let mut username = vec![0u8; auth[1] as usize];
+                    client.read_exact(&mut username).await.unwrap();
+                    let mut password = vec![0u8; client.read_u8().await.unwrap() as usize];
+                    client.read_exact(&mut password).await.unwrap();
+                    let accepted = username == b"alice" && password == b"secret";
+                    client
+                        .write_all(&[0x01, if accepted { 0x00 } else { 0x01 }])
+                        .await
+                        .unwrap();
+                    if !accepted {
+                        return;
+                    }
+
+                    let mut request = [0u8; 4];
+                    client.read_exact(&mut request).await.unwrap();
+                    assert_eq!(request[3], 0x03, "expected a domain name target");
+                    let mut name = vec![0u8; client.read_u8().await.unwrap() as usize];
+                    client.read_exact(&mut name).await.unwrap();
+                    let target_port = client.read_u16().await.unwrap();
+                    assert_eq!(name, b"localhost");
+
+                    let mut upstream = TcpStream::connect(("127.0.0.1", target_port))
+                        .await
+                        .unwrap();
+                    client
+                        .write_all(&[0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1, 0, 0])
+                        .await
+                        .unwrap();
+                    let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+                });
+            }
+        });
+        port
+    }
+
+    /// Minimal HTTP CONNECT proxy requiring `alice:secret` basic auth
+    async fn spawn_http_proxy() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((client, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut client = tokio::io::BufReader::new(client);
+                    let mut request_line = String::new();
+                    client.read_line(&mut request_line).await.unwrap();
+                    let mut authorized = false;
+                    loop {
+                        let mut line = String::new();
+                        client.read_line(&mut line).await.unwrap();
+                        if line == "\r\n" {
+                            break;
+                        }
+                        authorized |= line.trim() == "Proxy-Authorization: Basic YWxpY2U6c2VjcmV0";
+                    }
+                    let mut client = client.into_inner();
+                    if !authorized {
+                        client
+                            .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+                            .await
+                            .unwrap();
+                        return;
+                    }
+                    let target = request_line.split_whitespace().nth(1).unwrap().to_string();
+                    let mut upstream = TcpStream::connect(target).await.unwrap();
+                    client
+                        .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                        .await
+                        .unwrap();
+                    let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+                });
+            }
+        });
+        port
+    }
+
+    fn proxy(kind: ProxyKind, port: u16, password: &str) -> Proxy {
+        Proxy {
+            kind,
+            host: "127.0.0.1".to_string(),
+            port,
+            username: Some("alice".to_string()),
+            password: Some(password.to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_socks5_proxy() {
+        let target = spawn_target().await;
+        let proxy_port = spawn_socks5_proxy().await;
+
+        let stream = dial(
+            Some(&proxy(ProxyKind::Socks5, proxy_port, "secret")),
+            "localhost",
+            target,
+        )
+        .await
+        .unwrap();
+        assert_eq!(read_greeting(stream).await, "hello");
+
+        let error = dial(
+            Some(&proxy(ProxyKind::Socks5, proxy_port, "wrong")),
+            "localhost",
+            target,
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("rejected the credentials"));
+
+        let anonymous = Proxy {
+            username: None,
+            password: None,
+            ..proxy(ProxyKind::Socks5, proxy_port, "")
+        };
+        assert!(dial(Some(&anonymous), "localhost", target).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_http_connect_proxy() {
+        let target = spawn_target().await;
+        let proxy_port = spawn_http_proxy().await;
+
+        let stream = dial(
+            Some(&proxy(ProxyKind::Http, proxy_port, "secret")),
+            "127.0.0.1",
+            target,
+        )
+        .await
+        .unwrap();
+        assert_eq!(read_greeting(stream).await, "hello");
+
+        let error = dial(
+            Some(&proxy(ProxyKind::Http, proxy_port, "wrong")),
+            "127.0.0.1",
+            target,
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("407"), "{}", error);
+    }
+}
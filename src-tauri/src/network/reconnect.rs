@@ -0,0 +1,141 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_MULTIPLIER: f64 = 2.0;
+const DEFAULT_JITTER: f64 = 0.2;
+
+/// Exponential backoff with jitter for reconnect attempts
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial_delay: Duration,
+    max_delay: Duration,
+    multiplier: f64,
+    jitter: f64,
+    max_attempts: Option<u32>,
+    attempt: u32,
+    seed: u64,
+}
+
+impl Backoff {
+    pub fn new(initial_delay: Duration, max_delay: Duration) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Self {
+            initial_delay,
+            max_delay,
+            multiplier: DEFAULT_MULTIPLIER,
+            jitter: DEFAULT_JITTER,
+            max_attempts: None,
+            attempt: 0,
+            seed: (nanos ^ u64::from(std::process::id())) | 1,
+        }
+    }
+
+    /// Jitter as a fraction of the delay (0.2 spreads delays by +/-20%)
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Limit the number of attempts; `None` retries forever
+    pub fn with_max_attempts(mut self, max_attempts: Option<u32>) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Number of delays handed out so far
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    /// Delay before the next attempt, or `None` once attempts are exhausted
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if let Some(max_attempts) = self.max_attempts {
+            if self.attempt >= max_attempts {
+                return None;
+            }
+        }
+
+        let exponent = self.attempt.min(32) as i32;
+        let base = (self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent))
+            .min(self.max_delay.as_secs_f64());
+        self.attempt += 1;
+
+        let spread = self.jitter * (2.0 * self.next_random() - 1.0);
+        let delay = (base * (1.0 + spread)).clamp(0.0, self.max_delay.as_secs_f64());
+        Some(Duration::from_secs_f64(delay))
+    }
+
+    fn next_random(&mut self) -> f64 {
+        // xorshift64*, good enough to spread clients apart
+        let mut x = self.seed;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.seed = x;
+        let value = x.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        (value >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_exponentially_without_jitter() {
+        let mut backoff =
+            Backoff::new(Duration::from_secs(1), Duration::from_secs(60)).with_jitter(0.0);
+        let delays: Vec<u64> = (0..4)
+            .map(|_| backoff.next_delay().unwrap().as_secs())
+            .collect();
+        assert_eq!(delays, vec![1, 2, 4, 8]);
+    }
+
+    #[test]
+    fn test_backoff_caps_at_max_delay() {
+        let mut backoff =
+            Backoff::new(Duration::from_secs(1), Duration::from_secs(5)).with_jitter(0.5);
+        for _ in 0..20 {
+            assert!(backoff.next_delay().unwrap() <= Duration::from_secs(5));
+        }
+    }
+
+    #[test]
+    fn test_backoff_jitter_stays_in_range() {
+        let mut backoff = Backoff::new(Duration::from_secs(10), Duration::from_secs(100))
+            .with_multiplier(1.0)
+            .with_jitter(0.2);
+        for _ in 0..50 {
+            let delay = backoff.next_delay().unwrap().as_secs_f64();
+            assert!(
+                (8.0..=12.0).contains(&delay),
+                "delay out of range: {}",
+                delay
+            );
+        }
+    }
+
+    #[test]
+    fn test_backoff_max_attempts() {
+        let mut backoff = Backoff::new(Duration::from_millis(10), Duration::from_secs(1))
+            .with_max_attempts(Some(2));
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_none());
+
+        backoff.reset();
+        assert_eq!(backoff.attempt(), 0);
+        assert!(backoff.next_delay().is_some());
+    }
+}
@@ -0,0 +1,175 @@
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use super::messages::ProtocolMessage;
+
+/// Placeholder written instead of passwords
+const REDACTED: &str = "<redacted>";
+
+/// Which way a frame travelled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// One line of a traffic recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    /// Milliseconds since the recording started, from a monotonic clock
+    pub elapsed_ms: f64,
+    pub direction: Direction,
+    pub message: ProtocolMessage,
+}
+
+/// Appends every frame of a connection to a JSONL file
+pub struct TrafficRecorder {
+    path: PathBuf,
+    started: Instant,
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl TrafficRecorder {
+    /// Create (or truncate) a recording at `path`
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create recording {}", path.display()))?;
+        Ok(Self {
+            path,
+            started: Instant::now(),
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write one frame, with passwords removed
+    pub fn record(&self, direction: Direction, message: &ProtocolMessage) -> Result<()> {
+        let frame = RecordedFrame {
+            elapsed_ms: self.started.elapsed().as_secs_f64() * 1000.0,
+            direction,
+            message: redact(message),
+        };
+        let line = serde_json::to_string(&frame)?;
+        let mut writer = self.writer.lock();
+        writer.write_all(line.as_bytes())?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Read a recording written by [`TrafficRecorder`]
+pub fn read_recording(path: impl AsRef<Path>) -> Result<Vec<RecordedFrame>> {
+    let path = path.as_ref();
+    let file =
+        File::open(path).with_context(|| format!("Failed to open recording {}", path.display()))?;
+    let mut frames = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let frame = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid frame on line {}", index + 1))?;
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+/// Copy of `message` without server, room or controller passwords
+fn redact(message: &ProtocolMessage) -> ProtocolMessage {
+    let mut message = message.clone();
+    let hide = |password: &mut Option<String>| {
+        if password.is_some() {
+            *password = Some(REDACTED.to_string());
+        }
+    };
+    match &mut message {
+        ProtocolMessage::Hello { Hello } => {
+            hide(&mut Hello.password);
+            if let Some(room) = Hello.room.as_mut() {
+                hide(&mut room.password);
+            }
+        }
+        ProtocolMessage::Set { Set } => {
+            if let Some(room) = Set.room.as_mut() {
+                hide(&mut room.password);
+            }
+            if let Some(auth) = Set.controller_auth.as_mut() {
+                hide(&mut auth.password);
+            }
+            if let Some(room) = Set.new_controlled_room.as_mut() {
+                hide(&mut room.password);
+            }
+        }
+        _ => {}
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::messages::{ChatMessage, HelloMessage, RoomInfo};
+
+    #[test]
+    fn test_recording_round_trip_redacts_passwords() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let recorder = TrafficRecorder::create(&path).unwrap();
+
+        let hello = ProtocolMessage::Hello {
+            Hello: HelloMessage {
+                username: "alice".to_string(),
+                password: Some("hunter2".to_string()),
+                room: Some(RoomInfo {
+                    name: "lobby".to_string(),
+                    password: None,
+                }),
+                version: "1.2.255".to_string(),
+                realversion: "1.7.4".to_string(),
+                features: None,
+                motd: None,
+            },
+        };
+        recorder.record(Direction::Sent, &hello).unwrap();
+        recorder
+            .record(
+                Direction::Received,
+                &ProtocolMessage::Chat {
+                    Chat: ChatMessage::Text("hi".to_string()),
+                },
+            )
+            .unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("hunter2"));
+
+        let frames = read_recording(&path).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].direction, Direction::Sent);
+        assert_eq!(frames[1].direction, Direction::Received);
+        assert!(frames[1].elapsed_ms >= frames[0].elapsed_ms);
+        match &frames[0].message {
+            ProtocolMessage::Hello { Hello } => {
+                assert_eq!(Hello.password.as_deref(), Some(REDACTED));
+                assert_eq!(Hello.username, "alice");
+            }
+            other => panic!("Expected Hello, got {:?}", other),
+        }
+    }
+}
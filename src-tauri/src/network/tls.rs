@@ -1,11 +1,88 @@
-use anyhow::Result;
-use rustls::{ClientConfig, RootCertStore};
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, ClientConfig, RootCertStore, ServerName};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::SystemTime;
 use tokio::net::TcpStream;
 use tokio_rustls::{client::TlsStream, TlsConnector};
 
-/// Create a TLS connector with system root certificates
-pub fn create_tls_connector() -> Result<TlsConnector> {
+/// Which server certificates to accept
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsTrust {
+    /// Extra PEM bundle trusted in addition to the system roots
+    pub ca_bundle_path: Option<PathBuf>,
+    /// Expected SHA-256 fingerprint of the server certificate
+    pub pin_sha256: Option<String>,
+    /// Accept an unknown certificate when no pin is recorded yet
+    pub trust_on_first_use: bool,
+}
+
+/// Verifier applying pins and trust-on-first-use before chain validation
+struct TrustVerifier {
+    webpki: WebPkiVerifier,
+    pin: Option<String>,
+    trust_on_first_use: bool,
+    /// Fingerprint of the last certificate presented by the server
+    observed: Mutex<Option<String>>,
+}
+
+impl ServerCertVerifier for TrustVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let fingerprint = certificate_fingerprint(&end_entity.0);
+        *self.observed.lock() = Some(fingerprint.clone());
+
+        if let Some(pin) = &self.pin {
+            return if *pin == fingerprint {
+                Ok(ServerCertVerified::assertion())
+            } else {
+                Err(rustls::Error::General(
+                    "certificate does not match pinned fingerprint".to_string(),
+                ))
+            };
+        }
+        if self.trust_on_first_use {
+            return Ok(ServerCertVerified::assertion());
+        }
+        self.webpki.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )
+    }
+}
+
+/// Hex SHA-256 fingerprint of a DER certificate
+pub fn certificate_fingerprint(der: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(der);
+    format!("{:x}", hasher.finalize())
+}
+
+/// Normalize a user-supplied fingerprint (`AB:CD:..` or hex) for comparison
+pub fn normalize_fingerprint(value: &str) -> Option<String> {
+    let hex: String = value
+        .chars()
+        .filter(|c| !matches!(c, ':' | ' '))
+        .collect::<String>()
+        .to_lowercase();
+    (hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit())).then_some(hex)
+}
+
+fn load_root_store(trust: &TlsTrust) -> Result<RootCertStore> {
     let mut root_store = RootCertStore::empty();
 
     // Add system root certificates
@@ -13,21 +90,219 @@ pub fn create_tls_connector() -> Result<TlsConnector> {
         root_store.add(&rustls::Certificate(cert.0))?;
     }
 
+    if let Some(path) = &trust.ca_bundle_path {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open CA bundle {}", path.display()))?;
+        let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(file))
+            .with_context(|| format!("Failed to read CA bundle {}", path.display()))?;
+        if certs.is_empty() {
+            anyhow::bail!("CA bundle {} contains no certificates", path.display());
+        }
+        let (added, _) = root_store.add_parsable_certificates(&certs);
+        if added == 0 {
+            anyhow::bail!(
+                "CA bundle {} contains no usable certificates",
+                path.display()
+            );
+        }
+    }
+
+    Ok(root_store)
+}
+
+fn build_verifier(trust: &TlsTrust) -> Result<Arc<TrustVerifier>> {
+    let pin = match &trust.pin_sha256 {
+        Some(pin) => Some(
+            normalize_fingerprint(pin)
+                .with_context(|| format!("Invalid certificate pin: {}", pin))?,
+        ),
+        None => None,
+    };
+    Ok(Arc::new(TrustVerifier {
+        webpki: WebPkiVerifier::new(load_root_store(trust)?, None),
+        pin,
+        trust_on_first_use: trust.trust_on_first_use,
+        observed: Mutex::new(None),
+    }))
+}
+
+/// Create a TLS connector enforcing `trust`
+pub fn create_tls_connector(trust: &TlsTrust) -> Result<TlsConnector> {
+    let verifier = build_verifier(trust)?;
+    Ok(connector_for(verifier))
+}
+
+fn connector_for(verifier: Arc<TrustVerifier>) -> TlsConnector {
     let config = ClientConfig::builder()
         .with_safe_defaults()
-        .with_root_certificates(root_store)
+        .with_custom_certificate_verifier(verifier)
         .with_no_client_auth();
-
-    Ok(TlsConnector::from(Arc::new(config)))
+    TlsConnector::from(Arc::new(config))
 }
 
-/// Upgrade a TCP stream to TLS
-pub async fn upgrade_to_tls(stream: TcpStream, domain: &str) -> Result<TlsStream<TcpStream>> {
-    let connector = create_tls_connector()?;
-    let domain = match domain.parse::<std::net::IpAddr>() {
+/// Upgrade a TCP stream to TLS, returning the stream and the server certificate fingerprint
+pub async fn upgrade_to_tls(
+    stream: TcpStream,
+    domain: &str,
+    trust: &TlsTrust,
+) -> Result<(TlsStream<TcpStream>, Option<String>)> {
+    let verifier = build_verifier(trust)?;
+    let connector = connector_for(verifier.clone());
+    let server_name = match domain.parse::<std::net::IpAddr>() {
         Ok(ip) => rustls::ServerName::IpAddress(ip),
         Err(_) => rustls::ServerName::try_from(domain)?,
     };
-    let tls_stream = connector.connect(domain, stream).await?;
-    Ok(tls_stream)
+    match connector.connect(server_name, stream).await {
+        Ok(tls_stream) => {
+            let fingerprint = verifier.observed.lock().clone();
+            Ok((tls_stream, fingerprint))
+        }
+        Err(e) => {
+            let observed = verifier.observed.lock().clone();
+            if let (Some(pin), Some(observed)) = (&verifier.pin, observed) {
+                if *pin != observed {
+                    anyhow::bail!(
+                        "Certificate pin mismatch for {}: expected {}, server presented {}",
+                        domain,
+                        pin,
+                        observed
+                    );
+                }
+            }
+            Err(e).context("TLS handshake failed")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_fingerprint() {
+        let hex = "ab".repeat(32);
+        assert_eq!(normalize_fingerprint(&hex), Some(hex.clone()));
+        let colons = vec!["AB"; 32].join(":");
+        assert_eq!(normalize_fingerprint(&colons), Some(hex));
+        assert_eq!(normalize_fingerprint("abcd"), None);
+        assert_eq!(normalize_fingerprint(&"zz".repeat(32)), None);
+    }
+
+    #[test]
+    fn test_invalid_pin_and_bundle_are_rejected() {
+        let trust = TlsTrust {
+            pin_sha256: Some("not-a-fingerprint".to_string()),
+            ..Default::default()
+        };
+        assert!(create_tls_connector(&trust).is_err());
+
+        let trust = TlsTrust {
+            ca_bundle_path: Some(PathBuf::from("/nonexistent/ca.pem")),
+            ..Default::default()
+        };
+        let error = create_tls_connector(&trust).err().unwrap().to_string();
+        assert!(error.contains("/nonexistent/ca.pem"));
+    }
+
+    struct TestPki {
+        ca_pem: String,
+        leaf_der: Vec<u8>,
+        key_der: Vec<u8>,
+    }
+
+    fn generate_pki() -> TestPki {
+        let mut ca_params = rcgen::CertificateParams::new(Vec::new());
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        ca_params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "Syncplay Test CA");
+        let ca = rcgen::Certificate::from_params(ca_params).unwrap();
+        let leaf = rcgen::Certificate::from_params(rcgen::CertificateParams::new(vec![
+            "localhost".to_string(),
+        ]))
+        .unwrap();
+        TestPki {
+            ca_pem: ca.serialize_pem().unwrap(),
+            leaf_der: leaf.serialize_der_with_signer(&ca).unwrap(),
+            key_der: leaf.serialize_private_key_der(),
+        }
+    }
+
+    /// Accept a single TLS handshake on a local port
+    async fn serve_once(pki: &TestPki) -> u16 {
+        let config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![Certificate(pki.leaf_der.clone())],
+                rustls::PrivateKey(pki.key_der.clone()),
+            )
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            if let Ok((stream, _)) = listener.accept().await {
+                if let Ok(stream) = acceptor.accept(stream).await {
+                    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+                    drop(stream);
+                }
+            }
+        });
+        port
+    }
+
+    async fn handshake(pki: &TestPki, trust: TlsTrust) -> Result<Option<String>> {
+        let port = serve_once(pki).await;
+        let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        upgrade_to_tls(stream, "localhost", &trust)
+            .await
+            .map(|(_, fingerprint)| fingerprint)
+    }
+
+    #[tokio::test]
+    async fn test_pinned_certificate() {
+        let pki = generate_pki();
+        let fingerprint = certificate_fingerprint(&pki.leaf_der);
+
+        let trust = TlsTrust {
+            pin_sha256: Some(fingerprint.to_uppercase()),
+            ..Default::default()
+        };
+        let observed = handshake(&pki, trust).await.unwrap();
+        assert_eq!(observed, Some(fingerprint.clone()));
+
+        let trust = TlsTrust {
+            pin_sha256: Some("00".repeat(32)),
+            ..Default::default()
+        };
+        let error = handshake(&pki, trust).await.unwrap_err().to_string();
+        assert!(error.contains("pin mismatch"), "{}", error);
+        assert!(error.contains(&fingerprint), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_private_ca_requires_bundle() {
+        let pki = generate_pki();
+        assert!(handshake(&pki, TlsTrust::default()).await.is_err());
+
+        let bundle = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(bundle.path(), &pki.ca_pem).unwrap();
+        let trust = TlsTrust {
+            ca_bundle_path: Some(bundle.path().to_path_buf()),
+            ..Default::default()
+        };
+        assert!(handshake(&pki, trust).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_trust_on_first_use_reports_fingerprint() {
+        let pki = generate_pki();
+        let trust = TlsTrust {
+            trust_on_first_use: true,
+            ..Default::default()
+        };
+        let observed = handshake(&pki, trust).await.unwrap();
+        assert_eq!(observed, Some(certificate_fingerprint(&pki.leaf_der)));
+    }
 }
@@ -16,7 +16,6 @@ use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::sync::Arc;
 use std::time::Instant;
-use tauri::Manager;
 #[cfg(unix)]
 use tempfile::Builder;
 use tokio::process::Command;
@@ -40,6 +39,9 @@ impl<'a> Drop for PlayerConnectingGuard<'a> {
 }
 
 pub async fn ensure_player_connected(state: &Arc<AppState>) -> Result<(), String> {
+    if !state.is_playback_session() {
+        return Err("Only the active session controls the player".to_string());
+    }
     if state.is_player_connected() {
         return Ok(());
     }
@@ -225,13 +227,15 @@ pub async fn stop_player(state: &Arc<AppState>) -> Result<(), String> {
     Ok(())
 }
 
-pub fn spawn_player_state_loop(state: Arc<AppState>) {
+pub fn spawn_player_state_loop(root: Arc<AppState>) {
     tokio::spawn(async move {
         let mut last_observed: Option<PlayerStateSnapshot> = None;
         let mut eof_sent = false;
         let mut interval = tokio::time::interval(Duration::from_millis(100));
         loop {
             interval.tick().await;
+            // The player follows whichever session is active
+            let state = root.playback_session();
             let player = state.player.lock().clone();
             let Some(player) = player else { continue };
             if let Err(e) = player.poll_state().await {
@@ -289,11 +293,14 @@ pub fn spawn_player_state_loop(state: Arc<AppState>) {
                     .local_playback_state
                     .lock()
                     .update_from_player(position, paused, global.position, global.paused);
-                if !is_placeholder
+                let local_change = !is_placeholder
                     && state.is_connected()
                     && state.last_global_update.lock().is_some()
-                    && (local_pause_change || local_seeked)
-                {
+                    && (local_pause_change || local_seeked);
+                if local_change && !state.client_state.can_control() {
+                    undo_viewer_change(&state, &player, local_seeked, local_pause_change, paused)
+                        .await;
+                } else if local_change {
                     let play_state = PlayState {
                         position,
                         paused,
@@ -301,7 +308,7 @@ pub fn spawn_player_state_loop(state: Arc<AppState>) {
                         set_by: None,
                     };
                     let latency_calculation = *state.last_latency_calculation.lock();
-                    if let Err(e) = crate::commands::connection::send_state_message(
+                    if let Err(e) = crate::client::session::send_state_message(
                         &state,
                         Some(play_state),
                         latency_calculation,
@@ -335,6 +342,51 @@ pub fn spawn_player_state_loop(state: Arc<AppState>) {
     });
 }
 
+/// Put a managed room viewer's player back where the room is and say why.
+/// The server would revert the change anyway; undoing it here avoids flapping.
+async fn undo_viewer_change(
+    state: &Arc<AppState>,
+    player: &Arc<dyn PlayerBackend>,
+    seeked: bool,
+    pause_changed: bool,
+    paused: bool,
+) {
+    let global = state.client_state.get_global_state();
+    if seeked {
+        // The room kept playing since the last server update
+        let elapsed = state
+            .last_global_update
+            .lock()
+            .map(|updated| updated.elapsed().as_secs_f64())
+            .unwrap_or(0.0);
+        let position = if global.paused {
+            global.position
+        } else {
+            global.position + elapsed
+        };
+        if let Err(e) = player.set_position(position).await {
+            tracing::warn!("Failed to undo seek: {}", e);
+        }
+    }
+    if pause_changed {
+        if !global.paused {
+            // Resuming is our doing, not the user's
+            *state.suppress_unpause_check.lock() = true;
+        }
+        if let Err(e) = player.set_paused(global.paused).await {
+            tracing::warn!("Failed to undo pause change: {}", e);
+        }
+    }
+    let action = if seeked {
+        "seek"
+    } else if paused {
+        "pause"
+    } else {
+        "unpause"
+    };
+    crate::client::session::explain_operator_only(state, action);
+}
+
 pub async fn load_media_by_name(
     state: &Arc<AppState>,
     filename: &str,
@@ -537,16 +589,12 @@ fn resolve_placeholder_path(state: &AppState) -> Option<PathBuf> {
         "icon.svg",
         "app-icon.svg",
     ];
-    if let Some(handle) = state.app_handle.lock().clone() {
-        for name in candidates {
-            if let Ok(path) = handle
-                .path()
-                .resolve(name, tauri::path::BaseDirectory::Resource)
-            {
-                if path.exists() {
-                    return Some(path);
-                }
-            }
+    if let Some(host) = state.host.lock().clone() {
+        if let Some(path) = candidates
+            .iter()
+            .find_map(|name| host.resolve_resource(name))
+        {
+            return Some(path);
         }
     }
     let cwd = std::env::current_dir().ok()?;
@@ -671,7 +719,7 @@ fn spawn_event_loop(
                 reason: EndFileReason::Eof,
             } = event
             {
-                handle_end_of_file(&state).await;
+                handle_end_of_file(&state.playback_session()).await;
             }
         }
     });
@@ -690,7 +738,7 @@ fn emit_player_state(state: &Arc<AppState>, player_state: &PlayerState) {
     );
 }
 
-fn send_file_update(state: &Arc<AppState>, player_state: &PlayerState) {
+pub(crate) fn send_file_update(state: &Arc<AppState>, player_state: &PlayerState) {
     if player_state.filename.is_none() {
         return;
     }
@@ -788,6 +836,10 @@ async fn handle_end_of_file(state: &Arc<AppState>) {
     if !config.user.shared_playlist_enabled {
         return;
     }
+    if !state.client_state.can_control() {
+        tracing::debug!("Waiting for a room operator to advance the playlist");
+        return;
+    }
 
     if let Some(next) = state
         .playlist
@@ -38,7 +38,7 @@ impl MpvPlayerEvent {
             "playback-restart" => Self::PlaybackRestart,
             "end-file" => {
                 let end_reason = reason
-                    .map(EndFileReason::from_str)
+                    .map(EndFileReason::from_reason)
                     .unwrap_or(EndFileReason::Unknown("none".to_string()));
                 Self::EndFile { reason: end_reason }
             }
@@ -50,7 +50,7 @@ impl MpvPlayerEvent {
 }
 
 impl EndFileReason {
-    pub fn from_str(s: &str) -> Self {
+    pub fn from_reason(s: &str) -> Self {
         match s {
             "eof" => Self::Eof,
             "stop" => Self::Stop,
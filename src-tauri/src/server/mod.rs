@@ -0,0 +1,495 @@
+// Embedded Syncplay-compatible server
+
+pub mod room;
+pub mod state;
+pub mod watcher;
+
+use anyhow::{Context, Result};
+use futures::{SinkExt, StreamExt};
+use parking_lot::Mutex;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, Duration};
+use tokio_util::codec::Framed;
+
+use crate::network::messages::{ErrorMessage, ProtocolMessage};
+use crate::network::protocol::SyncplayCodec;
+use room::generate_salt;
+use state::{Flow, ServerState};
+
+const STATE_INTERVAL_SECONDS: u64 = 1;
+
+/// Server settings, mirroring the options of the reference Python server
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    pub password: Option<String>,
+    pub motd: Option<String>,
+    /// Salt for managed room names; keep it stable to keep room names valid
+    pub salt: String,
+    pub isolate_rooms: bool,
+    pub disable_ready: bool,
+    pub disable_chat: bool,
+    pub max_chat_message_length: usize,
+    pub max_username_length: usize,
+    pub max_room_name_length: usize,
+    pub max_filename_length: usize,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            password: None,
+            motd: None,
+            salt: generate_salt(),
+            isolate_rooms: false,
+            disable_ready: false,
+            disable_chat: false,
+            max_chat_message_length: 150,
+            max_username_length: 150,
+            max_room_name_length: 35,
+            max_filename_length: 250,
+        }
+    }
+}
+
+/// A running server; dropping it without `shutdown` leaves it running
+pub struct SyncplayServer {
+    local_addr: SocketAddr,
+    shutdown_tx: watch::Sender<bool>,
+    task: JoinHandle<()>,
+}
+
+impl SyncplayServer {
+    /// Bind to `addr` and start accepting clients
+    pub async fn bind(addr: &str, options: ServerOptions) -> Result<Self> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to bind server to {}", addr))?;
+        let local_addr = listener.local_addr()?;
+        tracing::info!("Syncplay server listening on {}", local_addr);
+
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let state = Arc::new(Mutex::new(ServerState::new(options)));
+        let task = tokio::spawn(run(listener, state, shutdown_rx));
+
+        Ok(Self {
+            local_addr,
+            shutdown_tx,
+            task,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Disconnect all clients and stop listening
+    pub async fn shutdown(self) {
+        let _ = self.shutdown_tx.send(true);
+        if let Err(e) = self.task.await {
+            tracing::warn!("Server task ended abnormally: {}", e);
+        }
+        tracing::info!("Syncplay server on {} stopped", self.local_addr);
+    }
+}
+
+async fn run(
+    listener: TcpListener,
+    state: Arc<Mutex<ServerState>>,
+    mut shutdown_rx: watch::Receiver<bool>,
+) {
+    let mut ticker = interval(Duration::from_secs(STATE_INTERVAL_SECONDS));
+    let mut clients = Vec::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                match accepted {
+                    Ok((stream, peer)) => {
+                        tracing::info!("Client connected from {}", peer);
+                        clients.retain(|client: &JoinHandle<()>| !client.is_finished());
+                        clients.push(tokio::spawn(serve_client(
+                            stream,
+                            state.clone(),
+                            shutdown_rx.clone(),
+                        )));
+                    }
+                    Err(e) => tracing::warn!("Failed to accept client: {}", e),
+                }
+            }
+            _ = ticker.tick() => {
+                state.lock().send_periodic_states();
+            }
+            _ = shutdown_rx.changed() => break,
+        }
+    }
+
+    for client in clients {
+        let _ = client.await;
+    }
+}
+
+async fn serve_client(
+    stream: TcpStream,
+    state: Arc<Mutex<ServerState>>,
+    mut shutdown_rx: watch::Receiver<bool>,
+) {
+    let (mut sink, mut stream) = Framed::new(stream, SyncplayCodec::new()).split();
+    let (tx, mut rx) = mpsc::unbounded_channel::<ProtocolMessage>();
+    let id = state.lock().add_watcher(tx.clone());
+
+    let writer = tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            if let Err(e) = sink.send(message).await {
+                tracing::debug!("Failed to write to client: {}", e);
+                break;
+            }
+        }
+    });
+
+    loop {
+        tokio::select! {
+            message = stream.next() => {
+                match message {
+                    Some(Ok(message)) => {
+                        if state.lock().handle_message(id, message) == Flow::Close {
+                            break;
+                        }
+                    }
+                    Some(Err(e)) => {
+                        tracing::warn!("Invalid message from client {}: {}", id, e);
+                        let _ = tx.send(ProtocolMessage::Error {
+                            Error: ErrorMessage {
+                                message: "Not a json encoded string".to_string(),
+                            },
+                        });
+                        break;
+                    }
+                    None => break,
+                }
+            }
+            _ = shutdown_rx.changed() => break,
+        }
+    }
+
+    state.lock().remove_watcher(id);
+    drop(tx);
+    let _ = writer.await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::messages::{
+        ChatMessage, ClientFeatures, ControllerAuth, HelloMessage, IgnoringInfo, PlayState,
+        PlaylistChange, RoomInfo, SetMessage, StateMessage, TLSMessage,
+    };
+    use crate::network::mock_server::MockPeer;
+    use crate::utils::is_controlled_room;
+
+    async fn start_server(options: ServerOptions) -> SyncplayServer {
+        SyncplayServer::bind("127.0.0.1:0", options).await.unwrap()
+    }
+
+    fn hello(username: &str, room: &str, password: Option<&str>) -> ProtocolMessage {
+        ProtocolMessage::Hello {
+            Hello: HelloMessage {
+                username: username.to_string(),
+                password: password.map(str::to_string),
+                room: Some(RoomInfo {
+                    name: room.to_string(),
+                    password: None,
+                }),
+                version: "1.2.255".to_string(),
+                realversion: "1.7.4".to_string(),
+                features: Some(ClientFeatures {
+                    shared_playlists: Some(true),
+                    chat: Some(true),
+                    ready_state: Some(true),
+                    managed_rooms: Some(true),
+                    persistent_rooms: Some(false),
+                    extra: Default::default(),
+                }),
+                motd: None,
+            },
+        }
+    }
+
+    async fn connect(server: &SyncplayServer) -> MockPeer {
+        MockPeer::connect(server.local_addr()).await
+    }
+
+    async fn login(server: &SyncplayServer, username: &str, room: &str) -> (MockPeer, String) {
+        let mut client = connect(server).await;
+        client.send(hello(username, room, None)).await;
+        let name = match client.recv().await {
+            ProtocolMessage::Hello { Hello } => Hello.username,
+            other => panic!("Expected Hello, got {:?}", other),
+        };
+        (client, name)
+    }
+
+    #[tokio::test]
+    async fn test_hello_tls_and_motd() {
+        let server = start_server(ServerOptions {
+            motd: Some("Welcome".to_string()),
+            ..Default::default()
+        })
+        .await;
+        let mut client = connect(&server).await;
+        client
+            .send(ProtocolMessage::TLS {
+                TLS: TLSMessage {
+                    start_tls: Some("send".to_string()),
+                },
+            })
+            .await;
+        match client.recv().await {
+            ProtocolMessage::TLS { TLS } => assert_eq!(TLS.start_tls.as_deref(), Some("false")),
+            other => panic!("Expected TLS answer, got {:?}", other),
+        }
+
+        client.send(hello("alice", "lobby", None)).await;
+        match client.recv().await {
+            ProtocolMessage::Hello { Hello } => {
+                assert_eq!(Hello.username, "alice");
+                assert_eq!(Hello.room.unwrap().name, "lobby");
+                assert_eq!(Hello.motd.as_deref(), Some("Welcome"));
+                let features = Hello.features.unwrap();
+                assert_eq!(features.managed_rooms, Some(true));
+                assert!(features.extra.contains_key("maxChatMessageLength"));
+            }
+            other => panic!("Expected Hello, got {:?}", other),
+        }
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_hello_rejections() {
+        let server = start_server(ServerOptions {
+            password: Some("secret".to_string()),
+            ..Default::default()
+        })
+        .await;
+
+        let mut client = connect(&server).await;
+        client.send(hello("alice", "lobby", Some("wrong"))).await;
+        match client.recv().await {
+            ProtocolMessage::Error { Error } => {
+                assert_eq!(Error.message, "Wrong password supplied")
+            }
+            other => panic!("Expected Error, got {:?}", other),
+        }
+
+        let mut client = connect(&server).await;
+        let mut old = hello("bob", "lobby", Some("secret"));
+        if let ProtocolMessage::Hello { Hello } = &mut old {
+            Hello.version = "1.1.0".to_string();
+        }
+        client.send(old).await;
+        match client.recv().await {
+            ProtocolMessage::Error { Error } => {
+                assert_eq!(Error.message, "Version mismatch between client and server")
+            }
+            other => panic!("Expected Error, got {:?}", other),
+        }
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_username_and_list() {
+        let server = start_server(ServerOptions::default()).await;
+        let (_alice, first) = login(&server, "alice", "lobby").await;
+        let (mut second, second_name) = login(&server, "alice", "lobby").await;
+        assert_eq!(first, "alice");
+        assert_eq!(second_name, "alice_");
+
+        second.send(ProtocolMessage::List { List: None }).await;
+        let list = second
+            .expect(|message| match message {
+                ProtocolMessage::List { List } => List,
+                _ => None,
+            })
+            .await;
+        let lobby = list.get("lobby").expect("lobby missing");
+        assert!(lobby.contains_key("alice"));
+        assert!(lobby.contains_key("alice_"));
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_join_chat_and_leave_broadcasts() {
+        let server = start_server(ServerOptions::default()).await;
+        let (mut alice, _) = login(&server, "alice", "lobby").await;
+        let (mut bob, _) = login(&server, "bob", "lobby").await;
+
+        alice
+            .expect(|message| match message {
+                ProtocolMessage::Set { Set } => Set
+                    .user
+                    .and_then(|users| users.get("bob")?.event.as_ref()?.joined),
+                _ => None,
+            })
+            .await;
+
+        bob.send(ProtocolMessage::Chat {
+            Chat: ChatMessage::Text("hi there".to_string()),
+        })
+        .await;
+        let (username, message) = alice
+            .expect(|message| match message {
+                ProtocolMessage::Chat {
+                    Chat: ChatMessage::Entry { username, message },
+                } => Some((username, message)),
+                _ => None,
+            })
+            .await;
+        assert_eq!(username, "bob");
+        assert_eq!(message, "hi there");
+
+        drop(bob);
+        alice
+            .expect(|message| match message {
+                ProtocolMessage::Set { Set } => Set
+                    .user
+                    .and_then(|users| users.get("bob")?.event.as_ref()?.left),
+                _ => None,
+            })
+            .await;
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_seek_is_forced_on_room_members() {
+        let server = start_server(ServerOptions::default()).await;
+        let (mut alice, _) = login(&server, "alice", "lobby").await;
+        let (mut bob, _) = login(&server, "bob", "lobby").await;
+
+        alice
+            .send(ProtocolMessage::State {
+                State: StateMessage {
+                    playstate: Some(PlayState {
+                        position: 42.0,
+                        paused: true,
+                        do_seek: Some(true),
+                        set_by: None,
+                    }),
+                    ping: None,
+                    // Acknowledge the forced state sent on join
+                    ignoring_on_the_fly: Some(IgnoringInfo {
+                        server: Some(1),
+                        client: None,
+                    }),
+                },
+            })
+            .await;
+
+        let state = bob
+            .expect(|message| match message {
+                ProtocolMessage::State { State }
+                    if State.playstate.as_ref()?.do_seek == Some(true) =>
+                {
+                    let playstate = State.playstate.as_ref()?;
+                    (playstate.set_by.as_deref() == Some("alice")).then_some(State)
+                }
+                _ => None,
+            })
+            .await;
+        assert_eq!(state.playstate.unwrap().position, 42.0);
+        assert!(state.ignoring_on_the_fly.unwrap().server.is_some());
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_playlist_shared_with_late_joiner() {
+        let server = start_server(ServerOptions::default()).await;
+        let (mut alice, _) = login(&server, "alice", "lobby").await;
+        alice
+            .send(ProtocolMessage::Set {
+                Set: Box::new(SetMessage {
+                    playlist_change: Some(PlaylistChange {
+                        user: None,
+                        files: vec!["a.mkv".to_string(), "b.mkv".to_string()],
+                    }),
+                    ..Default::default()
+                }),
+            })
+            .await;
+        alice
+            .expect(|message| match message {
+                ProtocolMessage::Set { Set } => Set.playlist_change,
+                _ => None,
+            })
+            .await;
+
+        let (mut bob, _) = login(&server, "bob", "lobby").await;
+        let change = bob
+            .expect(|message| match message {
+                ProtocolMessage::Set { Set } => Set.playlist_change,
+                _ => None,
+            })
+            .await;
+        assert_eq!(change.files, vec!["a.mkv", "b.mkv"]);
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_managed_room_creation_and_auth() {
+        let server = start_server(ServerOptions::default()).await;
+        let (mut alice, _) = login(&server, "alice", "movies").await;
+        let password = room::generate_room_password();
+
+        alice
+            .send(ProtocolMessage::Set {
+                Set: Box::new(SetMessage {
+                    controller_auth: Some(ControllerAuth {
+                        room: Some("movies".to_string()),
+                        password: Some(password.clone()),
+                        user: None,
+                        success: None,
+                    }),
+                    ..Default::default()
+                }),
+            })
+            .await;
+        let created = alice
+            .expect(|message| match message {
+                ProtocolMessage::Set { Set } => Set.new_controlled_room,
+                _ => None,
+            })
+            .await;
+        let room_name = created.room_name.unwrap();
+        assert!(is_controlled_room(&room_name));
+        assert_eq!(created.password.as_deref(), Some(password.as_str()));
+
+        alice
+            .send(ProtocolMessage::Set {
+                Set: Box::new(SetMessage {
+                    room: Some(RoomInfo {
+                        name: room_name.clone(),
+                        password: None,
+                    }),
+                    controller_auth: Some(ControllerAuth {
+                        room: Some(room_name.clone()),
+                        password: Some(password),
+                        user: None,
+                        success: None,
+                    }),
+                    ..Default::default()
+                }),
+            })
+            .await;
+        let auth = alice
+            .expect(|message| match message {
+                ProtocolMessage::Set { Set } => Set.controller_auth,
+                _ => None,
+            })
+            .await;
+        assert_eq!(auth.success, Some(true));
+        assert_eq!(auth.user.as_deref(), Some("alice"));
+        assert_eq!(auth.room.as_deref(), Some(room_name.as_str()));
+        server.shutdown().await;
+    }
+}
@@ -0,0 +1,233 @@
+use rand::Rng;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use crate::utils::is_controlled_room;
+
+/// Outcome of a controller authentication attempt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoomPasswordCheck {
+    Accepted,
+    Rejected,
+    /// The room is not a managed room; the password can create one
+    NotControlled,
+}
+
+/// Shared playback state for a room
+#[derive(Debug, Clone)]
+pub struct Room {
+    name: String,
+    position: f64,
+    paused: bool,
+    last_update: Instant,
+    set_by: Option<String>,
+    playlist: Vec<String>,
+    playlist_index: Option<usize>,
+    controllers: HashSet<String>,
+}
+
+impl Room {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            position: 0.0,
+            paused: true,
+            last_update: Instant::now(),
+            set_by: None,
+            playlist: Vec::new(),
+            playlist_index: None,
+            controllers: HashSet::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Current position, advanced by wall time while playing
+    pub fn position(&self) -> f64 {
+        if self.paused {
+            self.position
+        } else {
+            self.position + self.last_update.elapsed().as_secs_f64()
+        }
+    }
+
+    /// Seconds since the position was last set
+    pub fn age(&self) -> f64 {
+        self.last_update.elapsed().as_secs_f64()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_by(&self) -> Option<&str> {
+        self.set_by.as_deref()
+    }
+
+    pub fn set_position(&mut self, position: f64, set_by: Option<String>) {
+        self.position = position.max(0.0);
+        self.last_update = Instant::now();
+        self.set_by = set_by;
+    }
+
+    pub fn set_paused(&mut self, paused: bool, set_by: Option<String>) {
+        self.position = self.position();
+        self.last_update = Instant::now();
+        self.paused = paused;
+        self.set_by = set_by;
+    }
+
+    pub fn is_controlled(&self) -> bool {
+        is_controlled_room(&self.name)
+    }
+
+    pub fn can_control(&self, username: &str) -> bool {
+        !self.is_controlled() || self.controllers.contains(username)
+    }
+
+    pub fn is_controller(&self, username: &str) -> bool {
+        self.controllers.contains(username)
+    }
+
+    pub fn add_controller(&mut self, username: &str) {
+        self.controllers.insert(username.to_string());
+    }
+
+    pub fn remove_controller(&mut self, username: &str) {
+        self.controllers.remove(username);
+    }
+
+    pub fn playlist(&self) -> &[String] {
+        &self.playlist
+    }
+
+    pub fn set_playlist(&mut self, files: Vec<String>) {
+        self.playlist = files;
+        if self.playlist.is_empty() {
+            self.playlist_index = None;
+        }
+    }
+
+    pub fn playlist_index(&self) -> Option<usize> {
+        self.playlist_index
+    }
+
+    pub fn set_playlist_index(&mut self, index: Option<usize>) {
+        self.playlist_index = index;
+    }
+}
+
+/// Name of the managed room derived from `base` and its control password
+pub fn controlled_room_name(base: &str, password: &str, salt: &str) -> String {
+    format!("+{}:{}", base, room_hash(base, password, salt))
+}
+
+pub fn check_room_password(room: &str, password: &str, salt: &str) -> RoomPasswordCheck {
+    if !is_valid_room_password(password) {
+        return RoomPasswordCheck::Rejected;
+    }
+    if !is_controlled_room(room) {
+        return RoomPasswordCheck::NotControlled;
+    }
+    let Some((base, hash)) = room[1..].rsplit_once(':') else {
+        return RoomPasswordCheck::NotControlled;
+    };
+    if room_hash(base, password, salt) == hash {
+        RoomPasswordCheck::Accepted
+    } else {
+        RoomPasswordCheck::Rejected
+    }
+}
+
+/// Generate a control password in the `AB-123-456` format
+pub fn generate_room_password() -> String {
+    let mut rng = rand::thread_rng();
+    let letters: String = (0..2).map(|_| rng.gen_range(b'A'..=b'Z') as char).collect();
+    format!(
+        "{}-{:03}-{:03}",
+        letters,
+        rng.gen_range(0..1000),
+        rng.gen_range(0..1000)
+    )
+}
+
+/// Random salt used to derive managed room names
+pub fn generate_salt() -> String {
+    let mut rng = rand::thread_rng();
+    (0..10)
+        .map(|_| rng.gen_range(b'A'..=b'Z') as char)
+        .collect()
+}
+
+fn is_valid_room_password(password: &str) -> bool {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX
+        .get_or_init(|| Regex::new(r"^[A-Z]{2}-\d{3}-\d{3}$").expect("invalid room password regex"))
+        .is_match(password)
+}
+
+fn room_hash(base: &str, password: &str, salt: &str) -> String {
+    let salt = hex_digest(salt.as_bytes());
+    let provisional = hex_digest(format!("{}{}", base, salt).as_bytes());
+    let hash = hex_digest(format!("{}{}{}", provisional, salt, password).as_bytes());
+    hash[..12].to_uppercase()
+}
+
+fn hex_digest(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_controlled_room_roundtrip() {
+        let password = generate_room_password();
+        let room = controlled_room_name("movies", &password, "salt");
+        assert!(is_controlled_room(&room));
+        assert_eq!(
+            check_room_password(&room, &password, "salt"),
+            RoomPasswordCheck::Accepted
+        );
+        assert_eq!(
+            check_room_password(&room, "AA-000-000", "salt"),
+            RoomPasswordCheck::Rejected
+        );
+        assert_eq!(
+            check_room_password(&room, &password, "other"),
+            RoomPasswordCheck::Rejected
+        );
+    }
+
+    #[test]
+    fn test_check_room_password_plain_room() {
+        assert_eq!(
+            check_room_password("movies", "AB-123-456", "salt"),
+            RoomPasswordCheck::NotControlled
+        );
+        assert_eq!(
+            check_room_password("movies", "not-a-password", "salt"),
+            RoomPasswordCheck::Rejected
+        );
+    }
+
+    #[test]
+    fn test_room_control() {
+        let mut room = Room::new(controlled_room_name("movies", "AB-123-456", "salt"));
+        assert!(!room.can_control("alice"));
+        room.add_controller("alice");
+        assert!(room.can_control("alice"));
+        assert!(!room.can_control("bob"));
+
+        let open = Room::new("movies".to_string());
+        assert!(open.can_control("bob"));
+    }
+}
@@ -0,0 +1,773 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+use super::room::{check_room_password, controlled_room_name, Room, RoomPasswordCheck};
+use super::watcher::Watcher;
+use super::ServerOptions;
+use crate::network::messages::{
+    ChatMessage, ClientFeatures, ControllerAuth, ErrorMessage, FileInfo, HelloMessage,
+    ListResponse, NewControlledRoom, PlaylistChange, PlaylistIndexUpdate, ProtocolMessage,
+    ReadyState, RoomInfo, SetMessage, StateMessage, TLSMessage, UserEvent, UserInfo, UserUpdate,
+};
+use crate::utils::truncate_text;
+
+pub const SERVER_VERSION: &str = "1.7.4";
+const DEFAULT_ROOM: &str = "default";
+
+/// Whether a connection stays open after a message was handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Close,
+}
+
+/// Rooms and watchers of a running server
+pub struct ServerState {
+    options: ServerOptions,
+    rooms: HashMap<String, Room>,
+    watchers: HashMap<u64, Watcher>,
+    next_id: u64,
+}
+
+impl ServerState {
+    pub fn new(options: ServerOptions) -> Self {
+        Self {
+            options,
+            rooms: HashMap::new(),
+            watchers: HashMap::new(),
+            next_id: 1,
+        }
+    }
+
+    pub fn add_watcher(&mut self, tx: mpsc::UnboundedSender<ProtocolMessage>) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.watchers.insert(id, Watcher::new(id, tx));
+        id
+    }
+
+    pub fn remove_watcher(&mut self, id: u64) {
+        let Some(watcher) = self.watchers.get(&id) else {
+            return;
+        };
+        if watcher.is_logged_in() {
+            tracing::info!("{} left the server", watcher.name());
+            let room = watcher.room.clone();
+            let message = user_update_message(
+                watcher,
+                Some(UserEvent {
+                    joined: None,
+                    left: Some(true),
+                    extra: HashMap::new(),
+                }),
+            );
+            self.leave_room(id);
+            self.watchers.remove(&id);
+            self.broadcast(&room, message);
+        } else {
+            self.watchers.remove(&id);
+        }
+    }
+
+    pub fn handle_message(&mut self, id: u64, message: ProtocolMessage) -> Flow {
+        let logged_in = match self.watchers.get(&id) {
+            Some(watcher) => watcher.is_logged_in(),
+            None => return Flow::Close,
+        };
+
+        match message {
+            ProtocolMessage::TLS { TLS } => {
+                if TLS.start_tls.as_deref() == Some("send") {
+                    self.send_to(
+                        id,
+                        ProtocolMessage::TLS {
+                            TLS: TLSMessage {
+                                start_tls: Some("false".to_string()),
+                            },
+                        },
+                    );
+                }
+                Flow::Continue
+            }
+            ProtocolMessage::Hello { Hello } => self.handle_hello(id, Hello),
+            ProtocolMessage::Error { Error } => {
+                tracing::warn!("Client {} reported error: {}", id, Error.message);
+                Flow::Close
+            }
+            ProtocolMessage::Unknown(name, _) => {
+                tracing::warn!("Ignoring unknown message {} from client {}", name, id);
+                Flow::Continue
+            }
+            _ if !logged_in => {
+                self.send_error(id, "Not logged in, send Hello first");
+                Flow::Close
+            }
+            ProtocolMessage::Set { Set } => {
+                self.handle_set(id, *Set);
+                Flow::Continue
+            }
+            ProtocolMessage::State { State } => {
+                self.handle_state(id, State);
+                Flow::Continue
+            }
+            ProtocolMessage::Chat { Chat } => {
+                self.handle_chat(id, Chat);
+                Flow::Continue
+            }
+            ProtocolMessage::List { .. } => {
+                self.send_list(id);
+                Flow::Continue
+            }
+        }
+    }
+
+    /// Periodic State broadcast to every logged-in watcher
+    pub fn send_periodic_states(&mut self) {
+        let room_names: Vec<String> = self.rooms.keys().cloned().collect();
+        for room_name in room_names {
+            let position = self.room_position(&room_name);
+            let Some(room) = self.rooms.get(&room_name) else {
+                continue;
+            };
+            let paused = room.is_paused();
+            let set_by = room.set_by().map(str::to_string);
+            for watcher in self.watchers.values_mut() {
+                if watcher.is_logged_in() && watcher.room == room_name {
+                    watcher.send_state(position, paused, false, set_by.clone(), false);
+                }
+            }
+        }
+    }
+
+    fn handle_hello(&mut self, id: u64, hello: HelloMessage) -> Flow {
+        if self.watchers.get(&id).is_some_and(Watcher::is_logged_in) {
+            tracing::debug!("Ignoring repeated Hello from {}", id);
+            return Flow::Continue;
+        }
+
+        let username = hello.username.trim().to_string();
+        if username.is_empty() || hello.version.is_empty() {
+            self.send_error(id, "Not enough Hello arguments");
+            return Flow::Close;
+        }
+        if let Some(expected) = self.options.password.as_deref() {
+            if hello.password.as_deref() != Some(expected) {
+                self.send_error(id, "Wrong password supplied");
+                return Flow::Close;
+            }
+        }
+        if !is_supported_version(&hello.version) {
+            self.send_error(id, "Version mismatch between client and server");
+            return Flow::Close;
+        }
+
+        let username =
+            self.unique_username(truncate_text(&username, self.options.max_username_length));
+        let room = self.normalize_room_name(hello.room.map(|room| room.name));
+        let features = self.server_features();
+        let motd = self.options.motd.clone();
+        let Some(watcher) = self.watchers.get_mut(&id) else {
+            return Flow::Close;
+        };
+        watcher.username = Some(username.clone());
+        watcher.version = hello.version.clone();
+        watcher.features = hello.features;
+        tracing::info!("{} joined room {}", username, room);
+
+        watcher.send(ProtocolMessage::Hello {
+            Hello: HelloMessage {
+                username,
+                password: None,
+                room: Some(RoomInfo {
+                    name: room.clone(),
+                    password: None,
+                }),
+                version: hello.version,
+                realversion: SERVER_VERSION.to_string(),
+                features: Some(features),
+                motd,
+            },
+        });
+        self.join_room(id, room, true);
+        Flow::Continue
+    }
+
+    fn handle_set(&mut self, id: u64, set: SetMessage) {
+        if let Some(room) = set.room {
+            let room = self.normalize_room_name(Some(room.name));
+            let current = self.watchers.get(&id).map(|watcher| watcher.room.clone());
+            if current.as_deref() != Some(room.as_str()) {
+                self.leave_room(id);
+                self.join_room(id, room, false);
+            }
+        }
+
+        if let Some(mut file) = set.file {
+            file.name = file
+                .name
+                .map(|name| truncate_text(&name, self.options.max_filename_length));
+            if let Some(watcher) = self.watchers.get_mut(&id) {
+                watcher.file = Some(file);
+                let room = watcher.room.clone();
+                let message = user_update_message(watcher, None);
+                self.broadcast(&room, message);
+            }
+        }
+
+        if let Some(ready) = set.ready {
+            self.handle_ready(id, ready);
+        }
+
+        if let Some(change) = set.playlist_change {
+            self.handle_playlist_change(id, change);
+        }
+
+        if let Some(index) = set.playlist_index {
+            self.handle_playlist_index(id, index);
+        }
+
+        if let Some(auth) = set.controller_auth {
+            self.handle_controller_auth(id, auth);
+        }
+
+        if let Some(features) = set.features {
+            if let Some(watcher) = self.watchers.get_mut(&id) {
+                match serde_json::from_value(features) {
+                    Ok(features) => watcher.features = Some(features),
+                    Err(e) => tracing::debug!("Ignoring invalid features: {}", e),
+                }
+            }
+        }
+    }
+
+    fn handle_state(&mut self, id: u64, state: StateMessage) {
+        let Some(watcher) = self.watchers.get_mut(&id) else {
+            return;
+        };
+
+        if let Some(ignoring) = state.ignoring_on_the_fly {
+            if let Some(server) = ignoring.server {
+                if watcher.server_ignoring_on_the_fly == server {
+                    watcher.server_ignoring_on_the_fly = 0;
+                }
+            }
+            if let Some(client) = ignoring.client {
+                watcher.client_ignoring_on_the_fly = client;
+            }
+        }
+
+        if let Some(ping) = state.ping {
+            if let Some(latency_calculation) = ping.latency_calculation {
+                watcher
+                    .ping
+                    .receive_message(latency_calculation, ping.client_rtt.unwrap_or(0.0));
+            }
+            watcher.client_latency_calculation = ping.client_latency_calculation;
+        }
+
+        let Some(playstate) = state.playstate else {
+            return;
+        };
+        if watcher.server_ignoring_on_the_fly != 0 {
+            return;
+        }
+
+        let position = if playstate.paused {
+            playstate.position
+        } else {
+            playstate.position + watcher.ping.get_last_forward_delay()
+        };
+        watcher.position = Some(position);
+        watcher.position_updated = Instant::now();
+
+        let name = watcher.name().to_string();
+        let Some(room) = self.rooms.get_mut(&watcher.room) else {
+            return;
+        };
+        let pause_changed = room.is_paused() != playstate.paused;
+        if pause_changed && room.can_control(&name) {
+            room.set_paused(playstate.paused, Some(name));
+        }
+        let do_seek = playstate.do_seek.unwrap_or(false);
+        if do_seek || pause_changed {
+            self.force_position_update(id, do_seek, playstate.paused);
+        }
+    }
+
+    fn handle_chat(&mut self, id: u64, chat: ChatMessage) {
+        if self.options.disable_chat {
+            return;
+        }
+        let Some(watcher) = self.watchers.get(&id) else {
+            return;
+        };
+        let message = match chat {
+            ChatMessage::Text(message) => message,
+            ChatMessage::Entry { message, .. } => message,
+        };
+        let message = truncate_text(message.trim(), self.options.max_chat_message_length);
+        if message.is_empty() {
+            return;
+        }
+        let room = watcher.room.clone();
+        let entry = ProtocolMessage::Chat {
+            Chat: ChatMessage::Entry {
+                username: watcher.name().to_string(),
+                message,
+            },
+        };
+        self.broadcast_room(&room, entry);
+    }
+
+    fn handle_ready(&mut self, id: u64, ready: ReadyState) {
+        if self.options.disable_ready {
+            return;
+        }
+        let Some(watcher) = self.watchers.get_mut(&id) else {
+            return;
+        };
+        watcher.is_ready = ready.is_ready;
+        let room = watcher.room.clone();
+        let message = ProtocolMessage::Set {
+            Set: Box::new(SetMessage {
+                ready: Some(ReadyState {
+                    username: Some(watcher.name().to_string()),
+                    is_ready: ready.is_ready,
+                    manually_initiated: Some(ready.manually_initiated.unwrap_or(true)),
+                    set_by: None,
+                }),
+                ..Default::default()
+            }),
+        };
+        self.broadcast_room(&room, message);
+    }
+
+    fn handle_playlist_change(&mut self, id: u64, change: PlaylistChange) {
+        let Some(watcher) = self.watchers.get(&id) else {
+            return;
+        };
+        let name = watcher.name().to_string();
+        let room_name = watcher.room.clone();
+        let max_filename_length = self.options.max_filename_length;
+        let Some(room) = self.rooms.get_mut(&room_name) else {
+            return;
+        };
+
+        if !room.can_control(&name) {
+            // Undo the change on the offending client
+            watcher.send(playlist_change_message(
+                room.set_by().map(str::to_string),
+                room.playlist().to_vec(),
+            ));
+            return;
+        }
+
+        let files: Vec<String> = change
+            .files
+            .iter()
+            .map(|file| truncate_text(file, max_filename_length))
+            .collect();
+        room.set_playlist(files.clone());
+        self.broadcast_room(&room_name, playlist_change_message(Some(name), files));
+    }
+
+    fn handle_playlist_index(&mut self, id: u64, update: PlaylistIndexUpdate) {
+        let Some(watcher) = self.watchers.get(&id) else {
+            return;
+        };
+        let name = watcher.name().to_string();
+        let room_name = watcher.room.clone();
+        let Some(room) = self.rooms.get_mut(&room_name) else {
+            return;
+        };
+
+        if !room.can_control(&name) {
+            watcher.send(playlist_index_message(
+                room.set_by().map(str::to_string),
+                room.playlist_index(),
+            ));
+            return;
+        }
+
+        room.set_playlist_index(update.index);
+        self.broadcast_room(&room_name, playlist_index_message(Some(name), update.index));
+    }
+
+    fn handle_controller_auth(&mut self, id: u64, auth: ControllerAuth) {
+        let Some(watcher) = self.watchers.get(&id) else {
+            return;
+        };
+        let name = watcher.name().to_string();
+        let current_room = watcher.room.clone();
+        let room_name = auth
+            .room
+            .filter(|room| !room.is_empty())
+            .unwrap_or_else(|| current_room.clone());
+        let password = auth.password.unwrap_or_default();
+
+        let success = match check_room_password(&room_name, &password, &self.options.salt) {
+            RoomPasswordCheck::NotControlled => {
+                let room_name = controlled_room_name(&room_name, &password, &self.options.salt);
+                watcher.send(ProtocolMessage::Set {
+                    Set: Box::new(SetMessage {
+                        new_controlled_room: Some(NewControlledRoom {
+                            password: Some(password),
+                            room_name: Some(room_name),
+                        }),
+                        ..Default::default()
+                    }),
+                });
+                return;
+            }
+            RoomPasswordCheck::Accepted if room_name == current_room => {
+                if let Some(room) = self.rooms.get_mut(&current_room) {
+                    room.add_controller(&name);
+                }
+                true
+            }
+            RoomPasswordCheck::Accepted | RoomPasswordCheck::Rejected => false,
+        };
+
+        let message = ProtocolMessage::Set {
+            Set: Box::new(SetMessage {
+                controller_auth: Some(ControllerAuth {
+                    room: Some(current_room.clone()),
+                    password: None,
+                    user: Some(name),
+                    success: Some(success),
+                }),
+                ..Default::default()
+            }),
+        };
+        if success {
+            self.broadcast(&current_room, message);
+        } else {
+            self.send_to(id, message);
+        }
+    }
+
+    fn send_list(&self, id: u64) {
+        let Some(requester) = self.watchers.get(&id) else {
+            return;
+        };
+        let mut list: ListResponse = HashMap::new();
+        for watcher in self.watchers.values() {
+            if !watcher.is_logged_in() {
+                continue;
+            }
+            if self.options.isolate_rooms && watcher.room != requester.room {
+                continue;
+            }
+            let controller = self
+                .rooms
+                .get(&watcher.room)
+                .is_some_and(|room| room.is_controller(watcher.name()));
+            list.entry(watcher.room.clone()).or_default().insert(
+                watcher.name().to_string(),
+                UserInfo {
+                    file: watcher.file.clone(),
+                    controller: Some(controller),
+                    is_ready: watcher.is_ready,
+                    features: watcher.features.clone(),
+                },
+            );
+        }
+        requester.send(ProtocolMessage::List { List: Some(list) });
+    }
+
+    fn join_room(&mut self, id: u64, room_name: String, joined: bool) {
+        let Some(watcher) = self.watchers.get_mut(&id) else {
+            return;
+        };
+        watcher.room = room_name.clone();
+        watcher.position = None;
+        let event = joined.then(|| UserEvent {
+            joined: Some(true),
+            left: None,
+            extra: HashMap::from([(
+                "version".to_string(),
+                Value::String(watcher.version.clone()),
+            )]),
+        });
+        let message = user_update_message(watcher, event);
+
+        let room = self
+            .rooms
+            .entry(room_name.clone())
+            .or_insert_with(|| Room::new(room_name.clone()));
+        let playlist = room.playlist().to_vec();
+        let playlist_index = room.playlist_index();
+        let set_by = room.set_by().map(str::to_string);
+
+        if joined {
+            // The joining client learns about itself from the Hello reply
+            for other in self.watchers.values() {
+                if other.id != id && other.is_logged_in() && self.shares_scope(other, &room_name) {
+                    other.send(message.clone());
+                }
+            }
+        } else {
+            self.broadcast(&room_name, message);
+        }
+
+        if !playlist.is_empty() {
+            self.send_to(id, playlist_change_message(set_by.clone(), playlist));
+            self.send_to(id, playlist_index_message(set_by, playlist_index));
+        }
+
+        let position = self.room_position(&room_name);
+        let Some(room) = self.rooms.get(&room_name) else {
+            return;
+        };
+        let paused = room.is_paused();
+        let set_by = room.set_by().map(str::to_string);
+        if let Some(watcher) = self.watchers.get_mut(&id) {
+            watcher.send_state(position, paused, true, set_by, true);
+        }
+    }
+
+    fn leave_room(&mut self, id: u64) {
+        let Some(watcher) = self.watchers.get(&id) else {
+            return;
+        };
+        let name = watcher.name().to_string();
+        let room_name = watcher.room.clone();
+        let occupied = self
+            .watchers
+            .values()
+            .any(|other| other.id != id && other.is_logged_in() && other.room == room_name);
+        if occupied {
+            if let Some(room) = self.rooms.get_mut(&room_name) {
+                room.remove_controller(&name);
+            }
+        } else {
+            self.rooms.remove(&room_name);
+        }
+    }
+
+    fn force_position_update(&mut self, id: u64, do_seek: bool, watcher_paused: bool) {
+        let Some(watcher) = self.watchers.get(&id) else {
+            return;
+        };
+        let name = watcher.name().to_string();
+        let room_name = watcher.room.clone();
+        let watcher_position = watcher.position;
+        let Some(room) = self.rooms.get_mut(&room_name) else {
+            return;
+        };
+
+        if room.can_control(&name) {
+            let position = watcher_position.unwrap_or_else(|| room.position());
+            room.set_position(position, Some(name.clone()));
+            let paused = room.is_paused();
+            for other in self.watchers.values_mut() {
+                if other.is_logged_in() && other.room == room_name {
+                    other.send_state(position, paused, do_seek, Some(name.clone()), true);
+                }
+            }
+        } else {
+            // Not allowed to change the room: snap the client back
+            let position = room.position();
+            let paused = room.is_paused();
+            let set_by = room.set_by().map(str::to_string);
+            if let Some(watcher) = self.watchers.get_mut(&id) {
+                watcher.send_state(position, watcher_paused, false, Some(name), true);
+                watcher.send_state(position, paused, true, set_by, true);
+            }
+        }
+    }
+
+    /// Room position, following the slowest watcher that has a file open
+    fn room_position(&mut self, room_name: &str) -> f64 {
+        let Some(room) = self.rooms.get(room_name) else {
+            return 0.0;
+        };
+        if room.age() > 1.0 {
+            let paused = room.is_paused();
+            let slowest = self
+                .watchers
+                .values()
+                .filter(|watcher| {
+                    watcher.is_logged_in()
+                        && watcher.room == room_name
+                        && watcher.file.is_some()
+                        && (!room.is_controlled() || room.is_controller(watcher.name()))
+                })
+                .filter_map(|watcher| {
+                    watcher
+                        .current_position(paused)
+                        .map(|position| (position, watcher.name().to_string()))
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            if let Some((position, name)) = slowest {
+                if let Some(room) = self.rooms.get_mut(room_name) {
+                    room.set_position(position, Some(name));
+                }
+            }
+        }
+        self.rooms.get(room_name).map(Room::position).unwrap_or(0.0)
+    }
+
+    fn server_features(&self) -> ClientFeatures {
+        let extra = HashMap::from([
+            (
+                "isolateRooms".to_string(),
+                Value::from(self.options.isolate_rooms),
+            ),
+            (
+                "readiness".to_string(),
+                Value::from(!self.options.disable_ready),
+            ),
+            (
+                "maxChatMessageLength".to_string(),
+                Value::from(self.options.max_chat_message_length),
+            ),
+            (
+                "maxUsernameLength".to_string(),
+                Value::from(self.options.max_username_length),
+            ),
+            (
+                "maxRoomNameLength".to_string(),
+                Value::from(self.options.max_room_name_length),
+            ),
+            (
+                "maxFilenameLength".to_string(),
+                Value::from(self.options.max_filename_length),
+            ),
+        ]);
+        ClientFeatures {
+            shared_playlists: None,
+            chat: Some(!self.options.disable_chat),
+            ready_state: None,
+            managed_rooms: Some(true),
+            persistent_rooms: Some(false),
+            extra,
+        }
+    }
+
+    fn unique_username(&self, username: String) -> String {
+        let mut candidate = username;
+        while self
+            .watchers
+            .values()
+            .any(|watcher| watcher.username.as_deref() == Some(candidate.as_str()))
+        {
+            candidate.push('_');
+        }
+        candidate
+    }
+
+    fn normalize_room_name(&self, room: Option<String>) -> String {
+        let room = room.unwrap_or_default();
+        let room = truncate_text(room.trim(), self.options.max_room_name_length);
+        if room.is_empty() {
+            DEFAULT_ROOM.to_string()
+        } else {
+            room
+        }
+    }
+
+    fn shares_scope(&self, watcher: &Watcher, room: &str) -> bool {
+        !self.options.isolate_rooms || watcher.room == room
+    }
+
+    /// Send to everyone who can see `room`: the room itself when rooms are
+    /// isolated, otherwise the whole server
+    fn broadcast(&self, room: &str, message: ProtocolMessage) {
+        for watcher in self.watchers.values() {
+            if watcher.is_logged_in() && self.shares_scope(watcher, room) {
+                watcher.send(message.clone());
+            }
+        }
+    }
+
+    fn broadcast_room(&self, room: &str, message: ProtocolMessage) {
+        for watcher in self.watchers.values() {
+            if watcher.is_logged_in() && watcher.room == room {
+                watcher.send(message.clone());
+            }
+        }
+    }
+
+    fn send_to(&self, id: u64, message: ProtocolMessage) {
+        if let Some(watcher) = self.watchers.get(&id) {
+            watcher.send(message);
+        }
+    }
+
+    fn send_error(&self, id: u64, message: &str) {
+        self.send_to(
+            id,
+            ProtocolMessage::Error {
+                Error: ErrorMessage {
+                    message: message.to_string(),
+                },
+            },
+        );
+    }
+}
+
+fn is_supported_version(version: &str) -> bool {
+    let mut parts = version.split('.');
+    let major = parts.next().and_then(|part| part.parse::<u32>().ok());
+    let minor = parts.next().and_then(|part| part.parse::<u32>().ok());
+    matches!((major, minor), (Some(1), Some(minor)) if minor >= 2)
+}
+
+fn user_update_message(watcher: &Watcher, event: Option<UserEvent>) -> ProtocolMessage {
+    let update = UserUpdate {
+        room: Some(RoomInfo {
+            name: watcher.room.clone(),
+            password: None,
+        }),
+        file: Some(watcher.file.clone().unwrap_or(FileInfo {
+            name: None,
+            size: None,
+            duration: None,
+        })),
+        event,
+        controller: None,
+        is_ready: None,
+        features: None,
+    };
+    ProtocolMessage::Set {
+        Set: Box::new(SetMessage {
+            user: Some(HashMap::from([(watcher.name().to_string(), update)])),
+            ..Default::default()
+        }),
+    }
+}
+
+fn playlist_change_message(user: Option<String>, files: Vec<String>) -> ProtocolMessage {
+    ProtocolMessage::Set {
+        Set: Box::new(SetMessage {
+            playlist_change: Some(PlaylistChange { user, files }),
+            ..Default::default()
+        }),
+    }
+}
+
+fn playlist_index_message(user: Option<String>, index: Option<usize>) -> ProtocolMessage {
+    ProtocolMessage::Set {
+        Set: Box::new(SetMessage {
+            playlist_index: Some(PlaylistIndexUpdate { user, index }),
+            ..Default::default()
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supported_versions() {
+        assert!(is_supported_version("1.2.255"));
+        assert!(is_supported_version("1.7.4"));
+        assert!(!is_supported_version("1.1.0"));
+        assert!(!is_supported_version("2.0.0"));
+        assert!(!is_supported_version("garbage"));
+    }
+}
@@ -0,0 +1,113 @@
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+use crate::network::messages::{
+    ClientFeatures, FileInfo, IgnoringInfo, PingInfo, PlayState, ProtocolMessage, StateMessage,
+};
+use crate::network::ping::PingService;
+
+/// A connected client, as seen by the server
+pub struct Watcher {
+    pub id: u64,
+    /// Set once the Hello handshake succeeded
+    pub username: Option<String>,
+    pub room: String,
+    pub version: String,
+    pub features: Option<ClientFeatures>,
+    pub file: Option<FileInfo>,
+    pub is_ready: Option<bool>,
+    pub position: Option<f64>,
+    pub position_updated: Instant,
+    pub ping: PingService,
+    pub client_latency_calculation: Option<f64>,
+    pub server_ignoring_on_the_fly: u32,
+    pub client_ignoring_on_the_fly: u32,
+    tx: mpsc::UnboundedSender<ProtocolMessage>,
+}
+
+impl Watcher {
+    pub fn new(id: u64, tx: mpsc::UnboundedSender<ProtocolMessage>) -> Self {
+        Self {
+            id,
+            username: None,
+            room: String::new(),
+            version: String::new(),
+            features: None,
+            file: None,
+            is_ready: None,
+            position: None,
+            position_updated: Instant::now(),
+            ping: PingService::default(),
+            client_latency_calculation: None,
+            server_ignoring_on_the_fly: 0,
+            client_ignoring_on_the_fly: 0,
+            tx,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.username.as_deref().unwrap_or_default()
+    }
+
+    pub fn is_logged_in(&self) -> bool {
+        self.username.is_some()
+    }
+
+    pub fn send(&self, message: ProtocolMessage) {
+        if self.tx.send(message).is_err() {
+            tracing::debug!("Dropping message for closed watcher {}", self.id);
+        }
+    }
+
+    /// Last reported position, advanced by wall time if the room is playing
+    pub fn current_position(&self, room_paused: bool) -> Option<f64> {
+        let position = self.position?;
+        if room_paused {
+            Some(position)
+        } else {
+            Some(position + self.position_updated.elapsed().as_secs_f64())
+        }
+    }
+
+    pub fn send_state(
+        &mut self,
+        position: f64,
+        paused: bool,
+        do_seek: bool,
+        set_by: Option<String>,
+        forced: bool,
+    ) {
+        if forced {
+            self.server_ignoring_on_the_fly = self.server_ignoring_on_the_fly.saturating_add(1);
+        }
+        let ping = PingInfo {
+            latency_calculation: Some(PingService::new_timestamp()),
+            client_latency_calculation: self.client_latency_calculation.take(),
+            client_rtt: None,
+            server_rtt: Some(self.ping.get_rtt()),
+        };
+        let ignoring =
+            if self.server_ignoring_on_the_fly != 0 || self.client_ignoring_on_the_fly != 0 {
+                let client = std::mem::take(&mut self.client_ignoring_on_the_fly);
+                Some(IgnoringInfo {
+                    server: (self.server_ignoring_on_the_fly != 0)
+                        .then_some(self.server_ignoring_on_the_fly),
+                    client: (client != 0).then_some(client),
+                })
+            } else {
+                None
+            };
+        self.send(ProtocolMessage::State {
+            State: StateMessage {
+                playstate: Some(PlayState {
+                    position,
+                    paused,
+                    do_seek: Some(do_seek),
+                    set_by,
+                }),
+                ping: Some(ping),
+                ignoring_on_the_fly: ignoring,
+            },
+        });
+    }
+}
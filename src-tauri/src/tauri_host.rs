@@ -0,0 +1,63 @@
+// Tauri adapters for the client core
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+use crate::client::events::ClientEventSink;
+use crate::client::host::ClientHost;
+use crate::config::{save_config, PasswordVault, SyncplayConfig};
+
+/// Forwards client events to the webview and persists config via the store plugin
+pub struct TauriHost<R: Runtime> {
+    app: AppHandle<R>,
+}
+
+impl<R: Runtime> TauriHost<R> {
+    pub fn new(app: AppHandle<R>) -> Self {
+        Self { app }
+    }
+
+    fn password_vault(&self) -> Result<PasswordVault, String> {
+        let dir = self
+            .app
+            .path()
+            .app_data_dir()
+            .map_err(|e| format!("Failed to resolve app data directory: {}", e))?;
+        Ok(PasswordVault::in_dir(&dir))
+    }
+}
+
+impl<R: Runtime> ClientEventSink for TauriHost<R> {
+    fn emit(&self, event: &str, payload: serde_json::Value) {
+        if let Err(e) = self.app.emit(event, payload) {
+            tracing::error!("Failed to emit event {}: {}", event, e);
+        }
+    }
+}
+
+impl<R: Runtime> ClientHost for TauriHost<R> {
+    fn save_config(&self, config: &SyncplayConfig) -> Result<(), String> {
+        save_config(&self.app, config).map_err(|e| e.to_string())
+    }
+
+    fn load_room_passwords(&self) -> Result<HashMap<String, String>, String> {
+        self.password_vault()?
+            .load()
+            .map_err(|e| format!("{:#}", e))
+    }
+
+    fn save_room_passwords(&self, passwords: &HashMap<String, String>) -> Result<(), String> {
+        self.password_vault()?
+            .save(passwords)
+            .map_err(|e| format!("{:#}", e))
+    }
+
+    fn resolve_resource(&self, name: &str) -> Option<PathBuf> {
+        self.app
+            .path()
+            .resolve(name, tauri::path::BaseDirectory::Resource)
+            .ok()
+            .filter(|path| path.exists())
+    }
+}
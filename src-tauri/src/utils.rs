@@ -1,5 +1,6 @@
 use regex::Regex;
 use sha2::{Digest, Sha256};
+use std::net::Ipv6Addr;
 use url::Url;
 
 use crate::config::PrivacyMode;
@@ -255,6 +256,60 @@ pub fn truncate_text(value: &str, max_length: usize) -> String {
     value.chars().take(max_length).collect()
 }
 
+/// Host without the brackets of an IPv6 literal such as `[::1]`
+pub fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+    host.strip_prefix('[')
+        .and_then(|inner| inner.strip_suffix(']'))
+        .unwrap_or(host)
+        .to_string()
+}
+
+/// `host:port`, with IPv6 literals in brackets
+pub fn format_server_address(host: &str, port: u16) -> String {
+    let host = normalize_host(host);
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// Split `host`, `host:port`, `[v6]`, `[v6]:port` or a bare IPv6 literal
+pub fn parse_server_address(address: &str, default_port: u16) -> Result<(String, u16), String> {
+    let address = address.trim();
+    let invalid = || format!("Invalid server address: {}", address);
+    let (host, port) = if let Some(rest) = address.strip_prefix('[') {
+        let (host, after) = rest.split_once(']').ok_or_else(invalid)?;
+        if host.parse::<Ipv6Addr>().is_err() {
+            return Err(invalid());
+        }
+        match after {
+            "" => (host, None),
+            _ => (host, Some(after.strip_prefix(':').ok_or_else(invalid)?)),
+        }
+    } else if address.parse::<Ipv6Addr>().is_ok() {
+        (address, None)
+    } else {
+        match address.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (address, None),
+        }
+    };
+    if host.is_empty() || (host.contains(':') && host.parse::<Ipv6Addr>().is_err()) {
+        return Err(invalid());
+    }
+    let port = match port {
+        Some(port) => port
+            .parse::<u16>()
+            .ok()
+            .filter(|port| *port > 0)
+            .ok_or_else(|| format!("Invalid port in server address: {}", address))?,
+        None => default_port,
+    };
+    Ok((host.to_string(), port))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -288,4 +343,32 @@ mod tests {
         let text = truncate_text("hello", 3);
         assert_eq!(text, "hel");
     }
+
+    #[test]
+    fn test_parse_server_address() {
+        let parse = |value| parse_server_address(value, 8999);
+        assert_eq!(parse("syncplay.pl"), Ok(("syncplay.pl".to_string(), 8999)));
+        assert_eq!(
+            parse("syncplay.pl:8995"),
+            Ok(("syncplay.pl".to_string(), 8995))
+        );
+        assert_eq!(parse("[::1]:8997"), Ok(("::1".to_string(), 8997)));
+        assert_eq!(
+            parse("[2001:db8::1]"),
+            Ok(("2001:db8::1".to_string(), 8999))
+        );
+        assert_eq!(parse("2001:db8::1"), Ok(("2001:db8::1".to_string(), 8999)));
+        assert!(parse("[::1]8997").is_err());
+        assert!(parse("[syncplay.pl]:8997").is_err());
+        assert!(parse("a:b:c").is_err());
+        assert!(parse("syncplay.pl:0").is_err());
+        assert!(parse(":8999").is_err());
+
+        assert_eq!(format_server_address("::1", 8999), "[::1]:8999");
+        assert_eq!(format_server_address("[::1]", 8999), "[::1]:8999");
+        assert_eq!(
+            format_server_address("syncplay.pl", 8999),
+            "syncplay.pl:8999"
+        );
+    }
 }
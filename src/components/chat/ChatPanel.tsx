@@ -1,14 +1,17 @@
 import { useState, useRef, useEffect } from "react";
 import { useSyncplayStore } from "../../store";
 import { invoke } from "@tauri-apps/api/core";
+import { SessionTabs } from "../layout/SessionTabs";
 
 export function ChatPanel() {
   const messages = useSyncplayStore((state) => state.messages);
   const connection = useSyncplayStore((state) => state.connection);
+  const activeSessionId = useSyncplayStore((state) => state.activeSessionId);
   const config = useSyncplayStore((state) => state.config);
+  const capabilities = useSyncplayStore((state) => state.capabilities);
   const [inputValue, setInputValue] = useState("");
   const messagesEndRef = useRef<HTMLDivElement>(null);
-  const chatInputEnabled = config?.user.chat_input_enabled ?? true;
+  const chatInputEnabled = (config?.user.chat_input_enabled ?? true) && capabilities.chat;
 
   // Auto-scroll to bottom when new messages arrive
   useEffect(() => {
@@ -19,7 +22,7 @@ export function ChatPanel() {
     if (!inputValue.trim() || !connection.connected || !chatInputEnabled) return;
 
     try {
-      await invoke("send_chat_message", { message: inputValue });
+      await invoke("send_chat_message", { message: inputValue, sessionId: activeSessionId });
       setInputValue("");
     } catch (error) {
       console.error("Failed to send message:", error);
@@ -55,6 +58,8 @@ export function ChatPanel() {
 
   return (
     <div className="flex flex-col h-full">
+      <SessionTabs />
+
       {/* Messages area */}
       <div className="flex-1 p-5 pt-7 overflow-auto space-y-3">
         {messages.length === 0 ? (
@@ -87,10 +92,13 @@ export function ChatPanel() {
           placeholder={
             !connection.connected
               ? "Not connected"
-              : chatInputEnabled
-                ? "Type a message... (or /help for commands)"
-                : "Chat input disabled"
+              : !capabilities.chat
+                ? "Chat is not supported by this server"
+                : chatInputEnabled
+                  ? "Type a message... (or /help for commands)"
+                  : "Chat input disabled"
           }
+          maxLength={capabilities.maxChatMessageLength}
           className="w-full app-input px-4 py-2 rounded-md focus:outline-none focus:border-blue-500"
           disabled={!connection.connected || !chatInputEnabled}
         />
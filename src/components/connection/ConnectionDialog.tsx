@@ -3,7 +3,29 @@ import { LuSettings } from "react-icons/lu";
 import { useSyncplayStore } from "../../store";
 import { useNotificationStore } from "../../store/notifications";
 import { invoke } from "@tauri-apps/api/core";
-import { PublicServer, SyncplayConfig } from "../../types/config";
+import { ProbedServer, PublicServer, SyncplayConfig } from "../../types/config";
+import { DiagnosticCheck, DiagnosticReport, DiagnosticStep } from "../../services/tauri";
+
+const DIAGNOSTIC_LABELS: Record<DiagnosticCheck, string> = {
+  dns: "DNS lookup",
+  tcp_connect: "TCP connect",
+  start_tls: "TLS support",
+  certificate: "Certificate",
+  hello: "Hello handshake",
+};
+
+const DIAGNOSTIC_MARKS: Record<DiagnosticStep["status"], string> = {
+  passed: "✓",
+  warning: "!",
+  failed: "✗",
+  skipped: "–",
+};
+
+// IPv6 literals need brackets to carry a port
+function formatAddress(host: string, port: number) {
+  const bare = host.replace(/^\[(.*)\]$/, "$1");
+  return bare.includes(":") ? `[${bare}]:${port}` : `${bare}:${port}`;
+}
 
 interface ConnectionDialogProps {
   isOpen: boolean;
@@ -147,6 +169,10 @@ function ComboBox({
 
 export function ConnectionDialog({ isOpen, onClose }: ConnectionDialogProps) {
   const connection = useSyncplayStore((state) => state.connection);
+  const publicServers = useSyncplayStore((state) => state.publicServers);
+  const refreshPublicServers = useSyncplayStore((state) => state.refreshPublicServers);
+  const openSession = useSyncplayStore((state) => state.openSession);
+  const closeSession = useSyncplayStore((state) => state.closeSession);
   const addNotification = useNotificationStore((state) => state.addNotification);
   const [config, setConfig] = useState<SyncplayConfig | null>(null);
   const [activeTab, setActiveTab] = useState<"connection" | "player">("connection");
@@ -164,8 +190,19 @@ export function ConnectionDialog({ isOpen, onClose }: ConnectionDialogProps) {
   const [playersUpdatedAt, setPlayersUpdatedAt] = useState<number | null>(null);
   const [playersError, setPlayersError] = useState<string | null>(null);
   const [playerArgsInput, setPlayerArgsInput] = useState("");
-
-  const serverOptions = buildServerOptions(config?.recent_servers ?? [], config?.public_servers);
+  const [serverListError, setServerListError] = useState<string | null>(null);
+  const [diagnostics, setDiagnostics] = useState<DiagnosticReport | null>(null);
+  const [isDiagnosing, setIsDiagnosing] = useState(false);
+  const connectCancelledRef = useRef(false);
+  // Join another server or room next to the current session
+  const [inNewSession, setInNewSession] = useState(false);
+  const pendingSessionRef = useRef<string | null>(null);
+  const showForm = !connection.connected || inNewSession;
+
+  const serverOptions = buildServerOptions(
+    config?.recent_servers ?? [],
+    publicServers ?? config?.public_servers
+  );
   const roomOptions = config?.user.room_list ?? [];
 
   useEffect(() => {
@@ -175,7 +212,7 @@ export function ConnectionDialog({ isOpen, onClose }: ConnectionDialogProps) {
         const loaded = await invoke<SyncplayConfig>("get_config");
         setConfig(loaded);
         setFormData({
-          address: `${loaded.server.host}:${loaded.server.port}`,
+          address: formatAddress(loaded.server.host, loaded.server.port),
           username: loaded.user.username,
           room: loaded.user.default_room,
           password: loaded.server.password || "",
@@ -189,6 +226,13 @@ export function ConnectionDialog({ isOpen, onClose }: ConnectionDialogProps) {
     loadConfig();
   }, [isOpen]);
 
+  useEffect(() => {
+    if (!isOpen) return;
+    refreshPublicServers()
+      .then(() => setServerListError(null))
+      .catch((err) => setServerListError(`Could not refresh public servers: ${err}`));
+  }, [isOpen]);
+
   useEffect(() => {
     if (!config) return;
     const args = config.player.player_arguments || [];
@@ -207,7 +251,7 @@ export function ConnectionDialog({ isOpen, onClose }: ConnectionDialogProps) {
 
   const handleAddressSelect = (value: string) => {
     const entry = config?.recent_servers.find(
-      (server) => `${server.host}:${server.port}` === value
+      (server) => formatAddress(server.host, server.port) === value
     );
     setFormData((prev) => ({
       ...prev,
@@ -221,12 +265,23 @@ export function ConnectionDialog({ isOpen, onClose }: ConnectionDialogProps) {
     if (!trimmed) {
       return null;
     }
-    const lastColon = trimmed.lastIndexOf(":");
-    if (lastColon <= 0 || lastColon === trimmed.length - 1) {
-      return null;
+    let host: string;
+    let portValue: string;
+    const bracketed = /^\[([0-9a-fA-F:.]+)\]:(\d+)$/.exec(trimmed);
+    if (bracketed) {
+      [, host, portValue] = bracketed;
+    } else {
+      const lastColon = trimmed.lastIndexOf(":");
+      if (lastColon <= 0 || lastColon === trimmed.length - 1) {
+        return null;
+      }
+      host = trimmed.slice(0, lastColon).trim();
+      portValue = trimmed.slice(lastColon + 1).trim();
+      // An unbracketed IPv6 literal has no unambiguous port
+      if (host.includes(":")) {
+        return null;
+      }
     }
-    const host = trimmed.slice(0, lastColon).trim();
-    const portValue = trimmed.slice(lastColon + 1).trim();
     const port = Number.parseInt(portValue, 10);
     if (!host || Number.isNaN(port) || port <= 0 || port > 65535) {
       return null;
@@ -241,7 +296,8 @@ export function ConnectionDialog({ isOpen, onClose }: ConnectionDialogProps) {
   ): SyncplayConfig => ({
     ...base,
     server: {
-      ...base.server,
+      // Trust settings belong to the address, not to whichever server was last used
+      ...base.recent_servers.find((entry) => entry.host === host && entry.port === port),
       host,
       port,
       password: formData.password || null,
@@ -334,27 +390,59 @@ export function ConnectionDialog({ isOpen, onClose }: ConnectionDialogProps) {
     port: number,
     password: string | null
   ) => {
+    const existing = servers.find((entry) => entry.host === host && entry.port === port);
     const next = servers.filter((entry) => entry.host !== host || entry.port !== port);
-    next.unshift({ host, port, password });
+    next.unshift({ ...existing, host, port, password });
     return next.slice(0, 10);
   };
 
+  function formatServerLabel(server: PublicServer | ProbedServer) {
+    if (!("latencyMs" in server)) {
+      return server.name;
+    }
+    if (server.latencyMs === null) {
+      return `${server.name} (unreachable)`;
+    }
+    return `${server.name} (${Math.round(server.latencyMs)} ms)`;
+  }
+
   function buildServerOptions(
     recent: SyncplayConfig["recent_servers"],
-    publicServers?: PublicServer[]
+    publicServers?: Array<PublicServer | ProbedServer>
   ) {
     const recentOptions = recent.map((server) => ({
-      label: `${server.host}:${server.port}`,
-      value: `${server.host}:${server.port}`,
+      label: formatAddress(server.host, server.port),
+      value: formatAddress(server.host, server.port),
     }));
     const publicOptions =
       publicServers?.map((server) => ({
-        label: server.name,
+        label: formatServerLabel(server),
         value: server.address,
       })) ?? [];
     return [...publicOptions, ...recentOptions];
   }
 
+  const handleDiagnose = async () => {
+    const address = parseAddress(formData.address);
+    if (!address) {
+      setError("Address must be in host:port or [ipv6]:port format");
+      return;
+    }
+
+    setIsDiagnosing(true);
+    try {
+      const report = await invoke<DiagnosticReport>("diagnose_server", {
+        host: address.host,
+        port: address.port,
+      });
+      setDiagnostics(report);
+    } catch (err) {
+      setError(err as string);
+    } finally {
+      setIsDiagnosing(false);
+    }
+  };
+
   const handleConnect = async (saveConfig: boolean) => {
     if (!formData.username.trim()) {
       setError("Username is required");
@@ -363,12 +451,14 @@ export function ConnectionDialog({ isOpen, onClose }: ConnectionDialogProps) {
 
     const address = parseAddress(formData.address);
     if (!address) {
-      setError("Address must be in host:port format");
+      setError("Address must be in host:port or [ipv6]:port format");
       return;
     }
 
     setIsConnecting(true);
+    connectCancelledRef.current = false;
     setError(null);
+    setDiagnostics(null);
 
     try {
       if (saveConfig) {
@@ -395,19 +485,38 @@ export function ConnectionDialog({ isOpen, onClose }: ConnectionDialogProps) {
         setConfig(updated);
       }
 
+      if (inNewSession) {
+        pendingSessionRef.current = await openSession();
+      }
       await invoke("connect_to_server", {
         host: address.host,
         port: address.port,
         username: formData.username,
         room: formData.room,
         password: formData.password || null,
+        sessionId: pendingSessionRef.current,
       });
+      const target = formatAddress(address.host, address.port);
       addNotification({
         type: "success",
-        message: `Connected to ${address.host}:${address.port}`,
+        message: inNewSession
+          ? `Connected to ${target} in a new session`
+          : `Connected to ${target}`,
       });
+      pendingSessionRef.current = null;
+      setInNewSession(false);
       onClose();
     } catch (err) {
+      const pendingSession = pendingSessionRef.current;
+      pendingSessionRef.current = null;
+      if (pendingSession) {
+        closeSession(pendingSession).catch((error) => {
+          console.warn("Failed to close session", error);
+        });
+      }
+      if (connectCancelledRef.current) {
+        return;
+      }
       setError(err as string);
       addNotification({
         type: "error",
@@ -418,6 +527,24 @@ export function ConnectionDialog({ isOpen, onClose }: ConnectionDialogProps) {
     }
   };
 
+  const handleCancel = async () => {
+    if (!isConnecting) {
+      setInNewSession(false);
+      onClose();
+      return;
+    }
+    connectCancelledRef.current = true;
+    try {
+      await invoke("cancel_connection", { sessionId: pendingSessionRef.current });
+      addNotification({
+        type: "info",
+        message: "Connection attempt cancelled",
+      });
+    } catch (err) {
+      setError(err as string);
+    }
+  };
+
   const handleDisconnect = async () => {
     try {
       await invoke("disconnect_from_server");
@@ -438,10 +565,10 @@ export function ConnectionDialog({ isOpen, onClose }: ConnectionDialogProps) {
     >
       <div className="app-panel app-panel-glass rounded-xl p-6 w-full max-w-2xl shadow-xl">
         <h2 className="text-xl font-bold mb-4">
-          {connection.connected ? "Connected" : "Connect to Server"}
+          {showForm ? "Connect to Server" : "Connected"}
         </h2>
 
-        {!connection.connected && (
+        {showForm && (
           <div className="flex flex-wrap gap-2 mb-4 border-b app-divider">
             {[
               { id: "connection", label: "Connection" },
@@ -459,7 +586,7 @@ export function ConnectionDialog({ isOpen, onClose }: ConnectionDialogProps) {
           </div>
         )}
 
-        {connection.connected ? (
+        {!showForm ? (
           <div className="space-y-4">
             <div className="app-panel-muted p-4 rounded-lg">
               <p className="text-sm app-text-muted">
@@ -471,6 +598,12 @@ export function ConnectionDialog({ isOpen, onClose }: ConnectionDialogProps) {
               <button onClick={handleDisconnect} className="flex-1 btn-danger px-4 py-2 rounded">
                 Disconnect
               </button>
+              <button
+                onClick={() => setInNewSession(true)}
+                className="flex-1 btn-secondary px-4 py-2 rounded-md"
+              >
+                Join another server
+              </button>
               <button onClick={onClose} className="flex-1 btn-neutral px-4 py-2 rounded-md">
                 Close
               </button>
@@ -493,6 +626,7 @@ export function ConnectionDialog({ isOpen, onClose }: ConnectionDialogProps) {
                   onChange={(value) => setFormData({ ...formData, address: value })}
                   onSelect={handleAddressSelect}
                   placeholder="syncplay.pl:8999"
+                  helperText={serverListError ?? undefined}
                 />
 
                 <div>
@@ -683,6 +817,34 @@ export function ConnectionDialog({ isOpen, onClose }: ConnectionDialogProps) {
 
             {error && <div className="app-alert app-alert-danger px-4 py-2 text-sm">{error}</div>}
 
+            {error && !diagnostics && (
+              <button
+                type="button"
+                onClick={handleDiagnose}
+                disabled={isDiagnosing}
+                className="text-sm app-text-muted underline disabled:opacity-60"
+              >
+                {isDiagnosing ? "Diagnosing..." : "Diagnose connection"}
+              </button>
+            )}
+
+            {diagnostics && (
+              <div className="text-xs space-y-1">
+                {diagnostics.steps.map((step) => (
+                  <div
+                    key={step.check}
+                    className={step.status === "skipped" ? "app-text-muted" : ""}
+                  >
+                    <span className="font-mono">{DIAGNOSTIC_MARKS[step.status]}</span>{" "}
+                    <span className="font-medium">{DIAGNOSTIC_LABELS[step.check]}</span>:{" "}
+                    {step.detail}
+                    {step.durationMs !== null && ` (${Math.round(step.durationMs)} ms)`}
+                  </div>
+                ))}
+                {diagnostics.motd && <p className="app-text-muted">MOTD: {diagnostics.motd}</p>}
+              </div>
+            )}
+
             <div className="flex gap-2">
               <button
                 type="submit"
@@ -701,7 +863,7 @@ export function ConnectionDialog({ isOpen, onClose }: ConnectionDialogProps) {
               </button>
               <button
                 type="button"
-                onClick={onClose}
+                onClick={handleCancel}
                 className="flex-1 btn-neutral px-4 py-2 rounded-md"
               >
                 Cancel
@@ -18,6 +18,7 @@ import {
   LuZap,
 } from "react-icons/lu";
 import { getCurrentWindow } from "@tauri-apps/api/window";
+import { listen } from "@tauri-apps/api/event";
 import { useWindowDrag } from "../../hooks/useWindowDrag";
 import { PlaylistPanel } from "../playlist/PlaylistPanel";
 import { ConnectionDialog } from "../connection/ConnectionDialog";
@@ -46,6 +47,7 @@ export function MainLayout() {
   const [transparencyMode, setTransparencyMode] = useState<TransparencyPreference>("off");
   const connection = useSyncplayStore((state) => state.connection);
   const tlsStatus = useSyncplayStore((state) => state.tlsStatus);
+  const link = useSyncplayStore((state) => state.link);
   const rttMs = useSyncplayStore((state) => state.rttMs);
   const config = useSyncplayStore((state) => state.config);
   const setConfig = useSyncplayStore((state) => state.setConfig);
@@ -102,6 +104,24 @@ export function MainLayout() {
     initFromConfig();
   }, [connection.connected, addNotification, setConfig]);
 
+  useEffect(() => {
+    if (!isTauri()) return;
+    // A rejected server password ends the session; ask for it again
+    const unlisten = listen<{ server: string; message: string }>(
+      "server-password-required",
+      (event) => {
+        addNotification({
+          type: "error",
+          message: `Wrong password for ${event.payload.server}`,
+        });
+        setShowConnectionDialog(true);
+      }
+    );
+    return () => {
+      void unlisten.then((stop) => stop());
+    };
+  }, [addNotification]);
+
   useEffect(() => {
     if (!config) return;
     if (showPlaylistRef.current !== config.user.show_playlist) {
@@ -208,6 +228,16 @@ export function MainLayout() {
     return `${rounded}ms`;
   };
   const rttLabel = formatRtt(rttMs);
+  const linkLabels: Partial<Record<typeof link.state, string>> = {
+    connecting: "Connecting",
+    reconnecting: "Reconnecting",
+    tls_negotiating: "Securing connection",
+    authenticating: "Logging in",
+    stale: "Server not responding",
+  };
+  const linkLabel =
+    linkLabels[link.state] ??
+    (link.state === "disconnected" && link.reason !== "user_request" ? "Disconnected" : null);
 
   return (
     <div className="app-shell">
@@ -312,6 +342,15 @@ export function MainLayout() {
                   </button>
                 </div>
                 <div className="flex items-center gap-2 ml-auto">
+                  {linkLabel && (
+                    <div
+                      className="flex items-center px-2.5 py-1 rounded-full text-xs app-panel-muted"
+                      aria-label={linkLabel}
+                      title={link.detail ?? linkLabel}
+                    >
+                      {linkLabel}
+                    </div>
+                  )}
                   {connection.connected && rttLabel && (
                     <div
                       className="flex items-center gap-2 app-panel-muted px-2.5 py-1 rounded-full text-xs"
@@ -0,0 +1,83 @@
+import { LuX } from "react-icons/lu";
+import { DEFAULT_SESSION_ID, SessionView, useSyncplayStore } from "../../store";
+import { useNotificationStore } from "../../store/notifications";
+
+function sessionLabel(view: SessionView) {
+  if (!view.connection.connected || !view.connection.server) {
+    return "Not connected";
+  }
+  return view.connection.server;
+}
+
+// Switches which session drives playback; hidden while only one is open
+export function SessionTabs() {
+  const sessions = useSyncplayStore((state) => state.sessions);
+  const activeSessionId = useSyncplayStore((state) => state.activeSessionId);
+  const setActiveSession = useSyncplayStore((state) => state.setActiveSession);
+  const closeSession = useSyncplayStore((state) => state.closeSession);
+  const addNotification = useNotificationStore((state) => state.addNotification);
+
+  const entries = Object.entries(sessions);
+  if (entries.length < 2) {
+    return null;
+  }
+
+  const handleSelect = (sessionId: string) => {
+    if (sessionId === activeSessionId) return;
+    setActiveSession(sessionId).catch((error) => {
+      addNotification({
+        type: "error",
+        message: `Failed to switch session: ${error}`,
+      });
+    });
+  };
+
+  const handleClose = (sessionId: string) => {
+    closeSession(sessionId).catch((error) => {
+      addNotification({
+        type: "error",
+        message: `Failed to close session: ${error}`,
+      });
+    });
+  };
+
+  return (
+    <div
+      className="flex flex-wrap gap-1 px-5 pt-4 border-b app-divider"
+      data-tauri-drag-region="false"
+    >
+      {entries.map(([sessionId, view]) => (
+        <div
+          key={sessionId}
+          className={`flex items-center gap-1 app-tab ${
+            sessionId === activeSessionId ? "app-tab-active" : ""
+          }`}
+        >
+          <button
+            type="button"
+            onClick={() => handleSelect(sessionId)}
+            className="px-3 py-1.5 text-sm truncate max-w-[14rem]"
+            title={sessionLabel(view)}
+          >
+            {sessionLabel(view)}
+            {view.unreadMessages > 0 && (
+              <span className="ml-2 px-1.5 rounded-full text-xs app-tag-success">
+                {view.unreadMessages}
+              </span>
+            )}
+          </button>
+          {sessionId !== DEFAULT_SESSION_ID && (
+            <button
+              type="button"
+              onClick={() => handleClose(sessionId)}
+              className="p-1 app-text-muted"
+              aria-label="Close session"
+            >
+              <LuX className="app-icon" />
+            </button>
+          )}
+        </div>
+      ))}
+    </div>
+  );
+}
@@ -26,6 +26,7 @@ export function PlaylistPanel() {
   const player = useSyncplayStore((state) => state.player);
   const config = useSyncplayStore((state) => state.config);
   const setConfig = useSyncplayStore((state) => state.setConfig);
+  const playlistSupported = useSyncplayStore((state) => state.capabilities.sharedPlaylists);
   const addNotification = useNotificationStore((state) => state.addNotification);
   const [showMediaDirectories, setShowMediaDirectories] = useState(false);
   const [showTrustedDomains, setShowTrustedDomains] = useState(false);
@@ -189,7 +190,7 @@ export function PlaylistPanel() {
             <div className="flex items-center gap-2 flex-1">
               <button
                 onClick={handleAddFile}
-                disabled={!connection.connected}
+                disabled={!connection.connected || !playlistSupported}
                 className="btn-primary app-icon-button disabled:opacity-60 disabled:cursor-not-allowed"
                 aria-label="Add"
               >
@@ -197,7 +198,9 @@ export function PlaylistPanel() {
               </button>
               <button
                 onClick={handleClear}
-                disabled={!connection.connected || playlist.items.length === 0}
+                disabled={
+                  !connection.connected || !playlistSupported || playlist.items.length === 0
+                }
                 className="btn-danger app-icon-button disabled:opacity-60 disabled:cursor-not-allowed"
                 aria-label="Clear"
               >
@@ -261,7 +264,7 @@ export function PlaylistPanel() {
                   <span className="truncate flex-1">{item}</span>
                   <button
                     onClick={() => handleRemoveItem(index)}
-                    disabled={!connection.connected}
+                    disabled={!connection.connected || !playlistSupported}
                     className="ml-2 app-text-danger hover:opacity-80 disabled:opacity-60 app-tooltip"
                     aria-label="Remove"
                   >
@@ -282,6 +285,7 @@ export function PlaylistPanel() {
               onClick={handlePrevious}
               disabled={
                 !connection.connected ||
+                !playlistSupported ||
                 playlist.items.length === 0 ||
                 playlist.currentIndex === null ||
                 playlist.currentIndex === 0
@@ -295,6 +299,7 @@ export function PlaylistPanel() {
               onClick={handleNext}
               disabled={
                 !connection.connected ||
+                !playlistSupported ||
                 playlist.items.length === 0 ||
                 playlist.currentIndex === null ||
                 playlist.currentIndex >= playlist.items.length - 1
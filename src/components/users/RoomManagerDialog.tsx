@@ -1,6 +1,7 @@
 import { useEffect, useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import { SyncplayConfig } from "../../types/config";
+import { useSyncplayStore } from "../../store";
 import { useNotificationStore } from "../../store/notifications";
 
 interface RoomManagerDialogProps {
@@ -14,6 +15,7 @@ export function RoomManagerDialog({ isOpen, onClose }: RoomManagerDialogProps) {
   const [connecting, setConnecting] = useState(false);
   const [roomNameInput, setRoomNameInput] = useState("");
   const [roomListInput, setRoomListInput] = useState("");
+  const activeSessionId = useSyncplayStore((state) => state.activeSessionId);
   const addNotification = useNotificationStore((state) => state.addNotification);
 
   useEffect(() => {
@@ -60,7 +62,7 @@ export function RoomManagerDialog({ isOpen, onClose }: RoomManagerDialogProps) {
     if (!trimmed) return;
     setConnecting(true);
     try {
-      await invoke("change_room", { room: trimmed });
+      await invoke("change_room", { room: trimmed, sessionId: activeSessionId });
     } catch (error) {
       addNotification({
         type: "error",
@@ -5,11 +5,18 @@ import { useSyncplayStore } from "../../store";
 import { useNotificationStore } from "../../store/notifications";
 import { RoomManagerDialog } from "./RoomManagerDialog";
 
+// Peers further than this from our playback are shown as out of sync
+const LAG_THRESHOLD_SECONDS = 1;
+// Peers silent for longer than this are shown as idle
+const IDLE_AFTER_MS = 10 * 60 * 1000;
+
 export function UserList() {
   const users = useSyncplayStore((state) => state.users);
   const connection = useSyncplayStore((state) => state.connection);
+  const activeSessionId = useSyncplayStore((state) => state.activeSessionId);
   const config = useSyncplayStore((state) => state.config);
   const player = useSyncplayStore((state) => state.player);
+  const readinessSupported = useSyncplayStore((state) => state.capabilities.readiness);
   const addNotification = useNotificationStore((state) => state.addNotification);
   const [showRoomManager, setShowRoomManager] = useState(false);
   const lastPausedRef = useRef<boolean | null>(null);
@@ -20,13 +27,13 @@ export function UserList() {
   const currentRoom = currentUser?.room ?? config?.user.default_room ?? "Room";
 
   useEffect(() => {
-    if (!connection.connected) {
+    if (!connection.connected || !readinessSupported) {
       lastPausedRef.current = player.paused;
       return;
     }
     const lastPaused = lastPausedRef.current;
     if (player.paused && lastPaused === false && isReady) {
-      void invoke("set_ready", { isReady: false }).catch((error) => {
+      void invoke("set_ready", { isReady: false, sessionId: activeSessionId }).catch((error) => {
         const message =
           typeof error === "string"
             ? error
@@ -38,7 +45,14 @@ export function UserList() {
       });
     }
     lastPausedRef.current = player.paused;
-  }, [player.paused, isReady, connection.connected, addNotification]);
+  }, [
+    player.paused,
+    isReady,
+    connection.connected,
+    readinessSupported,
+    activeSessionId,
+    addNotification,
+  ]);
 
   const handleToggleReady = () => {
     if (!connection.connected) {
@@ -48,7 +62,7 @@ export function UserList() {
       });
       return;
     }
-    void invoke("set_ready", { isReady: !isReady }).catch((error) => {
+    void invoke("set_ready", { isReady: !isReady, sessionId: activeSessionId }).catch((error) => {
       const message =
         typeof error === "string"
           ? error
@@ -91,13 +105,15 @@ export function UserList() {
           <span className="text-xs app-text-muted">({users.length})</span>
         </div>
         <div className="flex items-center gap-2">
-          <button
-            onClick={handleToggleReady}
-            className={`app-icon-button ${isReady ? "btn-primary" : "btn-neutral"}`}
-            aria-label={isReady ? "Ready" : "Not ready"}
-          >
-            {isReady ? <LuCheck className="app-icon" /> : <LuCircle className="app-icon" />}
-          </button>
+          {readinessSupported && (
+            <button
+              onClick={handleToggleReady}
+              className={`app-icon-button ${isReady ? "btn-primary" : "btn-neutral"}`}
+              aria-label={isReady ? "Ready" : "Not ready"}
+            >
+              {isReady ? <LuCheck className="app-icon" /> : <LuCircle className="app-icon" />}
+            </button>
+          )}
           <button
             onClick={() => setShowRoomManager(true)}
             className="btn-neutral app-icon-button"
@@ -135,6 +151,18 @@ export function UserList() {
                 >
                   {user.isReady ? "Ready" : "Not Ready"}
                 </span>
+                {user.syncOffset !== null &&
+                  Math.abs(user.syncOffset) >= LAG_THRESHOLD_SECONDS && (
+                    <span className="text-xs app-tag-muted px-2.5 py-0.5 rounded-full">
+                      {user.syncOffset < 0 ? "Behind" : "Ahead"}{" "}
+                      {Math.abs(user.syncOffset).toFixed(1)}s
+                    </span>
+                  )}
+                {user.lastActivity &&
+                  Date.now() - Date.parse(user.lastActivity) > IDLE_AFTER_MS && (
+                    <span className="text-xs app-tag-muted px-2.5 py-0.5 rounded-full">Idle</span>
+                  )}
+                {user.version && <span className="text-xs app-text-muted">v{user.version}</span>}
               </div>
             </div>
           ))}
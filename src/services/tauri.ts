@@ -1,4 +1,5 @@
 import { invoke } from "@tauri-apps/api/core";
+import { ProbedServer } from "../types/config";
 
 export interface ConnectionParams {
   host: string;
@@ -6,6 +7,51 @@ export interface ConnectionParams {
   username: string;
   room: string;
   password?: string;
+  sessionId?: string;
+}
+
+export interface SessionSummary {
+  id: string;
+  server: string | null;
+  username: string;
+  room: string;
+  connected: boolean;
+  active: boolean;
+}
+
+export interface ConnectionMetrics {
+  statesCoalesced: number;
+  statesDropped: number;
+  queueOverflows: number;
+}
+
+export interface RoomSummary {
+  name: string;
+  userCount: number;
+  readyCount: number;
+  files: string[];
+  hasController: boolean;
+  managed: boolean;
+}
+
+export type DiagnosticCheck = "dns" | "tcp_connect" | "start_tls" | "certificate" | "hello";
+
+export interface DiagnosticStep {
+  check: DiagnosticCheck;
+  status: "passed" | "warning" | "failed" | "skipped";
+  durationMs: number | null;
+  detail: string;
+}
+
+export interface DiagnosticReport {
+  host: string;
+  port: number;
+  steps: DiagnosticStep[];
+  resolvedAddresses: string[];
+  encrypted: boolean;
+  certificateFingerprint: string | null;
+  serverVersion: string | null;
+  motd: string | null;
 }
 
 export const tauriApi = {
@@ -14,30 +60,96 @@ export const tauriApi = {
     return invoke("connect_to_server", { ...params });
   },
 
-  async disconnectFromServer(): Promise<void> {
-    return invoke("disconnect_from_server");
+  async disconnectFromServer(sessionId?: string): Promise<void> {
+    return invoke("disconnect_from_server", { sessionId });
+  },
+
+  async cancelConnection(sessionId?: string): Promise<void> {
+    return invoke("cancel_connection", { sessionId });
+  },
+
+  // Session commands
+  async listSessions(): Promise<SessionSummary[]> {
+    return invoke("list_sessions");
+  },
+
+  async openSession(): Promise<SessionSummary> {
+    return invoke("open_session");
+  },
+
+  async closeSession(sessionId: string): Promise<void> {
+    return invoke("close_session", { sessionId });
   },
 
-  async getConnectionStatus(): Promise<boolean> {
-    return invoke("get_connection_status");
+  async setActiveSession(sessionId: string): Promise<void> {
+    return invoke("set_active_session", { sessionId });
+  },
+
+  async getConnectionStatus(sessionId?: string): Promise<boolean> {
+    return invoke("get_connection_status", { sessionId });
+  },
+
+  async getConnectionMetrics(sessionId?: string): Promise<ConnectionMetrics> {
+    return invoke("get_connection_metrics", { sessionId });
+  },
+
+  async refreshPublicServers(): Promise<ProbedServer[]> {
+    return invoke("refresh_public_servers");
+  },
+
+  async diagnoseServer(host: string, port: number): Promise<DiagnosticReport> {
+    return invoke("diagnose_server", { host, port });
   },
 
   // Chat commands
-  async sendChatMessage(message: string): Promise<void> {
-    return invoke("send_chat_message", { message });
+  async sendChatMessage(message: string, sessionId?: string): Promise<void> {
+    return invoke("send_chat_message", { message, sessionId });
   },
 
   // Room commands
-  async changeRoom(room: string): Promise<void> {
-    return invoke("change_room", { room });
+  async changeRoom(room: string, sessionId?: string): Promise<void> {
+    return invoke("change_room", { room, sessionId });
+  },
+
+  async createManagedRoom(room: string): Promise<void> {
+    return invoke("create_managed_room", { room });
+  },
+
+  async getRoomDirectory(): Promise<RoomSummary[]> {
+    return invoke("get_room_directory");
+  },
+
+  async listOperatorPasswords(): Promise<string[]> {
+    return invoke("list_operator_passwords");
   },
 
-  async setReady(isReady: boolean): Promise<void> {
-    return invoke("set_ready", { isReady });
+  async forgetOperatorPassword(room: string): Promise<void> {
+    return invoke("forget_operator_password", { room });
+  },
+
+  async exportOperatorPasswords(path: string): Promise<number> {
+    return invoke("export_operator_passwords", { path });
+  },
+
+  async setReady(isReady: boolean, sessionId?: string): Promise<void> {
+    return invoke("set_ready", { isReady, sessionId });
   },
 
   // Playlist commands
   async updatePlaylist(action: string, filename?: string): Promise<void> {
     return invoke("update_playlist", { action, filename });
   },
+
+  // Local server commands
+  async startLocalServer(
+    port: number,
+    password?: string,
+    motd?: string,
+  ): Promise<string> {
+    return invoke("start_local_server", { port, password, motd });
+  },
+
+  async stopLocalServer(): Promise<void> {
+    return invoke("stop_local_server");
+  },
 };
@@ -1,14 +1,79 @@
 import { create } from "zustand";
 import { listen } from "@tauri-apps/api/event";
-import { SyncplayConfig } from "../types/config";
+import { ProbedServer, SyncplayConfig } from "../types/config";
+import { tauriApi } from "../services/tauri";
+
+// How long a fetched public server list is reused before refreshing
+const PUBLIC_SERVERS_TTL_MS = 10 * 60 * 1000;
+
+// Session driven by the backend's application-wide state
+export const DEFAULT_SESSION_ID = "default";
 
 // Type definitions matching backend events
 interface ConnectionState {
   connected: boolean;
   server: string | null;
+  reconnecting: boolean;
 }
 
-type TlsStatus = "unknown" | "pending" | "enabled" | "unsupported";
+type TlsStatus = "unknown" | "pending" | "enabled" | "unsupported" | "refused" | "disabled";
+
+type LinkState =
+  | "disconnected"
+  | "connecting"
+  | "reconnecting"
+  | "tls_negotiating"
+  | "authenticating"
+  | "authenticated"
+  | "stale";
+
+type LinkReason =
+  | "user_request"
+  | "handshake"
+  | "reconnect"
+  | "server_error"
+  | "protocol_error"
+  | "io_error"
+  | "tls_failure"
+  | "timeout";
+
+export interface LinkTransition {
+  state: LinkState;
+  reason: LinkReason;
+  detail: string | null;
+}
+
+export interface ServerCapabilities {
+  chat: boolean;
+  sharedPlaylists: boolean;
+  readiness: boolean;
+  managedRooms: boolean;
+  persistentRooms: boolean;
+  isolateRooms: boolean;
+  maxChatMessageLength: number;
+  maxUsernameLength: number | null;
+  maxRoomNameLength: number | null;
+  maxFilenameLength: number | null;
+}
+
+const defaultCapabilities: ServerCapabilities = {
+  chat: true,
+  sharedPlaylists: true,
+  readiness: true,
+  managedRooms: true,
+  persistentRooms: false,
+  isolateRooms: false,
+  maxChatMessageLength: 150,
+  maxUsernameLength: null,
+  maxRoomNameLength: null,
+  maxFilenameLength: null,
+};
+
+const defaultLink: LinkTransition = {
+  state: "disconnected",
+  reason: "user_request",
+  detail: null,
+};
 
 interface User {
   username: string;
@@ -16,6 +81,13 @@ interface User {
   file: string | null;
   isReady: boolean;
   isController: boolean;
+  features: Record<string, unknown> | null;
+  version: string | null;
+  // RFC 3339 timestamps
+  joinedAt: string | null;
+  lastActivity: string | null;
+  // Seconds ahead of the local player, negative when behind; only the slowest viewer is measured
+  syncOffset: number | null;
 }
 
 interface ChatMessage {
@@ -30,6 +102,54 @@ interface PlaylistState {
   currentIndex: number | null;
 }
 
+// Backend events name the session they belong to
+interface SessionTagged {
+  sessionId?: string;
+}
+
+// Per-session slice of the store; the top-level fields mirror the active session
+export interface SessionView {
+  connection: ConnectionState;
+  tlsStatus: TlsStatus;
+  link: LinkTransition;
+  capabilities: ServerCapabilities;
+  users: User[];
+  messages: ChatMessage[];
+  playlist: PlaylistState;
+  rttMs: number | null;
+  unreadMessages: number;
+}
+
+const emptySessionView = (): SessionView => ({
+  connection: {
+    connected: false,
+    server: null,
+    reconnecting: false,
+  },
+  tlsStatus: "unknown",
+  link: defaultLink,
+  capabilities: defaultCapabilities,
+  users: [],
+  messages: [],
+  playlist: {
+    items: [],
+    currentIndex: null,
+  },
+  rttMs: null,
+  unreadMessages: 0,
+});
+
+const visibleFields = (view: SessionView) => ({
+  connection: view.connection,
+  tlsStatus: view.tlsStatus,
+  link: view.link,
+  capabilities: view.capabilities,
+  users: view.users,
+  messages: view.messages,
+  playlist: view.playlist,
+  rttMs: view.rttMs,
+});
+
 interface PlayerState {
   filename: string | null;
   position: number | null;
@@ -42,12 +162,18 @@ interface SyncplayStore {
   // State
   connection: ConnectionState;
   tlsStatus: TlsStatus;
+  link: LinkTransition;
+  capabilities: ServerCapabilities;
   users: User[];
   messages: ChatMessage[];
   playlist: PlaylistState;
   player: PlayerState;
   rttMs: number | null;
   config: SyncplayConfig | null;
+  publicServers: ProbedServer[] | null;
+  publicServersFetchedAt: number | null;
+  activeSessionId: string;
+  sessions: Record<string, SessionView>;
 
   // Actions
   setConnectionStatus: (status: ConnectionState) => void;
@@ -58,6 +184,10 @@ interface SyncplayStore {
   setPlayerState: (state: PlayerState) => void;
   setRttMs: (rttMs: number | null) => void;
   setConfig: (config: SyncplayConfig) => void;
+  refreshPublicServers: (force?: boolean) => Promise<void>;
+  openSession: () => Promise<string>;
+  setActiveSession: (sessionId: string) => Promise<void>;
+  closeSession: (sessionId: string) => Promise<void>;
 
   // Event listener setup
   setupEventListeners: () => void;
@@ -65,136 +195,224 @@ interface SyncplayStore {
 
 let listenersInitialized = false;
 
-export const useSyncplayStore = create<SyncplayStore>((set) => ({
-  // Initial state
-  connection: {
-    connected: false,
-    server: null,
-  },
-  tlsStatus: "unknown",
-  users: [],
-  messages: [],
-  playlist: {
-    items: [],
-    currentIndex: null,
-  },
-  player: {
-    filename: null,
-    position: null,
-    duration: null,
-    paused: true,
-    speed: 1.0,
-  },
-  rttMs: null,
-  config: null,
+export const useSyncplayStore = create<SyncplayStore>((set, get) => {
+  // Apply an update to one session, mirroring it at the top level when active
+  const updateSession = (
+    sessionId: string | undefined,
+    update: (view: SessionView) => Partial<SessionView>
+  ) =>
+    set((state) => {
+      const id = sessionId ?? DEFAULT_SESSION_ID;
+      const current = state.sessions[id];
+      if (!current) {
+        // Late event from a session that has been closed
+        return {};
+      }
+      const next = { ...current, ...update(current) };
+      if (id !== state.activeSessionId) {
+        return { sessions: { ...state.sessions, [id]: next } };
+      }
+      const view = { ...next, unreadMessages: 0 };
+      return { sessions: { ...state.sessions, [id]: view }, ...visibleFields(view) };
+    });
 
-  // Actions
-  setConnectionStatus: (status) =>
-    set(() => ({
-      connection: status,
-    })),
-
-  setTlsStatus: (status) =>
-    set(() => ({
-      tlsStatus: status,
-    })),
-
-  setUsers: (users) =>
-    set(() => ({
-      users,
-    })),
-
-  addMessage: (message) =>
-    set((state) => ({
-      messages: [...state.messages, message],
-    })),
-
-  setPlaylist: (playlist) =>
-    set(() => ({
-      playlist,
-    })),
-
-  setPlayerState: (playerState) =>
-    set((state) => ({
-      player: { ...state.player, ...playerState },
-    })),
-
-  setRttMs: (rttMs) =>
-    set(() => ({
-      rttMs,
-    })),
-
-  setConfig: (config) =>
-    set(() => ({
-      config,
-    })),
-
-  // Setup event listeners from Tauri backend
-  setupEventListeners: () => {
-    if (listenersInitialized) {
-      return;
-    }
-    listenersInitialized = true;
-
-    const listenSafe = <T>(eventName: string, handler: (event: { payload: T }) => void) => {
-      listen<T>(eventName, handler).catch((error) => {
-        console.error(`Failed to listen for ${eventName}`, error);
-      });
-    };
+  return {
+    // Initial state
+    connection: {
+      connected: false,
+      server: null,
+      reconnecting: false,
+    },
+    tlsStatus: "unknown",
+    link: defaultLink,
+    capabilities: defaultCapabilities,
+    users: [],
+    messages: [],
+    playlist: {
+      items: [],
+      currentIndex: null,
+    },
+    player: {
+      filename: null,
+      position: null,
+      duration: null,
+      paused: true,
+      speed: 1.0,
+    },
+    rttMs: null,
+    config: null,
+    publicServers: null,
+    publicServersFetchedAt: null,
+    activeSessionId: DEFAULT_SESSION_ID,
+    sessions: { [DEFAULT_SESSION_ID]: emptySessionView() },
 
-    // Connection status changes
-    listenSafe<ConnectionState>("connection-status-changed", (event) => {
+    // Actions
+    setConnectionStatus: (status) =>
       set(() => ({
-        connection: event.payload,
-        rttMs: null,
-      }));
-    });
+        connection: status,
+      })),
 
-    listenSafe<{ status: TlsStatus }>("tls-status-changed", (event) => {
+    setTlsStatus: (status) =>
       set(() => ({
-        tlsStatus: event.payload.status,
-      }));
-    });
+        tlsStatus: status,
+      })),
 
-    // User list updates
-    listenSafe<{ users: User[] }>("user-list-updated", (event) => {
+    setUsers: (users) =>
       set(() => ({
-        users: event.payload.users,
-      }));
-    });
+        users,
+      })),
 
-    // Chat messages
-    listenSafe<ChatMessage>("chat-message-received", (event) => {
+    addMessage: (message) =>
       set((state) => ({
-        messages: [...state.messages, event.payload],
-      }));
-    });
+        messages: [...state.messages, message],
+      })),
+
+    setPlaylist: (playlist) =>
+      set(() => ({
+        playlist,
+      })),
+
+    setPlayerState: (playerState) =>
+      set((state) => ({
+        player: { ...state.player, ...playerState },
+      })),
 
-    // Playlist updates
-    listenSafe<PlaylistState>("playlist-updated", (event) => {
+    setRttMs: (rttMs) =>
       set(() => ({
-        playlist: event.payload,
+        rttMs,
+      })),
+
+    setConfig: (config) =>
+      set(() => ({
+        config,
+      })),
+
+    refreshPublicServers: async (force = false) => {
+      const fetchedAt = get().publicServersFetchedAt;
+      if (!force && fetchedAt !== null && Date.now() - fetchedAt < PUBLIC_SERVERS_TTL_MS) {
+        return;
+      }
+      const publicServers = await tauriApi.refreshPublicServers();
+      set(() => ({
+        publicServers,
+        publicServersFetchedAt: Date.now(),
       }));
-    });
+    },
 
-    // Player state updates
-    listenSafe<PlayerState>("player-state-changed", (event) => {
+    openSession: async () => {
+      const summary = await tauriApi.openSession();
       set((state) => ({
-        player: { ...state.player, ...event.payload },
+        sessions: { ...state.sessions, [summary.id]: emptySessionView() },
       }));
-    });
+      return summary.id;
+    },
 
-    listenSafe<{ rttMs: number }>("ping-updated", (event) => {
-      set(() => ({
-        rttMs: event.payload.rttMs,
+    // The backend confirms the switch with an active-session-changed event
+    setActiveSession: async (sessionId) => {
+      await tauriApi.setActiveSession(sessionId);
+    },
+
+    closeSession: async (sessionId) => {
+      await tauriApi.closeSession(sessionId);
+      set((state) => {
+        const sessions = { ...state.sessions };
+        delete sessions[sessionId];
+        return { sessions };
+      });
+    },
+
+    // Setup event listeners from Tauri backend
+    setupEventListeners: () => {
+      if (listenersInitialized) {
+        return;
+      }
+      listenersInitialized = true;
+
+      const listenSafe = <T>(eventName: string, handler: (event: { payload: T }) => void) => {
+        listen<T>(eventName, handler).catch((error) => {
+          console.error(`Failed to listen for ${eventName}`, error);
+        });
+      };
+
+      const listenSession = <T>(
+        eventName: string,
+        update: (payload: T, view: SessionView) => Partial<SessionView>
+      ) => {
+        listenSafe<T & SessionTagged>(eventName, (event) => {
+          updateSession(event.payload.sessionId, (view) => update(event.payload, view));
+        });
+      };
+
+      listenSafe<SessionTagged>("active-session-changed", (event) => {
+        const sessionId = event.payload.sessionId ?? DEFAULT_SESSION_ID;
+        set((state) => {
+          const view = { ...(state.sessions[sessionId] ?? emptySessionView()), unreadMessages: 0 };
+          return {
+            activeSessionId: sessionId,
+            sessions: { ...state.sessions, [sessionId]: view },
+            ...visibleFields(view),
+          };
+        });
+      });
+
+      // Connection status changes
+      listenSession<ConnectionState>("connection-status-changed", (connection) => ({
+        connection,
+        rttMs: null,
       }));
-    });
 
-    // Config updates
-    listenSafe<SyncplayConfig>("config-updated", (event) => {
-      set(() => ({
-        config: event.payload,
+      listenSession<LinkTransition>("connection-state-changed", ({ state, reason, detail }) => ({
+        link: { state, reason, detail },
       }));
-    });
-  },
-}));
+
+      listenSession<{ status: TlsStatus }>("tls-status-changed", (payload) => ({
+        tlsStatus: payload.status,
+      }));
+
+      listenSession<ServerCapabilities>("server-capabilities-changed", (capabilities) => ({
+        capabilities,
+      }));
+
+      listenSafe<{ kind: string; message: string; payload: unknown }>(
+        "protocol-diagnostic",
+        (event) => {
+          console.warn(`Protocol diagnostic (${event.payload.kind})`, event.payload);
+        }
+      );
+
+      // User list updates
+      listenSession<{ users: User[] }>("user-list-updated", (payload) => ({
+        users: payload.users,
+      }));
+
+      // Chat messages
+      listenSession<ChatMessage>("chat-message-received", (message, view) => ({
+        messages: [...view.messages, message],
+        unreadMessages: view.unreadMessages + 1,
+      }));
+
+      // Playlist updates
+      listenSession<PlaylistState>("playlist-updated", (playlist) => ({
+        playlist,
+      }));
+
+      // Player state updates
+      listenSafe<PlayerState>("player-state-changed", (event) => {
+        set((state) => ({
+          player: { ...state.player, ...event.payload },
+        }));
+      });
+
+      listenSession<{ rttMs: number }>("ping-updated", (payload) => ({
+        rttMs: payload.rttMs,
+      }));
+
+      // Config updates
+      listenSafe<SyncplayConfig>("config-updated", (event) => {
+        set(() => ({
+          config: event.payload,
+        }));
+      });
+    },
+  };
+});
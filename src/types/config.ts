@@ -7,11 +7,26 @@ export type UnpauseAction =
 export type ChatInputPosition = "top" | "middle" | "bottom";
 export type ChatOutputMode = "chatroom" | "scrolling";
 export type TransparencyMode = "off" | "low" | "high";
+export type ProxyType = "none" | "socks5" | "http";
+export type QueueOverflowPolicy = "block" | "coalesce_state" | "disconnect";
 
 export interface ServerConfig {
   host: string;
   port: number;
   password: string | null;
+  ca_bundle_path?: string | null;
+  cert_pin_sha256?: string | null;
+  trust_on_first_use?: boolean;
+  tls_policy?: "required" | "preferred" | "disabled";
+  proxy?: ProxyConfig | null;
+}
+
+export interface ProxyConfig {
+  proxy_type: ProxyType;
+  host: string;
+  port: number;
+  username?: string | null;
+  password?: string | null;
 }
 
 export interface PublicServer {
@@ -19,6 +34,10 @@ export interface PublicServer {
   address: string;
 }
 
+export interface ProbedServer extends PublicServer {
+  latencyMs: number | null;
+}
+
 export interface UserPreferences {
   username: string;
   default_room: string;
@@ -89,6 +108,18 @@ export interface UserPreferences {
   show_playlist: boolean;
   side_panel_layout: "rows" | "columns";
   auto_connect: boolean;
+  auto_reconnect: boolean;
+  reconnect_max_attempts: number;
+  connect_timeout_seconds: number;
+  tls_handshake_timeout_seconds: number;
+  hello_timeout_seconds: number;
+  keepalive_idle_timeout_seconds: number;
+  keepalive_grace_period_seconds: number;
+  max_frame_length_bytes: number;
+  message_queue_capacity: number;
+  queue_overflow_policy: QueueOverflowPolicy;
+  room_directory_refresh_seconds: number;
+  protocol_recording_dir: string | null;
   force_gui_prompt: boolean;
   check_for_updates_automatically: boolean | null;
   debug: boolean;
@@ -107,4 +138,6 @@ export interface SyncplayConfig {
   player: PlayerConfig;
   recent_servers: ServerConfig[];
   public_servers: PublicServer[];
+  public_server_list_url: string;
+  proxy?: ProxyConfig;
 }